@@ -0,0 +1,62 @@
+/*
+ * File:    pd_lockstep.h
+ * Brief:   C declarations for the lockstep library (see rust/lockstep.rs)
+ *
+ * Copyright (C) 2024 John Jekel
+ * See the LICENSE file at the root of the project for licensing info.
+ *
+ * Link against libcommon.so (built by `cargo build --release`, in target/release). Push every stage's
+ * values for a cycle, then call pd_lockstep_cycle(); stop the simulation if it returns anything but 0.
+ *
+*/
+
+#ifndef PD_LOCKSTEP_H
+#define PD_LOCKSTEP_H
+
+#include <stdint.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+/* ------------------------------------------------------------------------------------------------
+ * Constants
+ * --------------------------------------------------------------------------------------------- */
+
+//Returned by pd_lockstep_cycle() once there's nothing more to check (the test is over or the golden trace ran out)
+#define PD_LOCKSTEP_DONE        (-1)
+
+//Returned by pd_lockstep_cycle() if it was given a null handle (ex. pd_lockstep_open() failed)
+#define PD_LOCKSTEP_NO_HANDLE   (-2)
+
+/* ------------------------------------------------------------------------------------------------
+ * Types
+ * --------------------------------------------------------------------------------------------- */
+
+typedef struct pd_lockstep pd_lockstep_t;
+
+/* ------------------------------------------------------------------------------------------------
+ * Function Declarations
+ * --------------------------------------------------------------------------------------------- */
+
+//options are the same as pd6simdiff's (ex. "--pipeline pd6"), or NULL; returns NULL if something was wrong
+pd_lockstep_t* pd_lockstep_open(const char* golden_path, const char* options);
+
+void pd_lockstep_f(pd_lockstep_t* lockstep, uint32_t pc, uint32_t instr);
+void pd_lockstep_d(pd_lockstep_t* lockstep, uint32_t pc, uint32_t opcode, uint32_t rd, uint32_t rs1, uint32_t rs2, uint32_t funct3, uint32_t funct7, uint32_t imm, uint32_t shamt);
+void pd_lockstep_r(pd_lockstep_t* lockstep, uint32_t addr_rs1, uint32_t addr_rs2, uint32_t data_rs1, uint32_t data_rs2);
+void pd_lockstep_e(pd_lockstep_t* lockstep, uint32_t pc, uint32_t alu_result, uint32_t branch_taken);
+void pd_lockstep_m(pd_lockstep_t* lockstep, uint32_t pc, uint32_t addr, uint32_t read_not_write, uint32_t access_size, uint32_t memory_wdata);
+void pd_lockstep_w(pd_lockstep_t* lockstep, uint32_t pc, uint32_t we, uint32_t addr_rd, uint32_t data_rd);
+
+//0 to carry on, more than 0 for how many errors were found (and reported), or PD_LOCKSTEP_DONE
+int pd_lockstep_cycle(pd_lockstep_t* lockstep);
+
+//Prints the summary and frees the handle, returning the total number of errors
+int pd_lockstep_close(pd_lockstep_t* lockstep);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif//PD_LOCKSTEP_H
@@ -0,0 +1,36 @@
+/*
+ * File:    pd_lockstep.sv
+ * Brief:   DPI-C imports for the lockstep library (see rust/lockstep.rs)
+ *
+ * Copyright (C) 2024 John Jekel
+ * See the LICENSE file at the root of the project for licensing info.
+ *
+ * With Verilator, pass target/release/libcommon.so on the command line along with your sources.
+ *
+*/
+
+package pd_lockstep;
+
+//Returned by pd_lockstep_cycle() once there's nothing more to check (the test is over or the golden trace ran out)
+localparam int PD_LOCKSTEP_DONE         = -1;
+
+//Returned by pd_lockstep_cycle() if it was given a null handle (ex. pd_lockstep_open() failed)
+localparam int PD_LOCKSTEP_NO_HANDLE    = -2;
+
+//options are the same as pd6simdiff's (ex. "--pipeline pd6"); returns null if something was wrong
+import "DPI-C" function chandle pd_lockstep_open(input string golden_path, input string options);
+
+import "DPI-C" function void pd_lockstep_f(input chandle lockstep, input int unsigned pc, input int unsigned instr);
+import "DPI-C" function void pd_lockstep_d(input chandle lockstep, input int unsigned pc, input int unsigned opcode, input int unsigned rd, input int unsigned rs1, input int unsigned rs2, input int unsigned funct3, input int unsigned funct7, input int unsigned imm, input int unsigned shamt);
+import "DPI-C" function void pd_lockstep_r(input chandle lockstep, input int unsigned addr_rs1, input int unsigned addr_rs2, input int unsigned data_rs1, input int unsigned data_rs2);
+import "DPI-C" function void pd_lockstep_e(input chandle lockstep, input int unsigned pc, input int unsigned alu_result, input int unsigned branch_taken);
+import "DPI-C" function void pd_lockstep_m(input chandle lockstep, input int unsigned pc, input int unsigned addr, input int unsigned read_not_write, input int unsigned access_size, input int unsigned memory_wdata);
+import "DPI-C" function void pd_lockstep_w(input chandle lockstep, input int unsigned pc, input int unsigned we, input int unsigned addr_rd, input int unsigned data_rd);
+
+//0 to carry on, more than 0 for how many errors were found (and reported), or PD_LOCKSTEP_DONE
+import "DPI-C" function int pd_lockstep_cycle(input chandle lockstep);
+
+//Prints the summary and frees the handle, returning the total number of errors
+import "DPI-C" function int pd_lockstep_close(input chandle lockstep);
+
+endpackage
@@ -0,0 +1,233 @@
+/*
+ * File:    pd_lockstep_test.c
+ * Brief:   Replays a trace through the lockstep library, checking what each call returns
+ *
+ * Copyright (C) 2024 John Jekel
+ * See the LICENSE file at the root of the project for licensing info.
+ *
+ * Usage: pd_lockstep_test golden.trace [your.trace] ["options"]
+ *
+ * Pushes every line of your trace (or of the golden trace itself if you don't give one) through the
+ * pd_lockstep_* functions like a testbench would, one cycle at a time, and checks the return codes make
+ * sense along the way. Before that it makes sure a null handle, a missing golden trace and a malformed one
+ * are all handled without taking the process down. Exits with 0 only if every check passed and no errors
+ * were found. c/pd_lockstep_test.sh builds everything and runs this.
+ *
+*/
+
+/* ------------------------------------------------------------------------------------------------
+ * Includes
+ * --------------------------------------------------------------------------------------------- */
+
+#include "pd_lockstep.h"
+
+#include <stdbool.h>
+#include <stdio.h>
+#include <stdlib.h>
+#include <string.h>
+#include <unistd.h>
+
+/* ------------------------------------------------------------------------------------------------
+ * Macros
+ * --------------------------------------------------------------------------------------------- */
+
+#define CHECK(condition) check((condition), #condition, __LINE__)
+
+/* ------------------------------------------------------------------------------------------------
+ * Static Variables
+ * --------------------------------------------------------------------------------------------- */
+
+static unsigned failed_checks = 0;
+
+/* ------------------------------------------------------------------------------------------------
+ * Static Function Declarations
+ * --------------------------------------------------------------------------------------------- */
+
+static void check(bool condition, const char* what, int line);
+static void check_null_handle(void);
+static void check_bad_golden(void);
+static int replay(const char* golden_path, const char* test_path, const char* options);
+static bool push_line(pd_lockstep_t* lockstep, const char* line);
+
+/* ------------------------------------------------------------------------------------------------
+ * Function Implementations
+ * --------------------------------------------------------------------------------------------- */
+
+int main(int argc, char** argv) {
+    if ((argc < 2) || (argc > 4)) {
+        fprintf(stderr, "Usage: %s golden.trace [your.trace] [\"options\"]\n", argv[0]);
+        return 2;
+    }
+    const char* golden_path = argv[1];
+    const char* test_path   = (argc > 2) ? argv[2] : argv[1];
+    const char* options     = (argc > 3) ? argv[3] : NULL;
+    setvbuf(stdout, NULL, _IOLBF, 0);//So our output stays in order with the library's
+
+    puts("\x1b[1mChecking null handles and bad golden traces...\x1b[0m");
+    check_null_handle();
+    check_bad_golden();
+
+    printf("\x1b[1mReplaying %s through the lockstep library...\x1b[0m\n", test_path);
+    int errors = replay(golden_path, test_path, options);
+
+    if (failed_checks > 0) {
+        printf("\x1b[1;31m%u check(s) on the lockstep library failed!\x1b[0m\n", failed_checks);
+        return 1;
+    } else if (errors != 0) {
+        printf("\x1b[1;31mThe lockstep library found %d error(s) in the replayed trace\x1b[0m\n", errors);
+        return 1;
+    } else {
+        puts("\x1b[1;32mEvery check passed!\x1b[0m");
+        return 0;
+    }
+}
+
+static void check(bool condition, const char* what, int line) {
+    if (!condition) {
+        printf("\x1b[1;31mCheck failed on line %d: %s\x1b[0m\n", line, what);
+        ++failed_checks;
+    }
+}
+
+static void check_null_handle(void) {
+    CHECK(pd_lockstep_open(NULL, NULL) == NULL);
+
+    //None of these should do anything without a handle
+    pd_lockstep_f(NULL, 0, 0);
+    pd_lockstep_d(NULL, 0, 0, 0, 0, 0, 0, 0, 0, 0);
+    pd_lockstep_r(NULL, 0, 0, 0, 0);
+    pd_lockstep_e(NULL, 0, 0, 0);
+    pd_lockstep_m(NULL, 0, 0, 0, 0, 0);
+    pd_lockstep_w(NULL, 0, 0, 0, 0);
+    CHECK(pd_lockstep_cycle(NULL) == PD_LOCKSTEP_NO_HANDLE);
+    CHECK(pd_lockstep_close(NULL) == 0);
+}
+
+static void check_bad_golden(void) {
+    CHECK(pd_lockstep_open("/nonexistent/golden.trace", NULL) == NULL);
+
+    //A line that doesn't parse has to come back as NULL rather than a panic, which would abort us
+    char path[] = "/tmp/pd_lockstep_test_XXXXXX";
+    int fd = mkstemp(path);
+    CHECK(fd >= 0);
+    if (fd < 0) {
+        return;
+    }
+    FILE* file = fdopen(fd, "w");
+    fputs("[F] 01000000 00000013\n[Q] 01000000\n", file);
+    fclose(file);
+
+    CHECK(pd_lockstep_open(path, NULL) == NULL);
+    CHECK(pd_lockstep_open(path, "--not-an-option") == NULL);
+    unlink(path);
+}
+
+//Returns the total number of errors pd_lockstep_close() reports
+static int replay(const char* golden_path, const char* test_path, const char* options) {
+    pd_lockstep_t* lockstep = pd_lockstep_open(golden_path, options);
+    CHECK(lockstep != NULL);
+    if (!lockstep) {
+        return 0;
+    }
+
+    FILE* file = fopen(test_path, "r");
+    CHECK(file != NULL);
+    if (!file) {
+        pd_lockstep_close(lockstep);
+        return 0;
+    }
+
+    //Lines can be in any order within a cycle, so a cycle ends when a stage shows up a second time
+    char        line[1024];
+    char        seen[8]         = "";
+    int         verdict         = 0;
+    int         errors_reported = 0;
+    unsigned    cycles          = 0;
+    bool        more            = true;
+    while (more && (verdict == 0)) {
+        more = fgets(line, sizeof(line), file) != NULL;
+
+        const char* tag = more ? strchr(line, '[') : NULL;//Skips the stamp, if any
+        if (more && (!tag || (line[0] == '#'))) {
+            continue;//Headers and blank lines
+        }
+
+        if (!more || strchr(seen, tag[1])) {
+            if (seen[0] == '\0') {
+                break;//Nothing pushed since the last cycle
+            }
+            verdict = pd_lockstep_cycle(lockstep);
+            ++cycles;
+            CHECK(verdict >= PD_LOCKSTEP_DONE);
+            if (verdict > 0) {
+                errors_reported += verdict;
+            }
+            seen[0] = '\0';
+        }
+
+        if (more && (verdict == 0)) {
+            if (!push_line(lockstep, tag)) {
+                printf("\x1b[1;33mCan't replay this line (X or Z values can't be pushed): %s\x1b[0m", line);
+                ++failed_checks;
+                break;
+            }
+            strncat(seen, tag + 1, 1);
+        }
+    }
+    fclose(file);
+
+    //Once it's done, it should stay done
+    if (verdict == PD_LOCKSTEP_DONE) {
+        CHECK(pd_lockstep_cycle(lockstep) == PD_LOCKSTEP_DONE);
+    }
+
+    printf("Replayed %u cycle(s), the last of which returned %d\n", cycles, verdict);
+    int total_errors = pd_lockstep_close(lockstep);
+    CHECK(total_errors >= errors_reported);
+    return total_errors;
+}
+
+//Returns false if the line couldn't be parsed
+static bool push_line(pd_lockstep_t* lockstep, const char* line) {
+    unsigned int f[9];
+    switch (line[1]) {
+        case 'F':
+            if (sscanf(line, "[F] %x %x", &f[0], &f[1]) != 2) {
+                return false;
+            }
+            pd_lockstep_f(lockstep, f[0], f[1]);
+            return true;
+        case 'D':
+            if (sscanf(line, "[D] %x %x %x %x %x %x %x %x %x", &f[0], &f[1], &f[2], &f[3], &f[4], &f[5], &f[6], &f[7], &f[8]) != 9) {
+                return false;
+            }
+            pd_lockstep_d(lockstep, f[0], f[1], f[2], f[3], f[4], f[5], f[6], f[7], f[8]);
+            return true;
+        case 'R':
+            if (sscanf(line, "[R] %x %x %x %x", &f[0], &f[1], &f[2], &f[3]) != 4) {
+                return false;
+            }
+            pd_lockstep_r(lockstep, f[0], f[1], f[2], f[3]);
+            return true;
+        case 'E':
+            if (sscanf(line, "[E] %x %x %x", &f[0], &f[1], &f[2]) != 3) {
+                return false;
+            }
+            pd_lockstep_e(lockstep, f[0], f[1], f[2]);
+            return true;
+        case 'M':
+            if (sscanf(line, "[M] %x %x %x %x %x", &f[0], &f[1], &f[2], &f[3], &f[4]) != 5) {
+                return false;
+            }
+            pd_lockstep_m(lockstep, f[0], f[1], f[2], f[3], f[4]);
+            return true;
+        case 'W':
+            if (sscanf(line, "[W] %x %x %x %x", &f[0], &f[1], &f[2], &f[3]) != 4) {
+                return false;
+            }
+            pd_lockstep_w(lockstep, f[0], f[1], f[2], f[3]);
+            return true;
+        default:
+            return false;
+    }
+}
@@ -0,0 +1,15 @@
+#!/bin/bash
+#Copyright (C) 2024 John Jekel
+#
+#Builds the lockstep library and c/pd_lockstep_test.c, then replays a trace (second argument, or the golden
+#trace itself if there isn't one) through the library against a golden trace (first argument). An optional
+#third argument is the options string, ex. "--pipeline pd6"
+
+set -e
+
+repo=$(dirname "$0")/..
+target=${CARGO_TARGET_DIR:-$repo/target}/release
+
+cargo build --release --lib --manifest-path "$repo/Cargo.toml"
+cc -std=c99 -D_POSIX_C_SOURCE=200809L -Wall -Wextra -I"$repo/c" -o "$target/pd_lockstep_test" "$repo/c/pd_lockstep_test.c" -L"$target" -lcommon
+LD_LIBRARY_PATH="$target" "$target/pd_lockstep_test" "$@"
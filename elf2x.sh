@@ -0,0 +1,6 @@
+#!/bin/bash
+#Copyright (C) 2024 John Jekel
+#
+#Converts a RV32 ELF file (first argument) into a .x memory image (second argument, or stdout if omitted)
+
+cargo run --release --bin elf2x -- $@
@@ -13,6 +13,8 @@
 #include "common.h"
 #include "decode.h"
 
+#include <cerrno>
+#include <cstdint>
 #include <cstdlib>
 #include <cstdio>
 #include <stdexcept>
@@ -23,12 +25,19 @@
 #include <unordered_map>
 #include <utility>
 #include <cstring>
+#include <sstream>
+#include <string>
+#include <algorithm>
 
 /* ------------------------------------------------------------------------------------------------
  * Static Variables
  * --------------------------------------------------------------------------------------------- */
 
-static const uint32_t register_init_values[32] = {
+//Defaults match the course testbench; see load_machine_config() to override them
+static uint32_t mem_base = 0x01000000;
+static uint32_t mem_size = 0x00100000;
+
+static uint32_t register_init_values[32] = {
     0x00000000,             //x0:  0
     0x00000000,             //x1:  0
     0x01000000 + 0x00100000,//x2:  32'h01000000 + `MEM_DEPTH
@@ -68,6 +77,10 @@ static const uint32_t register_init_values[32] = {
  * --------------------------------------------------------------------------------------------- */
 
 static void usage(void);
+static void load_machine_config(const char* machine_config_file_path);
+static std::string trim(const std::string& text);
+static std::optional<uint32_t> parse_hex(std::string text);
+static int register_index(const std::string& name);
 static void dump(uint32_t instruction);
 static void pd2(uint32_t instruction, uint32_t addr);
 static void pd2_trace(const char* vhex32_file_path);
@@ -105,6 +118,21 @@ int main(int argc, const char* const* argv) {
         }
         case 4: {
             switch (argv[1][0]) {
+                case 't': {
+                    load_machine_config(argv[3]);
+                    pd2_trace(argv[2]);
+                    return 0;
+                }
+                case 'e': {
+                    load_machine_config(argv[3]);
+                    pd3_golden_preprocess(argv[2]);
+                    return 0;
+                }
+                case '4': {
+                    load_machine_config(argv[3]);
+                    pd4_golden_preprocess(argv[2]);
+                    return 0;
+                }
                 case 'c': {
                     pd2_check(argv[2], argv[3]);
                     return 0;
@@ -133,7 +161,126 @@ static void usage(void) {
     printf("`irvedecoder t <vhex32 file>`, which will emit a PD2 trace file you can diff against\n");
     printf("`irvedecoder c <vhex32 file> <trace file>`, which will check the PD2 trace file against the vhex32 file for correctness\n");
     printf("`irvedecoder e <trace file>`, which sets alu_result to 0 for all execute instructions in the trace file -> stdout\n");
-    printf("`irvedecoder 4 <trace file>`, which is useful for PD4");
+    printf("`irvedecoder 4 <trace file>`, which is useful for PD4\n");
+    printf("`t`, `e` and `4` also accept a machine config file as an extra argument (see rust/machine.rs for the format)\n");
+}
+
+static void load_machine_config(const char* machine_config_file_path) {
+    std::ifstream machine_config_file(machine_config_file_path);
+
+    if (!machine_config_file.is_open()) {
+        throw std::runtime_error("Failed to open file");
+    }
+
+    std::optional<uint32_t> sp;
+
+    //Rejects the same malformed lines and bad numbers rust/machine.rs's MachineConfig::parse() does
+    std::string line;
+    for (unsigned line_num = 1; std::getline(machine_config_file, line); ++line_num) {
+        line = trim(line.substr(0, line.find('#')));
+        if (line.empty()) {
+            continue;
+        }
+
+        auto equals = line.find('=');
+        if (equals == std::string::npos) {
+            throw std::invalid_argument("Line " + std::to_string(line_num) + ": expected `<key> = <value>`, got \"" + line + "\"");
+        }
+
+        std::string key     = trim(line.substr(0, equals));
+        std::string value   = trim(line.substr(equals + 1));
+        std::transform(key.begin(), key.end(), key.begin(), ::tolower);
+
+        //Keep these in sync with the keys MachineConfig::parse() in rust/machine.rs knows about
+        if ((key == "end_of_test") || (key == "traps") || (key == "mtvec") || (key == "branch_predictor")) {
+            continue;//Only the Rust checkers care about these
+        }
+
+        int reg = -1;
+        if ((key != "mem_base") && (key != "mem_size") && (key != "mem_depth") && (key != "reset_pc")) {
+            reg = register_index(key);
+            if (reg < 0) {
+                throw std::invalid_argument("Line " + std::to_string(line_num) + ": unknown setting \"" + key + "\"");
+            } else if (reg == 0) {
+                throw std::invalid_argument("Line " + std::to_string(line_num) + ": x0 is always 0");
+            }
+        }
+
+        std::optional<uint32_t> number = parse_hex(value);
+        if (!number) {
+            throw std::invalid_argument("Line " + std::to_string(line_num) + ": bad hex number \"" + value + "\"");
+        }
+
+        if (key == "mem_base") {
+            mem_base = *number;
+        } else if ((key == "mem_size") || (key == "mem_depth")) {
+            mem_size = *number;
+        } else if (key == "reset_pc") {
+            //We walk memory from mem_base, so nothing to do here
+        } else if (reg == 2) {
+            sp = *number;
+        } else {
+            register_init_values[reg] = *number;
+        }
+    }
+
+    //Like the testbench, sp starts at the top of memory unless told otherwise
+    register_init_values[2] = sp.value_or(mem_base + mem_size);
+}
+
+static std::string trim(const std::string& text) {
+    auto first  = std::find_if_not(text.begin(), text.end(), ::isspace);
+    auto last   = std::find_if_not(text.rbegin(), text.rend(), ::isspace).base();
+    return (first < last) ? std::string(first, last) : std::string();
+}
+
+//Like parse_hex() in rust/machine.rs: an optional 0x, then hex digits with optional `_` separators
+static std::optional<uint32_t> parse_hex(std::string text) {
+    if (text.compare(0, 2, "0x") == 0) {
+        text.erase(0, 2);
+    }
+    text.erase(std::remove(text.begin(), text.end(), '_'), text.end());
+
+    //strtoull() would also skip whitespace and take a sign or its own 0x, so make sure it's all digits
+    if (text.empty() || !std::all_of(text.begin(), text.end(), ::isxdigit)) {
+        return std::nullopt;
+    }
+
+    errno = 0;
+    char* end = nullptr;
+    unsigned long long number = std::strtoull(text.c_str(), &end, 16);
+    if ((*end != '\0') || (errno == ERANGE) || (number > UINT32_MAX)) {
+        return std::nullopt;
+    }
+    return static_cast<uint32_t>(number);
+}
+
+//x0-x31 and ABI names (plus fp for s0), or -1 if it isn't a register
+static int register_index(const std::string& name) {
+    static const char* const abi_names[32] = {
+        "zero", "ra", "sp", "gp", "tp", "t0", "t1", "t2",
+        "s0", "s1", "a0", "a1", "a2", "a3", "a4", "a5",
+        "a6", "a7", "s2", "s3", "s4", "s5", "s6", "s7",
+        "s8", "s9", "s10", "s11", "t3", "t4", "t5", "t6"
+    };
+
+    if ((name.size() > 1) && (name[0] == 'x')) {
+        std::string number = name.substr(1);
+        if (!std::all_of(number.begin(), number.end(), ::isdigit)) {
+            return -1;
+        }
+        unsigned long index = std::strtoul(number.c_str(), nullptr, 10);
+        return (index < 32) ? static_cast<int>(index) : -1;
+    } else if (name == "fp") {
+        return 8;
+    }
+
+    for (int i = 0; i < 32; ++i) {
+        if (name == abi_names[i]) {
+            return i;
+        }
+    }
+    return -1;
 }
 
 static void dump(uint32_t instruction) {
@@ -185,7 +332,7 @@ static void pd2_trace(const char* vhex32_file_path) {
 
     vhex32_file >> std::hex;
 
-    uint32_t addr = 0x01000000;
+    uint32_t addr = mem_base;
     uint32_t vhex32_inst;
 
     try {
@@ -210,7 +357,7 @@ static void pd2_check(const char* vhex32_file_path, const char* trace_file_path)
     vhex32_file >> std::hex;
     trace_file >> std::hex;
 
-    uint32_t addr = 0x01000000;
+    uint32_t addr = mem_base;
     uint32_t vhex32_inst;
     while (vhex32_file >> vhex32_inst) {
         printf("vhex32: %08X\n", vhex32_inst);
@@ -24,6 +24,10 @@
  * --------------------------------------------------------------------------------------------- */
 
 use common::*;
+use common::cli::CommandLine;
+use common::reference::{ReferenceLog, Suspect};
+use common::rules::{Field, RuleTable};
+use common::symbols::SymbolTable;
 use riscv_tools::*;
 
 /* ------------------------------------------------------------------------------------------------
@@ -73,13 +77,48 @@ use riscv_tools::*;
  * --------------------------------------------------------------------------------------------- */
 
 fn main() -> std::process::ExitCode {
-    let golden = std::env::args().nth(1).expect("No golden trace file provided!");
-    let test   = std::env::args().nth(2).expect("No test trace file provided!");
+    let command_line = CommandLine::from_env(&["--rules", "--elf", "--objdump"], &[]).expect("Bad arguments!");
+    let golden = command_line.positional().first().expect("No golden trace file provided!");
+    let test   = command_line.positional().get(1).expect("No test trace file provided!");
+
+    let rules = match RuleTable::from_option(command_line.value("--rules")) {
+        Ok(rules) => rules,
+        Err(e) => {
+            println!("{}", e);
+            return std::process::ExitCode::FAILURE;
+        },
+    };
+
+    let symbols = match SymbolTable::load(command_line.value("--elf"), command_line.value("--objdump")) {
+        Ok(symbols) => symbols,
+        Err(e) => {
+            println!("{}", e);
+            return std::process::ExitCode::FAILURE;
+        },
+    };
+
+    let (golden, test) = match (ParsedLineIterator::from_path(golden), ParsedLineIterator::from_path(test)) {
+        (Ok(golden), Ok(test)) => (golden, test),
+        (Err(e), _) | (_, Err(e)) => {
+            println!("{}", e);
+            return std::process::ExitCode::FAILURE;
+        },
+    };
+
+    if let Err(e) = verify_same_benchmark(&golden, &test) {
+        println!("{}", e);
+        return std::process::ExitCode::FAILURE;
+    }
 
-    let golden = ParsedLineIterator::from_path(golden).expect("Failed to open golden trace file!");
-    let test   = ParsedLineIterator::from_path(test).expect("Failed to open test trace file!");
+    let (golden, test) = match (golden.read_stamped(), test.read_stamped()) {
+        (Ok(golden), Ok(test)) => (golden, test),
+        (Err(e), _) | (_, Err(e)) => {
+            println!("{}", e);
+            return std::process::ExitCode::FAILURE;
+        },
+    };
 
-    let total_error_count = compare(golden, test);
+    let total_error_count = compare(golden, test, &rules, &symbols);
     println!("Done! If you didn't see any errors above, then you (should) be good!");
 
     if total_error_count > 0 {
@@ -89,231 +128,259 @@ fn main() -> std::process::ExitCode {
     }
 }
 
-fn compare(golden: ParsedLineIterator, test: ParsedLineIterator) -> u32 {
+fn compare(golden: Vec<(Stamp, ParsedLine)>, test: Vec<(Stamp, ParsedLine)>, rules: &RuleTable, symbols: &SymbolTable) -> u32 {
     let mut total_error_count = 0;
+    let mut unknown_value_error_count = 0;
+    let mut reference = ReferenceLog::new();
     let mut last_fetched_pc: Option<u32> = None;
     let mut last_fetched_instr: Option<Instruction> = None;
-    for (ii, (g, t)) in golden.zip(test).enumerate() {
+    let mut last_operands: Option<(u32, u32)> = None;//What the golden [R] line read for the last fetched instruction
+    for (ii, ((_, g), (_, t))) in golden.into_iter().zip(test).enumerate() {
         //Common code for nicely printing errors
         let disassembly = last_fetched_instr.as_ref().map(|instr| disassemble(instr));
-        let mut error_count_this_line = 0;
-        let mut print_error = |msg: &str| {
-            if error_count_this_line == 0 {
-                println!("At least one error on line {}:", ii + 1);
+        let location    = last_fetched_pc.map(|pc| format!("@PC {:08x}{}", pc, symbols.annotate(pc)));
+        let mut error_count_this_line   = 0;
+        let mut message_count_this_line = 0;//Including ones that aren't counted as errors
+        let mut print_error = |class: ErrorClass, msg: &str| {
+            if message_count_this_line == 0 {
+                println!("At least one {} on line {}:", if class.counts_as_error() {"error"} else {"problem"}, ii + 1);
                 println!("  Golden: {}", g);
                 println!("  Yours:  {}", t);
                 if let Some(disassembly) = disassembly.as_ref() {
                     println!("  Golden Disassembly: {}", disassembly);
                 }
+                if let Some(location) = location.as_ref() {
+                    println!("  Golden Location:    {}", location);
+                }
                 println!("  Errors:");
             }
+            message_count_this_line += 1;
+            if !class.counts_as_error() {
+                println!("    Not counted ({}): {}", class, msg);
+                return;
+            }
+
             error_count_this_line += 1;
-            println!("    Error {}: {}", error_count_this_line, msg);
+            if class == ErrorClass::Mismatch {
+                println!("    Error {}: {}", error_count_this_line, msg);
+            } else {
+                if class == ErrorClass::UnknownValue {
+                    unknown_value_error_count += 1;
+                }
+                println!("    Error {} ({}): {}", error_count_this_line, class, msg);
+            }
         };
 
         match (g, t) {
             (ParsedLine::F{pc: g_pc, instr: g_instr}, ParsedLine::F{pc: t_pc, instr: t_instr}) => {
-                last_fetched_pc     = Some(g_pc);
+                last_fetched_pc     = Some(g_pc.value());
                 last_fetched_instr  = Some(Instruction::from(g_instr));
-                if g_pc != t_pc {
-                    print_error("PCs do not match!");
+                last_operands       = None;
+                let last_fetched_instr = last_fetched_instr.as_ref().unwrap();
+                if rules.must_match(last_fetched_instr, Field::FPc) {
+                    check_field!(print_error, g_pc, t_pc, "PCs do not match!");
                 }
 
-                if g_instr != t_instr {
-                    print_error("Instructions do not match!");
+                if rules.must_match(last_fetched_instr, Field::FInstr) {
+                    check_field!(print_error, g_instr, t_instr, "Instructions do not match!");
                 }
             },
             (ParsedLine::D{pc: g_pc, opcode: g_opcode, rd: g_rd, rs1: g_rs1, rs2: g_rs2, funct3: g_funct3, funct7: g_funct7, imm: g_imm, shamt: g_shamt},
             ParsedLine::D{pc: t_pc, opcode: t_opcode, rd: t_rd, rs1: t_rs1, rs2: t_rs2, funct3: t_funct3, funct7: t_funct7, imm: t_imm, shamt: t_shamt}) => {
                 let last_fetched_pc     = last_fetched_pc.unwrap();
                 let last_fetched_instr  = last_fetched_instr.as_ref().unwrap();
-                if last_fetched_pc != g_pc {
-                    print_error("PC changed since last fetch somehow!");
+                if g_pc != last_fetched_pc {
+                    reference.record(Suspect::GoldenTrace);
+                    print_error(ErrorClass::Reference, "The golden trace's PC changed since the last fetch somehow!");
                 }
 
-                if g_pc != t_pc {
-                    print_error("PCs do not match!");
+                if rules.must_match(last_fetched_instr, Field::DPc) {
+                    check_field!(print_error, g_pc, t_pc, "PCs do not match!");
                 }
 
-                if g_opcode != t_opcode {
-                    print_error("Opcodes do not match!");
+                if rules.must_match(last_fetched_instr, Field::DOpcode) {
+                    check_field!(print_error, g_opcode, t_opcode, &format!("Opcodes do not match!{}", hints::decode_hint(last_fetched_instr.assume_uncompressed(), Field::DOpcode, g_opcode.widen(), t_opcode.widen())));
                 }
 
                 //We sometimes don't do comparisons if they are don't cares
 
-                if let Some(jzj_rd) = last_fetched_instr.get_rd() {
-                    if g_rd != t_rd {
-                        print_error("RDs do not match!");
-                    }
-                    assert_eq!(g_rd, jzj_rd);//Else likely bug in my Rust code
+                if rules.must_match(last_fetched_instr, Field::DRd) {
+                    check_field!(print_error, g_rd, t_rd, &format!("RDs do not match!{}", hints::decode_hint(last_fetched_instr.assume_uncompressed(), Field::DRd, g_rd.widen(), t_rd.widen())));
+                }
+                if let Some(message) = last_fetched_instr.get_rd().and_then(|jzj_rd| reference.check_decode(last_fetched_instr, Field::DRd, g_rd.widen(), jzj_rd as u32)) {
+                    print_error(ErrorClass::Reference, &message);
                 }
 
-                if let Some(jzj_rs1) = last_fetched_instr.get_rs1() {
-                    if g_rs1 != t_rs1 {
-                        print_error("RS1s do not match!");
-                    }
-                    assert_eq!(g_rs1, jzj_rs1);//Else likely bug in my Rust code
+                if rules.must_match(last_fetched_instr, Field::DRs1) {
+                    check_field!(print_error, g_rs1, t_rs1, &format!("RS1s do not match!{}", hints::decode_hint(last_fetched_instr.assume_uncompressed(), Field::DRs1, g_rs1.widen(), t_rs1.widen())));
+                }
+                if let Some(message) = last_fetched_instr.get_rs1().and_then(|jzj_rs1| reference.check_decode(last_fetched_instr, Field::DRs1, g_rs1.widen(), jzj_rs1 as u32)) {
+                    print_error(ErrorClass::Reference, &message);
                 }
 
-                if let Some(jzj_rs2) = last_fetched_instr.get_rs2() {
-                    if g_rs2 != t_rs2 {
-                        print_error("RS2s do not match!");
-                    }
-                    assert_eq!(g_rs2, jzj_rs2);//Else likely bug in my Rust code
+                if rules.must_match(last_fetched_instr, Field::DRs2) {
+                    check_field!(print_error, g_rs2, t_rs2, &format!("RS2s do not match!{}", hints::decode_hint(last_fetched_instr.assume_uncompressed(), Field::DRs2, g_rs2.widen(), t_rs2.widen())));
+                }
+                if let Some(message) = last_fetched_instr.get_rs2().and_then(|jzj_rs2| reference.check_decode(last_fetched_instr, Field::DRs2, g_rs2.widen(), jzj_rs2 as u32)) {
+                    print_error(ErrorClass::Reference, &message);
                 }
 
-                if let Some(jzj_funct3) = last_fetched_instr.get_funct3() {
-                    if g_funct3 != t_funct3 {
-                        print_error("Funct3s do not match!");
-                    }
-                    assert_eq!(g_funct3, jzj_funct3);//Else likely bug in my Rust code
+                if rules.must_match(last_fetched_instr, Field::DFunct3) {
+                    check_field!(print_error, g_funct3, t_funct3, &format!("Funct3s do not match!{}", hints::decode_hint(last_fetched_instr.assume_uncompressed(), Field::DFunct3, g_funct3.widen(), t_funct3.widen())));
+                }
+                if let Some(message) = last_fetched_instr.get_funct3().and_then(|jzj_funct3| reference.check_decode(last_fetched_instr, Field::DFunct3, g_funct3.widen(), jzj_funct3 as u32)) {
+                    print_error(ErrorClass::Reference, &message);
                 }
 
-                if let Some(jzj_funct7) = last_fetched_instr.get_funct7() {
-                    if g_funct7 != t_funct7 {
-                        print_error("Funct7s do not match!");
-                    }
-                    assert_eq!(g_funct7, jzj_funct7);//Else likely bug in my Rust code
+                if rules.must_match(last_fetched_instr, Field::DFunct7) {
+                    check_field!(print_error, g_funct7, t_funct7, &format!("Funct7s do not match!{}", hints::decode_hint(last_fetched_instr.assume_uncompressed(), Field::DFunct7, g_funct7.widen(), t_funct7.widen())));
+                }
+                if let Some(message) = last_fetched_instr.get_funct7().and_then(|jzj_funct7| reference.check_decode(last_fetched_instr, Field::DFunct7, g_funct7.widen(), jzj_funct7 as u32)) {
+                    print_error(ErrorClass::Reference, &message);
                 }
 
-                if let Some(jzj_imm) = last_fetched_instr.get_imm() {
-                    if g_imm != t_imm {
-                        print_error("IMMs do not match!");
-                    }
-                    assert_eq!(g_imm, jzj_imm as u32);//Else likely bug in my Rust code
+                if rules.must_match(last_fetched_instr, Field::DImm) {
+                    check_field!(print_error, g_imm, t_imm, &format!("IMMs do not match!{}", hints::decode_hint(last_fetched_instr.assume_uncompressed(), Field::DImm, g_imm.widen(), t_imm.widen())));
+                }
+                if let Some(message) = last_fetched_instr.get_imm().and_then(|jzj_imm| reference.check_decode(last_fetched_instr, Field::DImm, g_imm.widen(), jzj_imm as u32)) {
+                    print_error(ErrorClass::Reference, &message);
                 }
 
-                if let Some(jzj_shamt) = last_fetched_instr.get_shamt() {
-                    if g_shamt != t_shamt {
-                        print_error("SHAMTs do not match!");
-                    }
-                    assert_eq!(g_shamt, jzj_shamt);//Else likely bug in my Rust code
+                if rules.must_match(last_fetched_instr, Field::DShamt) {
+                    check_field!(print_error, g_shamt, t_shamt, &format!("SHAMTs do not match!{}", hints::decode_hint(last_fetched_instr.assume_uncompressed(), Field::DShamt, g_shamt.widen(), t_shamt.widen())));
+                }
+                if let Some(message) = last_fetched_instr.get_shamt().and_then(|jzj_shamt| reference.check_decode(last_fetched_instr, Field::DShamt, g_shamt.widen(), jzj_shamt as u32)) {
+                    print_error(ErrorClass::Reference, &message);
                 }
             },
             (ParsedLine::R{addr_rs1: g_addr_rs1, addr_rs2: g_addr_rs2, data_rs1: g_data_rs1, data_rs2: g_data_rs2},
             ParsedLine::R{addr_rs1: t_addr_rs1, addr_rs2: t_addr_rs2, data_rs1: t_data_rs1, data_rs2: t_data_rs2}) => {
                 let last_fetched_instr  = last_fetched_instr.as_ref().unwrap();
+                if g_data_rs1.is_known() && g_data_rs2.is_known() {
+                    last_operands = Some((g_data_rs1.value(), g_data_rs2.value()));
+                }
 
-                if let Some(jzj_rs1) = last_fetched_instr.get_rs1() {
-                    if g_addr_rs1 != t_addr_rs1 {
-                        print_error("RS1 addresses do not match!");
-                    }
-                    assert_eq!(g_addr_rs1, jzj_rs1);//Else likely bug in my Rust code
+                if rules.must_match(last_fetched_instr, Field::RAddrRs1) {
+                    check_field!(print_error, g_addr_rs1, t_addr_rs1, "RS1 addresses do not match!");
+                }
+                if let Some(message) = last_fetched_instr.get_rs1().and_then(|jzj_rs1| reference.check_decode(last_fetched_instr, Field::RAddrRs1, g_addr_rs1.widen(), jzj_rs1 as u32)) {
+                    print_error(ErrorClass::Reference, &message);
+                }
 
-                    if g_data_rs1 != t_data_rs1 {
-                        print_error("RS1 data does not match!");
-                    }
+                if rules.must_match(last_fetched_instr, Field::RDataRs1) {
+                    check_field!(print_error, g_data_rs1, t_data_rs1, "RS1 data does not match!");
                 }
 
-                if let Some(jzj_rs2) = last_fetched_instr.get_rs2() {
-                    if g_addr_rs2 != t_addr_rs2 {
-                        print_error("RS2 addresses do not match!");
-                    }
-                    assert_eq!(g_addr_rs2, jzj_rs2);//Else likely bug in my Rust code
+                if rules.must_match(last_fetched_instr, Field::RAddrRs2) {
+                    check_field!(print_error, g_addr_rs2, t_addr_rs2, "RS2 addresses do not match!");
+                }
+                if let Some(message) = last_fetched_instr.get_rs2().and_then(|jzj_rs2| reference.check_decode(last_fetched_instr, Field::RAddrRs2, g_addr_rs2.widen(), jzj_rs2 as u32)) {
+                    print_error(ErrorClass::Reference, &message);
+                }
 
-                    if g_data_rs2 != t_data_rs2 {
-                        print_error("RS2 data does not match!");
-                    }
+                if rules.must_match(last_fetched_instr, Field::RDataRs2) {
+                    check_field!(print_error, g_data_rs2, t_data_rs2, "RS2 data does not match!");
                 }
             },
             (ParsedLine::E{pc: g_pc, alu_result: g_alu_result, branch_taken: g_branch_taken},
             ParsedLine::E{pc: t_pc, alu_result: t_alu_result, branch_taken: t_branch_taken}) => {
                 let last_fetched_pc     = last_fetched_pc.unwrap();
                 let last_fetched_instr  = last_fetched_instr.as_ref().unwrap();
-                assert_eq!(last_fetched_pc, g_pc, "Your traces are really messed up!");
-                if last_fetched_pc != g_pc {
-                    print_error("PC changed since last fetch somehow!");
+                if g_pc != last_fetched_pc {
+                    reference.record(Suspect::GoldenTrace);
+                    print_error(ErrorClass::Reference, "The golden trace's PC changed since the last fetch somehow!");
                 }
 
-                if g_pc != t_pc {
-                    print_error("PCs do not match!");
+                if rules.must_match(last_fetched_instr, Field::EPc) {
+                    check_field!(print_error, g_pc, t_pc, "PCs do not match!");
                 }
 
-                if !last_fetched_instr.is_fence() && !last_fetched_instr.is_system() {
-                    if g_alu_result != t_alu_result {
-                        print_error("ALU results do not match!");
-                    }
+                if rules.must_match(last_fetched_instr, Field::EAluResult) {
+                    let hint = hints::alu_hint(last_fetched_instr.assume_uncompressed(), last_fetched_pc, last_operands, g_alu_result, t_alu_result);
+                    check_field!(print_error, g_alu_result, t_alu_result, &format!("ALU results do not match!{}", hint));
                 }
 
-                if last_fetched_instr.is_btype() {
-                    if g_branch_taken != t_branch_taken {
-                        print_error("Branch taken flags do not match!");
-                    }
+                if rules.must_match(last_fetched_instr, Field::EBranchTaken) {
+                    let hint = hints::branch_hint(last_fetched_instr.assume_uncompressed(), last_fetched_pc, last_operands, (None, None), g_branch_taken, t_branch_taken, None);
+                    check_field!(print_error, g_branch_taken, t_branch_taken, &format!("Branch taken flags do not match!{}", hint));
                 }
             },
             (ParsedLine::M{pc: g_pc, addr: g_addr, read_not_write: g_read_not_write, access_size: g_access_size, memory_wdata: g_memory_wdata},
             ParsedLine::M{pc: t_pc, addr: t_addr, read_not_write: t_read_not_write, access_size: t_access_size, memory_wdata: t_memory_wdata}) => {
                 let last_fetched_pc     = last_fetched_pc.unwrap();
                 let last_fetched_instr  = last_fetched_instr.as_ref().unwrap();
-                assert_eq!(last_fetched_pc, g_pc, "Your traces are really messed up!");
-                if last_fetched_pc != g_pc {
-                    print_error("PC changed since last fetch somehow!");
+                if g_pc != last_fetched_pc {
+                    reference.record(Suspect::GoldenTrace);
+                    print_error(ErrorClass::Reference, "The golden trace's PC changed since the last fetch somehow!");
                 }
 
-                if g_pc != t_pc {
-                    print_error("PCs do not match!");
+                if rules.must_match(last_fetched_instr, Field::MPc) {
+                    check_field!(print_error, g_pc, t_pc, "PCs do not match!");
                 }
 
-                if g_read_not_write != t_read_not_write {
-                    print_error("Read/write flags do not match!");
+                if rules.must_match(last_fetched_instr, Field::MReadNotWrite) {
+                    check_field!(print_error, g_read_not_write, t_read_not_write, "Read/write flags do not match!");
                 }
 
-                if last_fetched_instr.is_memory() {
-                    if g_addr != t_addr {
-                        print_error("Addresses do not match!");
-                    }
+                if rules.must_match(last_fetched_instr, Field::MAddr) {
+                    check_field!(print_error, g_addr, t_addr, "Addresses do not match!");
+                }
 
-                    if g_access_size != t_access_size {
-                        print_error("Access sizes do not match!");
-                    }
+                if rules.must_match(last_fetched_instr, Field::MAccessSize) {
+                    check_field!(print_error, g_access_size, t_access_size, "Access sizes do not match!");
                 }
 
-                if last_fetched_instr.is_stype() {
-                    if g_memory_wdata != t_memory_wdata {
-                        print_error("Memory write data does not match!");
-                    }
+                if rules.must_match(last_fetched_instr, Field::MMemoryWdata) {
+                    check_field!(print_error, g_memory_wdata, t_memory_wdata, "Memory write data does not match!");
                 }
             },
             (ParsedLine::W{pc: g_pc, we: g_we, addr_rd: g_addr_rd, data_rd: g_data_rd},
             ParsedLine::W{pc: t_pc, we: t_we, addr_rd: t_addr_rd, data_rd: t_data_rd}) => {
                 let last_fetched_pc     = last_fetched_pc.unwrap();
                 let last_fetched_instr  = last_fetched_instr.as_ref().unwrap();
-                if last_fetched_pc != g_pc {
-                    print_error("PC changed since last fetch somehow!");
+                if g_pc != last_fetched_pc {
+                    reference.record(Suspect::GoldenTrace);
+                    print_error(ErrorClass::Reference, "The golden trace's PC changed since the last fetch somehow!");
                 }
 
-                if g_pc != t_pc {
-                    print_error("PCs do not match!");
+                if rules.must_match(last_fetched_instr, Field::WPc) {
+                    check_field!(print_error, g_pc, t_pc, "PCs do not match!");
                 }
 
-                if !last_fetched_instr.is_fence() {
-                    if g_we != t_we {
-                        print_error("Write enable flags do not match!");
-                    }
+                if rules.must_match(last_fetched_instr, Field::WWe) {
+                    check_field!(print_error, g_we, t_we, "Write enable flags do not match!");
+                }
 
-                    if let Some(jzj_addr_rd) = last_fetched_instr.get_rd() {
-                        if g_addr_rd != t_addr_rd {
-                            print_error("RD addresses do not match!");
-                        }
-                        assert_eq!(g_addr_rd, jzj_addr_rd);//Else likely bug in my Rust code
+                if rules.must_match(last_fetched_instr, Field::WAddrRd) {
+                    check_field!(print_error, g_addr_rd, t_addr_rd, "RD addresses do not match!");
+                }
+                if let Some(message) = last_fetched_instr.get_rd().and_then(|jzj_addr_rd| reference.check_decode(last_fetched_instr, Field::WAddrRd, g_addr_rd.widen(), jzj_addr_rd as u32)) {
+                    print_error(ErrorClass::Reference, &message);
+                }
 
-                        if g_data_rd != t_data_rd {
-                            print_error("RD data does not match!");
-                        }
-                    }
+                if rules.must_match(last_fetched_instr, Field::WDataRd) {
+                    check_field!(print_error, g_data_rd, t_data_rd, "RD data does not match!");
                 }
             },
             _ => panic!("Mismatched lines! Golden: {:?}, Test: {:?}", g, t),
         }
 
 
-        if error_count_this_line > 0 {
+        if message_count_this_line > 0 {
             println!("End of error report for line {}.", ii + 1);
         }
 
         total_error_count += error_count_this_line;
     }
 
+    if unknown_value_error_count > 0 {
+        println!("{} of the errors were X/Z values in your trace where the golden trace has a real value", unknown_value_error_count);
+    }
+
+    if let Some(suspect) = reference.summary() {
+        println!("Reference inconsistencies found: {} (the golden trace disagreeing with itself or the decoder, not problems with your CPU; not counted as errors); {}", reference.count(), suspect);
+    }
+
     total_error_count
 }
 
@@ -0,0 +1,135 @@
+/*
+ * File:    elf2x.rs
+ * Brief:   Converts a RV32 ELF file into a `.x` memory image
+ *
+ * Copyright (C) 2024 John Jekel
+ * See the LICENSE file at the root of the project for licensing info.
+ *
+ * Lets you write your own test program, compile it, and turn it into a `.x` file you can drop into
+ * `verif/data` (or hand to `irvedecoder`) without needing the rv32-benchmarks build scripts.
+ *
+*/
+
+/*!
+ * Converts a RV32 ELF file into a `.x` memory image
+*/
+
+/* ------------------------------------------------------------------------------------------------
+ * Submodules
+ * --------------------------------------------------------------------------------------------- */
+
+//TODO (includes "mod ..." and "pub mod ...")
+
+/* ------------------------------------------------------------------------------------------------
+ * Uses
+ * --------------------------------------------------------------------------------------------- */
+
+use common::cli::CommandLine;
+use common::elf::Elf;
+use common::machine::MachineConfig;
+use common::xfile;
+
+/* ------------------------------------------------------------------------------------------------
+ * Macros
+ * --------------------------------------------------------------------------------------------- */
+
+//TODO (also pub(crate) use the_macro statements here too)
+
+/* ------------------------------------------------------------------------------------------------
+ * Constants
+ * --------------------------------------------------------------------------------------------- */
+
+//TODO
+
+/* ------------------------------------------------------------------------------------------------
+ * Static Variables
+ * --------------------------------------------------------------------------------------------- */
+
+//TODO
+
+/* ------------------------------------------------------------------------------------------------
+ * Types
+ * --------------------------------------------------------------------------------------------- */
+
+//TODO includes "type"-defs, structs, enums, unions, etc
+
+/* ------------------------------------------------------------------------------------------------
+ * Associated Functions and Methods
+ * --------------------------------------------------------------------------------------------- */
+
+//TODO
+
+/* ------------------------------------------------------------------------------------------------
+ * Traits And Default Implementations
+ * --------------------------------------------------------------------------------------------- */
+
+//TODO
+
+/* ------------------------------------------------------------------------------------------------
+ * Trait Implementations
+ * --------------------------------------------------------------------------------------------- */
+
+//TODO
+
+/* ------------------------------------------------------------------------------------------------
+ * Functions
+ * --------------------------------------------------------------------------------------------- */
+
+fn main() -> std::process::ExitCode {
+    let result = (|| {
+        let command_line = CommandLine::from_env(&["--base", "--machine"], &[])?;
+        let (elf_path, x_path) = match command_line.positional() {
+            [elf_path]          => (elf_path, None),
+            [elf_path, x_path]  => (elf_path, Some(x_path)),
+            _                   => return Err("Usage: elf2x [--machine path/to/machine.cfg] [--base 01000000] path/to/program.elf [path/to/output.x]".to_string()),
+        };
+
+        let machine = match command_line.value("--machine") {
+            Some(path)  => MachineConfig::from_path(path).map_err(|e| format!("Error loading machine config at path {}: {}", path, e))?,
+            None        => MachineConfig::default(),
+        };
+
+        let base_addr = match command_line.value("--base") {
+            Some(base) => u32::from_str_radix(base.strip_prefix("0x").unwrap_or(base), 16).map_err(|_| format!("Bad base address \"{}\"", base))?,
+            None       => machine.mem_base,
+        };
+
+        let elf = Elf::from_path(elf_path).map_err(|e| format!("Error loading ELF file at path {}: {}", elf_path, e))?;
+        if elf.entry() != base_addr {
+            //Our cores start executing at the first word of memory, not the ELF entry point
+            eprintln!("Warning: entry point is {:08x} but the core will start at {:08x}", elf.entry(), base_addr);
+        }
+
+        let words = xfile::from_elf(&elf, base_addr, machine.mem_size)?;
+
+        match x_path {
+            Some(x_path) => {
+                let file = std::fs::File::create(x_path).map_err(|e| format!("Error creating {}: {}", x_path, e))?;
+                xfile::write(&words, std::io::BufWriter::new(file)).map_err(|e| e.to_string())?;
+                eprintln!("Wrote {} words ({:08x} thru {:08x}) to {}", words.len(), base_addr, base_addr.wrapping_add((words.len() as u32).wrapping_mul(4)).wrapping_sub(1), x_path);
+            },
+            None => xfile::write(&words, std::io::stdout().lock()).map_err(|e| e.to_string())?,
+        }
+
+        Ok(())
+    })();
+
+    if let Err(e) = result {
+        eprintln!("{}", e);
+        std::process::ExitCode::FAILURE
+    } else {
+        std::process::ExitCode::SUCCESS
+    }
+}
+
+/* ------------------------------------------------------------------------------------------------
+ * Tests
+ * --------------------------------------------------------------------------------------------- */
+
+//TODO
+
+/* ------------------------------------------------------------------------------------------------
+ * Benchmarks
+ * --------------------------------------------------------------------------------------------- */
+
+//TODO
@@ -5,7 +5,9 @@
  * Copyright (C) 2024 John Jekel
  * See the LICENSE file at the root of the project for licensing info.
  *
- * The first argument is the golden trace file, and the second argument is your trace file.
+ * The first argument is the golden trace file, and the second argument is your trace file. Without a
+ * golden trace, `pd5diff self-check your.trace` checks your trace against itself instead.
+ * `pd5diff validate-golden golden.trace` checks a golden trace makes sense on its own.
  *
 */
 
@@ -24,7 +26,10 @@
  * --------------------------------------------------------------------------------------------- */
 
 use common::*;
-use riscv_tools::*;
+use common::cli::CommandLine;
+use common::compare::{compare_sim, CompareOptions, Whose};
+use common::cycle::{align_reset, Cycle, CycleIterator};
+use common::selfcheck::self_check;
 
 /* ------------------------------------------------------------------------------------------------
  * Macros
@@ -57,57 +62,18 @@ const LOGO: &'static str = concat!("\x1b[1;36m", r"
 
 type Result<T> = std::result::Result<T, ()>;
 
-#[derive(Default)]
-struct StageState {
-    pc:     u32,
-    instr:  Option<Instruction>,
-}
-
-#[derive(Default)]
-struct Pipeline {
-    f:  StageState,
-    d:  StageState,
-    e:  StageState,
-    m:  StageState,
-    w:  StageState,
+//What we were asked to do
+enum Task {
+    Compare{golden_path: String, test_path: String},
+    SelfCheck{test_path: String},
+    ValidateGolden{golden_path: String},
 }
 
 /* ------------------------------------------------------------------------------------------------
  * Associated Functions and Methods
  * --------------------------------------------------------------------------------------------- */
 
-impl StageState {
-    fn dis(&self) -> String {
-        if let Some(instr_ref) = self.instr.as_ref() {
-            format!("instruction @PC {:08x}: {}", self.pc, disassemble(instr_ref))
-        } else {
-            String::from("nothing (bubble)")
-        }
-    }
-
-    const fn is_bubble(&self) -> bool {
-        self.instr.is_none()
-    }
-}
-
-impl Pipeline {
-    fn dumb_advance(&mut self, f_pc: u32, f_instr: Instruction) {
-        self.w = std::mem::take(&mut self.m);
-        self.m = std::mem::take(&mut self.e);
-        self.e = std::mem::take(&mut self.d);
-        self.d = std::mem::take(&mut self.f);
-        self.f = StageState {
-            pc:     f_pc,
-            instr:  Some(f_instr),
-        };
-    }
-
-    fn dumb_advance_with_stalled_fetch_and_decode(&mut self) {
-        self.w = std::mem::take(&mut self.m);
-        self.m = std::mem::take(&mut self.e);
-        self.e = StageState::default();
-    }
-}
+//TODO
 
 /* ------------------------------------------------------------------------------------------------
  * Traits And Default Implementations
@@ -132,14 +98,50 @@ fn main() -> std::process::ExitCode {
     println!();
 
     let main_body_result = (|| {
-        let (golden_path, test_path) = args()?;
-
-        let golden_trace    = load_trace(golden_path)?;
-        let test_trace      = load_trace(test_path)?;
-        println!("\x1b[1;32mSuccessfully loaded both traces!\x1b[0m");
-
-        println!("\x1b[1mComparing traces...\x1b[0m");
-        let errors = compare(golden_trace, test_trace);
+        let (task, command_line) = args()?;
+
+        let options = CompareOptions::from_command_line(&command_line, "pd5").map_err(|e| println!("\x1b[1;31m{}\x1b[0m", e))?;
+
+        let errors = match task {
+            Task::Compare{golden_path, test_path} => {
+                let golden_trace    = load_trace(golden_path)?;
+                let test_trace      = load_trace(test_path)?;
+                println!("\x1b[1;32mSuccessfully loaded both traces!\x1b[0m");
+
+                verify_same_benchmark(&golden_trace, &test_trace).map_err(|e| println!("\x1b[1;31m{}\x1b[0m", e))?;
+
+                println!("\x1b[1mComparing traces...\x1b[0m");
+                //TODO for better performance, avoid collecting here
+                //Lines are grouped into cycles by their tags, so they can be in any order within a cycle
+                let golden_cycles: Vec<Cycle>   = CycleIterator::new(read_trace(golden_trace)?).collect();
+                let mut test_cycles: Vec<Cycle> = CycleIterator::new(read_trace(test_trace)?).collect();
+                test_cycles.drain(..align_reset(&golden_cycles, &test_cycles, command_line.flag("--exact-start")));
+
+                compare_sim("pd5diff", &golden_cycles, &test_cycles, options)
+            },
+            Task::SelfCheck{test_path} => {
+                let test_trace = load_trace(test_path)?;
+                println!("\x1b[1;32mSuccessfully loaded your trace!\x1b[0m");
+                if let Some(benchmark) = test_trace.header(BENCHMARK_HEADER) {
+                    println!("Benchmark: \x1b[1;36m{}\x1b[0m", benchmark);
+                }
+
+                println!("\x1b[1mChecking your trace against itself...\x1b[0m");
+                let test_cycles: Vec<Cycle> = CycleIterator::new(read_trace(test_trace)?).collect();
+                self_check(&test_cycles, Whose::Yours, &options)
+            },
+            Task::ValidateGolden{golden_path} => {
+                let golden_trace = load_trace(golden_path)?;
+                println!("\x1b[1;32mSuccessfully loaded the golden trace!\x1b[0m");
+                if let Some(benchmark) = golden_trace.header(BENCHMARK_HEADER) {
+                    println!("Benchmark: \x1b[1;36m{}\x1b[0m", benchmark);
+                }
+
+                println!("\x1b[1mValidating the golden trace...\x1b[0m");
+                let golden_cycles: Vec<Cycle> = CycleIterator::new(read_trace(golden_trace)?).collect();
+                self_check(&golden_cycles, Whose::Golden, &options)
+            },
+        };
 
         if errors > 0 {
             println!("\x1b[1;31mFound {} error(s)!\x1b[0m", errors);
@@ -159,379 +161,52 @@ fn main() -> std::process::ExitCode {
     }
 }
 
-fn args() -> Result<(String, String)> {
-    let mut args = std::env::args();
+fn args() -> Result<(Task, CommandLine)> {
+    let usage = || {
+        println!("\x1b[1;31mUsage: pd5diff [--rules path/to/rules.txt] [--elf path/to/benchmark.elf] [--objdump path/to/benchmark.dump] [--machine path/to/machine.cfg] [--end-of-test pc:00000000,ecall,...] [--predictor not-taken|backward-taken|infer] [--pipeline pd5|pd6|path/to/pipeline.cfg] [--lint] [--exact-start] path/to/golden_trace.trace path/to/your_trace.trace\x1b[0m");
+        println!("\x1b[1;31m   or: pd5diff self-check [options above] path/to/your_trace.trace\x1b[0m");
+        println!("\x1b[1;31m   or: pd5diff validate-golden [options above] path/to/golden_trace.trace\x1b[0m");
+    };
 
-    if args.len() != 3 {
-        println!("\x1b[1;31mUsage: pd5diff path/to/golden_trace.trace path/to/your_trace.trace\x1b[0m");
-        return Err(());
-    }
-
-    let golden_path = args.nth(1).ok_or(())?;
-    let test_path   = args.next().ok_or(())?;
+    let command_line = match CommandLine::from_env(&["--rules", "--elf", "--objdump", "--machine", "--end-of-test", "--predictor", "--pipeline"], &["--lint", "--exact-start"]) {
+        Ok(command_line) => command_line,
+        Err(e) => {
+            println!("\x1b[1;31m{}\x1b[0m", e);
+            usage();
+            return Err(());
+        }
+    };
 
-    println!("Path to golden trace: \x1b[1;33m{}\x1b[0m", golden_path);
-    println!("Path to your trace:   \x1b[1;37m{}\x1b[0m", test_path);
+    let task = match command_line.positional() {
+        [subcommand, test_path] if subcommand == "self-check" => {
+            println!("Path to your trace:   \x1b[1;37m{}\x1b[0m", test_path);
+            Task::SelfCheck{test_path: test_path.clone()}
+        },
+        [subcommand, golden_path] if subcommand == "validate-golden" => {
+            println!("Path to golden trace: \x1b[1;33m{}\x1b[0m", golden_path);
+            Task::ValidateGolden{golden_path: golden_path.clone()}
+        },
+        [golden_path, test_path] => {
+            println!("Path to golden trace: \x1b[1;33m{}\x1b[0m", golden_path);
+            println!("Path to your trace:   \x1b[1;37m{}\x1b[0m", test_path);
+            Task::Compare{golden_path: golden_path.clone(), test_path: test_path.clone()}
+        },
+        _ => {
+            usage();
+            return Err(());
+        },
+    };
 
-    Ok((golden_path, test_path))
+    Ok((task, command_line))
 }
 
 fn load_trace(path: impl AsRef<std::path::Path>) -> Result<ParsedLineIterator> {
-    let iterator = ParsedLineIterator::from_path(path.as_ref());
-
-    match iterator {
-        Ok(iterator) => {
-            Ok(iterator)
-        },
-        Err(e) => {
-            println!("\x1b[1;31mError loading trace at path {}: {}\x1b[0m", path.as_ref().display(), e);
-            Err(())
-        }
-    }
+    ParsedLineIterator::from_path(path).map_err(|e| println!("\x1b[1;31m{}\x1b[0m", e))
 }
 
-//Returns the number of errors
-fn compare(golden: ParsedLineIterator, test: ParsedLineIterator) -> u32 {
-    let mut total_error_count   = 0;
-    let mut pipeline            = Pipeline::default();
-
-    //TODO for better performance, avoid collecting here (too bad array_chunks() is unstable)
-    let lines_vec: Vec<(ParsedLine, ParsedLine)> = golden.zip(test).collect();
-    let line_chunks = lines_vec.chunks(6);//[F], [D], [R], [E], [M], [W]
-
-    let mut squash_fetch_and_decode_next_cycle = false;
-
-    for (chunk_num, chunk) in line_chunks.enumerate() {
-        //Convenient aliases
-        let chunk_num           = chunk_num + 1;//Since enumerate() is zero-indexed
-        let (g_fline, t_fline)  = chunk[0];
-        let (g_dline, t_dline)  = chunk[1];
-        let (g_rline, t_rline)  = chunk[2];
-        let (g_eline, t_eline)  = chunk[3];
-        let (g_mline, t_mline)  = chunk[4];
-        let (g_wline, t_wline)  = chunk[5];
-
-        //////////////////////////////////////////////////////////////////////////////////////////////////////
-        //Pipeline updating logic
-        //////////////////////////////////////////////////////////////////////////////////////////////////////
-        if squash_fetch_and_decode_next_cycle {
-            pipeline.f = StageState::default();
-            pipeline.d = StageState::default();
-            squash_fetch_and_decode_next_cycle = false;
-        }
-
-        //Check if fetch and decode stalled, in which case we shouldn't touch fetch or decode and should squash execute
-        let fetch_and_decode_stalled = if let (ParsedLine::D{pc: g_d_pc, ..}, ParsedLine::E{pc: g_e_pc, ..}) = (g_dline, g_eline) {
-            //Don't check if E is a bubble because it could be we're stalling multiple cycles
-            //We do need to check D though because if the PCs just happen to match but were squashed we're not actually stalling
-            if !pipeline.d.is_bubble() {
-                g_d_pc == g_e_pc
-            } else {
-                false
-            }
-        } else {
-            println!("\x1b[1;31mWeirdness in golden trace, are your arguments to pd5diff correct?\x1b[0m");
-            false
-        };
-
-        if fetch_and_decode_stalled {
-            pipeline.dumb_advance_with_stalled_fetch_and_decode();
-        } else if let ParsedLine::F{pc: g_pc, instr: g_instr} = g_fline {
-            if g_pc == 0 {
-                println!("PC in golden trace became 00000000, assuming we've reached the end!");
-                println!("\x1b[90m(This is expected for simple-programs golden traces, since if you");
-                println!("look at their assembly, when they return from main, since `ra` is initialized");
-                println!("to 0 by our hardware, but never by their code, the PC naturally becomes 0.");
-                println!("Technically a bug in their test programs, but it's a nice end-of-code flag for us!)\x1b[0m");
-
-                break;
-            }
-            if g_instr == 0 {
-                println!("Encountered illegal instruction in golden trace, assuming we've reached the end!");
-                println!("\x1b[90m(This is expected for individual-instruction golden traces, since we simply");
-                println!("implement ecall as a NOP, and since these traces end in an ecall, we thus run");
-                println!("into the data afterwards in memory, interpreting it as an instruction)\x1b[0m");
-                break;
-            }
-            pipeline.dumb_advance(g_pc, g_instr.into());
-        } else {
-            println!("\x1b[1;31mWeirdness in golden trace, are your arguments to pd5diff correct?\x1b[0m");
-        }
-
-        //If execute is processing a branch and the branch taken flag is set, or this is an
-        //unconditional jump, squash fetch and decode next cycle
-        if let Some(instr) = pipeline.e.instr.as_ref() {
-            if instr.is_btype() {
-                if let ParsedLine::E{branch_taken: g_branch_taken, ..} = g_eline {
-                    //It seems that branch taken in their traces for PD5 is now also set for
-                    //unconditional branches? Weird that that wasn't the case for PD4...
-                    squash_fetch_and_decode_next_cycle = g_branch_taken;
-                }
-            } else if instr.is_uncond_jump() {
-                squash_fetch_and_decode_next_cycle = true;
-            }
-        }
-
-        //////////////////////////////////////////////////////////////////////////////////////////////////////
-        //Error handling used by line checking below
-        //////////////////////////////////////////////////////////////////////////////////////////////////////
-        let mut chunk_error_count = 0;
-        let mut print_error = |message| {
-            if chunk_error_count == 0 {
-                println!(
-                    "At least one error on clock cycle #{} containing lines {} thru {} (inclusive):",
-                    chunk_num,
-                    chunk_num * 6 - 5,
-                    chunk_num * 6
-                );
-                println!("  \x1b[1;33mGolden\x1b[0m                                      | \x1b[1mYours\x1b[0m");
-                println!("  \x1b[1;33m  {}\x1b[0m                     |   \x1b[1m{}\x1b[0m", g_fline, t_fline);
-                println!("  \x1b[1;33m  {}\x1b[0m |   \x1b[1m{}\x1b[0m", g_dline, t_dline);
-                println!("  \x1b[1;33m  {}\x1b[0m               |   \x1b[1m{}\x1b[0m", g_rline, t_rline);
-                println!("  \x1b[1;33m  {}\x1b[0m                   |   \x1b[1m{}\x1b[0m", g_eline, t_eline);
-                println!("  \x1b[1;33m  {}\x1b[0m        |   \x1b[1m{}\x1b[0m", g_mline, t_mline);
-                println!("  \x1b[1;33m  {}\x1b[0m                |   \x1b[1m{}\x1b[0m", g_wline, t_wline);
-                println!("  \x1b[1;33mGolden Disassembly:");
-                println!("    \x1b[1;33m[F]     is processing {}\x1b[0m", pipeline.f.dis());
-                println!("    \x1b[1;33m[D]/[R] is processing {}\x1b[0m", pipeline.d.dis());
-                println!("    \x1b[1;33m[E]     is processing {}\x1b[0m", pipeline.e.dis());
-                println!("    \x1b[1;33m[M]     is processing {}\x1b[0m", pipeline.m.dis());
-                println!("    \x1b[1;33m[W]     is processing {}\x1b[0m", pipeline.w.dis());
-                println!("  \x1b[1;31mError(s):\x1b[0m");
-            }
-            chunk_error_count += 1;
-            println!("    \x1b[1;31mError {}: {}\x1b[0m", chunk_error_count, message);
-        };
-
-        //////////////////////////////////////////////////////////////////////////////////////////////////////
-        //[F] Line Checking
-        //////////////////////////////////////////////////////////////////////////////////////////////////////
-        if let (ParsedLine::F{pc: g_pc, instr: g_instr}, ParsedLine::F{pc: t_pc, instr: t_instr}) = (g_fline, t_fline) {
-            if g_pc != t_pc {
-                print_error("[F] PCs do not match!");
-            }
-            assert_eq!(g_pc, pipeline.f.pc, "pd5diff bug or bad golden trace");
-
-            if g_instr != t_instr {
-                print_error("[F] Fetched instructions do not match!");
-            }
-        } else {
-            print_error("[F] Mismatched line types or bad traces! Something is VERY wrong!");
-        }
-
-        //////////////////////////////////////////////////////////////////////////////////////////////////////
-        //[D] and [R] Line Checking
-        //////////////////////////////////////////////////////////////////////////////////////////////////////
-        if !pipeline.d.is_bubble() {
-            let instr = pipeline.d.instr.as_ref().unwrap();
-
-            if let (
-                ParsedLine::D{pc: g_pc, opcode: g_opcode, rd: g_rd, rs1: g_rs1, rs2: g_rs2, funct3: g_funct3, funct7: g_funct7, imm: g_imm, shamt: g_shamt},
-                ParsedLine::D{pc: t_pc, opcode: t_opcode, rd: t_rd, rs1: t_rs1, rs2: t_rs2, funct3: t_funct3, funct7: t_funct7, imm: t_imm, shamt: t_shamt}
-            ) = (g_dline, t_dline) {
-                if g_pc != t_pc {
-                    print_error("[D] PCs do not match!");
-                }
-                assert_eq!(g_pc, pipeline.d.pc, "pd5diff bug or bad golden trace");
-
-                if !instr.is_fence() {
-                    if g_opcode != t_opcode {
-                        print_error("[D] Opcodes do not match!");
-                    }
-                }
-
-                //We sometimes don't do comparisons if they are don't cares
-
-                if let Some(jzj_rd) = instr.get_rd() {
-                    if g_rd != t_rd {
-                        print_error("[D] RDs do not match!");
-                    }
-                    assert_eq!(g_rd, jzj_rd, "pd5diff bug or bad golden trace");
-                }
-
-                if let Some(jzj_rs1) = instr.get_rs1() {
-                    if g_rs1 != t_rs1 {
-                        print_error("[D] RS1s do not match!");
-                    }
-                    assert_eq!(g_rs1, jzj_rs1, "pd5diff bug or bad golden trace");
-                }
-
-                if let Some(jzj_rs2) = instr.get_rs2() {
-                    if g_rs2 != t_rs2 {
-                        print_error("[D] RS2s do not match!");
-                    }
-                    assert_eq!(g_rs2, jzj_rs2, "pd5diff bug or bad golden trace");
-                }
-
-                if let Some(jzj_funct3) = instr.get_funct3() {
-                    if g_funct3 != t_funct3 {
-                        print_error("[D] Funct3s do not match!");
-                    }
-                    assert_eq!(g_funct3, jzj_funct3, "pd5diff bug or bad golden trace");
-                }
-
-                if let Some(jzj_funct7) = instr.get_funct7() {
-                    if g_funct7 != t_funct7 {
-                        print_error("[D] Funct7s do not match!");
-                    }
-                    assert_eq!(g_funct7, jzj_funct7, "pd5diff bug or bad golden trace");
-                }
-
-                if let Some(jzj_imm) = instr.get_imm() {
-                    if g_imm != t_imm {
-                        print_error("[D] IMMs do not match!");
-                    }
-                    assert_eq!(g_imm, jzj_imm as u32, "pd5diff bug or bad golden trace");
-                }
-
-                if let Some(jzj_shamt) = instr.get_shamt() {
-                    if g_shamt != t_shamt {
-                        print_error("[D] SHAMTs do not match!");
-                    }
-                    assert_eq!(g_shamt, jzj_shamt, "pd5diff bug or bad golden trace");
-                }
-            } else {
-                print_error("[D] Mismatched line types or bad traces! Something is VERY wrong!");
-            }
-
-            if let (
-                ParsedLine::R{addr_rs1: g_addr_rs1, addr_rs2: g_addr_rs2, data_rs1: g_data_rs1, data_rs2: g_data_rs2},
-                ParsedLine::R{addr_rs1: t_addr_rs1, addr_rs2: t_addr_rs2, data_rs1: t_data_rs1, data_rs2: t_data_rs2}
-            ) = (g_rline, t_rline) {
-                if let Some(jzj_rs1) = instr.get_rs1() {
-                    if g_addr_rs1 != t_addr_rs1 {
-                        print_error("[R] RS1 addresses do not match!");
-                    }
-                    assert_eq!(g_addr_rs1, jzj_rs1, "pd5diff bug or bad golden trace");
-
-                    if g_data_rs1 != t_data_rs1 {
-                        print_error("[R] RS1 data does not match!");
-                    }
-                }
-
-                if let Some(jzj_rs2) = instr.get_rs2() {
-                    if g_addr_rs2 != t_addr_rs2 {
-                        print_error("[R] RS2 addresses do not match!");
-                    }
-                    assert_eq!(g_addr_rs2, jzj_rs2, "pd5diff bug or bad golden trace");
-
-                    if g_data_rs2 != t_data_rs2 {
-                        print_error("[R] RS2 data does not match!");
-                    }
-                }
-            } else {
-                print_error("[R] Mismatched line types or bad traces! Something is VERY wrong!");
-            }
-        }
-
-        //////////////////////////////////////////////////////////////////////////////////////////////////////
-        //[E] Line Checking
-        //////////////////////////////////////////////////////////////////////////////////////////////////////
-        if !pipeline.e.is_bubble() {
-            let instr = pipeline.e.instr.as_ref().unwrap();
-
-            if let (
-                ParsedLine::E{pc: g_pc, alu_result: g_alu_result, branch_taken: g_branch_taken},
-                ParsedLine::E{pc: t_pc, alu_result: t_alu_result, branch_taken: t_branch_taken}
-            ) = (g_eline, t_eline) {
-                if g_pc != t_pc {
-                    print_error("[E] PCs do not match!");
-                }
-                assert_eq!(g_pc, pipeline.e.pc, "pd5diff bug or bad golden trace");
-
-                if !instr.is_fence() && !instr.is_system() {
-                    if g_alu_result != t_alu_result {
-                        print_error("[E] ALU results do not match!");
-                    }
-                }
-
-                if instr.is_btype() {
-                    if g_branch_taken != t_branch_taken {
-                        print_error("[E] Branch taken line does not match!");
-                    }
-                }
-            } else {
-                print_error("[E] Mismatched line types or bad traces! Something is VERY wrong!");
-            }
-        }
-
-        //////////////////////////////////////////////////////////////////////////////////////////////////////
-        //[M] Line Checking
-        //////////////////////////////////////////////////////////////////////////////////////////////////////
-        if !pipeline.m.is_bubble() {
-            let instr = pipeline.m.instr.as_ref().unwrap();
-            if let (
-                ParsedLine::M{pc: g_pc, addr: g_addr, read_not_write: g_read_not_write, access_size: g_access_size, memory_wdata: g_memory_wdata},
-                ParsedLine::M{pc: t_pc, addr: t_addr, read_not_write: t_read_not_write, access_size: t_access_size, memory_wdata: t_memory_wdata}
-            ) = (g_mline, t_mline) {
-                if g_pc != t_pc {
-                    print_error("[M] PCs do not match!");
-                }
-                assert_eq!(g_pc, pipeline.m.pc, "pd5diff bug or bad golden trace");
-
-                if g_read_not_write != t_read_not_write {
-                    print_error("[M] Read-not-write line does not match!");
-                }
-
-                if instr.is_memory() {
-                    if g_addr != t_addr {
-                        print_error("[M] Addresses do not match!");
-                    }
-
-                    if g_access_size != t_access_size {
-                        print_error("[M] Access sizes do not match!");
-                    }
-                }
-
-                if instr.is_stype() {
-                    if g_memory_wdata != t_memory_wdata {
-                        print_error("[M] Memory write data does not match!");
-                    }
-                }
-            } else {
-                print_error("[M] Mismatched line types or bad traces! Something is VERY wrong!");
-            }
-        }
-
-        //////////////////////////////////////////////////////////////////////////////////////////////////////
-        //[W] Line Checking
-        //////////////////////////////////////////////////////////////////////////////////////////////////////
-        if !pipeline.w.is_bubble() {
-            let instr = pipeline.w.instr.as_ref().unwrap();
-            if let (
-                ParsedLine::W{pc: g_pc, we: g_we, addr_rd: g_addr_rd, data_rd: g_data_rd},
-                ParsedLine::W{pc: t_pc, we: t_we, addr_rd: t_addr_rd, data_rd: t_data_rd}
-            ) = (g_wline, t_wline) {
-                if g_pc != t_pc {
-                    print_error("[W] PCs do not match!");
-                }
-                assert_eq!(g_pc, pipeline.w.pc, "pd5diff bug or bad golden trace");
-
-                if !instr.is_fence() {
-                    if g_we != t_we {
-                        print_error("[W] Write enable line does not match!");
-                    }
-
-                    if let Some(jzj_addr_rd) = instr.get_rd() {
-                        if g_addr_rd != t_addr_rd {
-                            print_error("[W] RD addresses do not match!");
-                        }
-                        assert_eq!(g_addr_rd, jzj_addr_rd, "pd5diff bug or bad golden trace");
-
-                        if g_data_rd != t_data_rd {
-                            print_error("[W] RD data does not match!");
-                        }
-                    }
-                }
-            } else {
-                print_error("[W] Mismatched line types or bad traces! Something is VERY wrong!");
-            }
-        }
-
-        total_error_count += chunk_error_count;
-    }
-
-    total_error_count
+//Reads the rest of the trace, stopping with the file and line number of the first line that can't be parsed
+fn read_trace(trace: ParsedLineIterator) -> Result<std::vec::IntoIter<(Stamp, ParsedLine)>> {
+    trace.read_stamped().map(Vec::into_iter).map_err(|e| println!("\x1b[1;31m{}\x1b[0m", e))
 }
 
 /* ------------------------------------------------------------------------------------------------
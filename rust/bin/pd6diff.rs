@@ -5,7 +5,10 @@
  * Copyright (C) 2024 John Jekel
  * See the LICENSE file at the root of the project for licensing info.
  *
- * The first argument is the golden trace file, and the second argument is your trace file.
+ * The first argument is the golden trace file, and the second argument is your trace file. Without a
+ * golden trace, `pd6simdiff self-check your.trace` checks your trace against itself instead.
+ * `pd6simdiff validate-golden golden_sim.trace [golden_board.trace]` checks a golden trace makes sense
+ * on its own (and that its [W] lines are the same as the golden_board trace's, if given).
  *
 */
 
@@ -24,7 +27,16 @@
  * --------------------------------------------------------------------------------------------- */
 
 use common::*;
-use riscv_tools::*;
+use common::cli::CommandLine;
+use common::compare::{check_golden_redirect, compare_sim, warn_about_golden_cycle, CompareOptions, CycleReport, ReportLines, ReportTotals, Whose};
+use common::capture::{self, Capture, CaptureEnd, CaptureProblem, CaptureSettings};
+use common::cycle::{align_reset, Cycle, CycleIterator};
+use common::lint::Linter;
+use common::logic::Logic;
+use common::reference::ReferenceLog;
+use common::rules::Field;
+use common::selfcheck::self_check;
+use common::tracker::PipelineTracker;
 
 use std::fmt::Display;
 
@@ -47,6 +59,13 @@ const LOGO: &'static str = concat!("\x1b[1;36m", r"
 |_|                                  for ECE 320
 ", "\x1b[0m");
 
+//How far apart the traces can get before we give up on lining them back up, and how many [W] PCs in a row have to match to call them lined up
+const MAX_RESYNC_DISTANCE:  usize = 16;
+const RESYNC_MATCH_LEN:     usize = 4;
+
+//How many differences between golden_sim and golden_board [W] lines to print before just counting them
+const MAX_BOARD_DIFFS_SHOWN: usize = 20;
+
 /* ------------------------------------------------------------------------------------------------
  * Static Variables
  * --------------------------------------------------------------------------------------------- */
@@ -57,71 +76,38 @@ const LOGO: &'static str = concat!("\x1b[1;36m", r"
  * Types
  * --------------------------------------------------------------------------------------------- */
 
-type Result<T>  = std::result::Result<T, ()>;
-type MaybeInstr = std::result::Result<Instruction, InstrNotPresentReason>;
+type Result<T> = std::result::Result<T, ()>;
 
-#[derive(Debug)]
-enum InstrNotPresentReason {
-    Bubble,
-    StallSoInstrWordNotAvailable,
+enum Mode {
+    Board,//pd6boarddiff
+    Sim//pd6simdiff
 }
 
-struct StageState {
-    pc:     u32,
-    instr:  MaybeInstr,
+//What we were asked to do
+enum Task {
+    Compare{golden_path: String, test_path: String},
+    Capture{golden_path: String, device_path: String},//Your trace comes straight from the board
+    SelfCheck{test_path: String},
+    ValidateGolden{golden_path: String, board_path: Option<String>},//The golden_board trace is optional
 }
 
-#[derive(Default)]
-struct Pipeline {
-    f:  StageState,
-    d:  StageState,
-    e:  StageState,
-    m:  StageState,
-    w:  StageState,
+//Your [W] lines in board mode, and which of them came right after a capture problem (if captured live)
+struct BoardTrace {
+    lines:          Vec<(Stamp, ParsedLine)>,
+    problem_lines:  Vec<usize>,
 }
 
-enum Mode {
-    Board,//pd6boarddiff
-    Sim//pd6simdiff
+//How many lines to skip in one trace or the other to get the traces back in step
+#[derive(Debug, PartialEq, Eq)]
+enum Misalignment {
+    Dropped(usize),//Golden cycles whose lines your trace is missing
+    Repeated(usize),//Lines in your trace that don't belong
 }
 
 /* ------------------------------------------------------------------------------------------------
  * Associated Functions and Methods
  * --------------------------------------------------------------------------------------------- */
 
-impl StageState {
-    fn dis(&self) -> String {
-        match self.instr.as_ref() {
-            Ok(instr_ref)                                               => format!("instruction @PC {:08x}: {:08x}: {}", self.pc, instr_ref.assume_uncompressed(), disassemble(instr_ref)),
-            Err(InstrNotPresentReason::Bubble)                          => String::from("nothing (bubble)"),
-            Err(InstrNotPresentReason::StallSoInstrWordNotAvailable)    => format!("instruction @PC {:08x}: ????????: instruction word not available from golden trace due to stall next cycle", self.pc),
-        }
-    }
-
-    const fn is_bubble(&self) -> bool {
-        matches!(self.instr, Err(InstrNotPresentReason::Bubble))
-    }
-}
-
-impl Pipeline {
-    fn dumb_advance(&mut self, f_pc: u32, f_instr: MaybeInstr) {
-        self.w = std::mem::take(&mut self.m);
-        self.m = std::mem::take(&mut self.e);
-        self.e = std::mem::take(&mut self.d);
-        self.d = std::mem::take(&mut self.f);
-        self.f = StageState {
-            pc:     f_pc,
-            instr:  f_instr,
-        };
-    }
-
-    fn dumb_advance_with_stalled_fetch_and_decode(&mut self) {
-        self.w = std::mem::take(&mut self.m);
-        self.m = std::mem::take(&mut self.e);
-        self.e = StageState::default();
-    }
-}
-
 impl Mode {
     fn get() -> Self {
         match env!("CARGO_BIN_NAME") {
@@ -142,15 +128,6 @@ impl Mode {
  * Trait Implementations
  * --------------------------------------------------------------------------------------------- */
 
-impl Default for StageState {
-    fn default() -> Self {
-        Self {
-            pc:     0,
-            instr:  Err(InstrNotPresentReason::Bubble),
-        }
-    }
-}
-
 impl Display for Mode {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
@@ -174,16 +151,74 @@ fn main() -> std::process::ExitCode {
         let mode = Mode::get();
         println!("Running in \x1b[1;36m{}\x1b[0m mode", mode);
 
-        let (golden_path, test_path) = args()?;
+        let (task, command_line) = args(&mode)?;
 
-        let golden_trace    = load_trace(golden_path)?;
-        let test_trace      = load_trace(test_path)?;
-        println!("\x1b[1;32mSuccessfully loaded both traces!\x1b[0m");
+        let options = CompareOptions::from_command_line(&command_line, "pd6").map_err(|e| println!("\x1b[1;31m{}\x1b[0m", e))?;
 
-        println!("\x1b[1mComparing traces...\x1b[0m");
-        let errors = match mode {
-            Mode::Board => compare_board(golden_trace, test_trace),
-            Mode::Sim   => compare_sim  (golden_trace, test_trace),
+        let errors = match task {
+            Task::Capture{golden_path, device_path} => {
+                let golden_trace = load_trace(golden_path)?;
+                if let Some(benchmark) = golden_trace.header(BENCHMARK_HEADER) {
+                    println!("Benchmark: \x1b[1;36m{}\x1b[0m", benchmark);
+                }
+                let golden_cycles: Vec<Cycle>   = CycleIterator::new(read_trace(golden_trace)?).collect();
+                let board_trace                 = capture_board(&device_path, &command_line, golden_cycles.len())?;
+
+                println!("\x1b[1mComparing traces...\x1b[0m");
+                compare_board(golden_cycles, board_trace, &options)
+            },
+            Task::Compare{golden_path, test_path} => {
+                let golden_trace    = load_trace(golden_path)?;
+                let test_trace      = load_trace(test_path)?;
+                println!("\x1b[1;32mSuccessfully loaded both traces!\x1b[0m");
+
+                verify_same_benchmark(&golden_trace, &test_trace).map_err(|e| println!("\x1b[1;31m{}\x1b[0m", e))?;
+
+                println!("\x1b[1mComparing traces...\x1b[0m");
+                match mode {
+                    Mode::Board => compare_board(CycleIterator::new(read_trace(golden_trace)?).collect(), BoardTrace{lines: read_trace(test_trace)?.collect(), problem_lines: Vec::new()}, &options),
+                    Mode::Sim   => {
+                        //Lines are grouped into cycles by their tags, so they can be in any order within a cycle
+                        let golden_cycles: Vec<Cycle>   = CycleIterator::new(read_trace(golden_trace)?).collect();
+                        let mut test_cycles: Vec<Cycle> = CycleIterator::new(read_trace(test_trace)?).collect();
+                        test_cycles.drain(..align_reset(&golden_cycles, &test_cycles, command_line.flag("--exact-start")));
+
+                        compare_sim("pd6diff", &golden_cycles, &test_cycles, options)
+                    },
+                }
+            },
+            Task::SelfCheck{test_path} => {
+                let test_trace = load_trace(test_path)?;
+                println!("\x1b[1;32mSuccessfully loaded your trace!\x1b[0m");
+                if let Some(benchmark) = test_trace.header(BENCHMARK_HEADER) {
+                    println!("Benchmark: \x1b[1;36m{}\x1b[0m", benchmark);
+                }
+
+                println!("\x1b[1mChecking your trace against itself...\x1b[0m");
+                let test_cycles: Vec<Cycle> = CycleIterator::new(read_trace(test_trace)?).collect();
+                self_check(&test_cycles, Whose::Yours, &options)
+            },
+            Task::ValidateGolden{golden_path, board_path} => {
+                let golden_trace    = load_trace(golden_path)?;
+                let board_trace     = board_path.map(load_trace).transpose()?;
+                println!("\x1b[1;32mSuccessfully loaded the golden trace(s)!\x1b[0m");
+
+                if let Some(board_trace) = board_trace.as_ref() {
+                    verify_same_benchmark(&golden_trace, board_trace).map_err(|e| println!("\x1b[1;31m{}\x1b[0m", e))?;
+                } else if let Some(benchmark) = golden_trace.header(BENCHMARK_HEADER) {
+                    println!("Benchmark: \x1b[1;36m{}\x1b[0m", benchmark);
+                }
+
+                println!("\x1b[1mValidating the golden trace...\x1b[0m");
+                let golden_cycles: Vec<Cycle> = CycleIterator::new(read_trace(golden_trace)?).collect();
+                let mut errors = self_check(&golden_cycles, Whose::Golden, &options);
+
+                if let Some(board_trace) = board_trace {
+                    println!("\x1b[1mComparing its [W] lines against the golden board trace...\x1b[0m");
+                    errors += compare_golden_board(&golden_cycles, read_trace(board_trace)?.collect());
+                }
+                errors
+            },
         };
 
         if errors > 0 {
@@ -204,635 +239,459 @@ fn main() -> std::process::ExitCode {
     }
 }
 
-fn args() -> Result<(String, String)> {
-    let mut args = std::env::args();
+fn args(mode: &Mode) -> Result<(Task, CommandLine)> {
+    let usage = || {
+        println!("\x1b[1;31mUsage: pd6diff [--rules path/to/rules.txt] [--elf path/to/benchmark.elf] [--objdump path/to/benchmark.dump] [--machine path/to/machine.cfg] [--end-of-test pc:00000000,ecall,...] [--predictor not-taken|backward-taken|infer] [--pipeline pd5|pd6|path/to/pipeline.cfg] [--lint] [--exact-start] path/to/golden_trace.trace path/to/your_trace.trace\x1b[0m");
+        println!("\x1b[1;31m   or: pd6boarddiff capture [--baud 115200] [--save path/to/your_trace.trace] [--idle-timeout seconds] [other options above] path/to/golden_trace.trace /dev/ttyUSB1\x1b[0m");
+        println!("\x1b[1;31m   or: pd6simdiff self-check [options above] path/to/your_trace.trace\x1b[0m");
+        println!("\x1b[1;31m   or: pd6diff validate-golden [options above] path/to/golden_sim_trace.trace [path/to/golden_board_trace.trace]\x1b[0m");
+    };
 
-    if args.len() != 3 {
-        println!("\x1b[1;31mUsage: pd6diff path/to/golden_trace.trace path/to/your_trace.trace\x1b[0m");
-        return Err(());
-    }
-
-    let golden_path = args.nth(1).ok_or(())?;
-    let test_path   = args.next().ok_or(())?;
+    let command_line = match CommandLine::from_env(&["--rules", "--elf", "--objdump", "--machine", "--end-of-test", "--predictor", "--pipeline", "--baud", "--save", "--idle-timeout"], &["--lint", "--exact-start"]) {
+        Ok(command_line) => command_line,
+        Err(e) => {
+            println!("\x1b[1;31m{}\x1b[0m", e);
+            usage();
+            return Err(());
+        }
+    };
 
-    println!("Path to golden trace: \x1b[1;33m{}\x1b[0m", golden_path);
-    println!("Path to your trace:   \x1b[1;37m{}\x1b[0m", test_path);
+    let task = match command_line.positional() {
+        [subcommand, golden_path, device_path] if subcommand == "capture" => {
+            if let Mode::Sim = mode {
+                println!("\x1b[1;31mCapturing only makes sense for board traces, use pd6boarddiff instead\x1b[0m");
+                return Err(());
+            }
+            println!("Path to golden trace: \x1b[1;33m{}\x1b[0m", golden_path);
+            println!("Capturing your trace from: \x1b[1;37m{}\x1b[0m", device_path);
+            Task::Capture{golden_path: golden_path.clone(), device_path: device_path.clone()}
+        },
+        [subcommand, test_path] if subcommand == "self-check" => {
+            if let Mode::Board = mode {
+                println!("\x1b[1;31mSelf-checking needs every stage's lines, not just [W], so use pd6simdiff on a simulation trace instead\x1b[0m");
+                return Err(());
+            }
+            println!("Path to your trace:   \x1b[1;37m{}\x1b[0m", test_path);
+            Task::SelfCheck{test_path: test_path.clone()}
+        },
+        [subcommand, golden_path] if subcommand == "validate-golden" => {
+            println!("Path to golden trace: \x1b[1;33m{}\x1b[0m", golden_path);
+            Task::ValidateGolden{golden_path: golden_path.clone(), board_path: None}
+        },
+        [subcommand, golden_path, board_path] if subcommand == "validate-golden" => {
+            println!("Path to golden trace: \x1b[1;33m{}\x1b[0m", golden_path);
+            println!("Path to golden board trace: \x1b[1;33m{}\x1b[0m", board_path);
+            Task::ValidateGolden{golden_path: golden_path.clone(), board_path: Some(board_path.clone())}
+        },
+        [golden_path, test_path] => {
+            println!("Path to golden trace: \x1b[1;33m{}\x1b[0m", golden_path);
+            println!("Path to your trace:   \x1b[1;37m{}\x1b[0m", test_path);
+            Task::Compare{golden_path: golden_path.clone(), test_path: test_path.clone()}
+        },
+        _ => {
+            usage();
+            return Err(());
+        },
+    };
 
-    Ok((golden_path, test_path))
+    Ok((task, command_line))
 }
 
 fn load_trace(path: impl AsRef<std::path::Path>) -> Result<ParsedLineIterator> {
-    let iterator = ParsedLineIterator::from_path(path.as_ref());
-
-    match iterator {
-        Ok(iterator) => {
-            Ok(iterator)
-        },
-        Err(e) => {
-            println!("\x1b[1;31mError loading trace at path {}: {}\x1b[0m", path.as_ref().display(), e);
-            Err(())
-        }
-    }
+    ParsedLineIterator::from_path(path).map_err(|e| println!("\x1b[1;31m{}\x1b[0m", e))
 }
 
-//Returns the number of errors
-fn compare_board(golden: ParsedLineIterator, test: ParsedLineIterator) -> u32 {
-    let mut total_error_count   = 0;
-    let mut pipeline            = Pipeline::default();
-
-    //TODO for better performance, avoid collecting here (too bad array_chunks() is unstable)
-    //golden has [F], [D], [R], [E], [M], [W]
-    //test only has [W] when in board mode
-    let golden: Vec<ParsedLine>                     = golden.collect();
-    let lines_vec: Vec<(&[ParsedLine], ParsedLine)> = golden.chunks(6).zip(test).collect();
-    let line_chunks_windowed                        = lines_vec.windows(2);
-
-    let mut squash_fetch_and_decode_next_cycle = false;
-
-    for (window_num, chunk_window) in line_chunks_windowed.enumerate() {
-        //Convenient aliases
-        let window_num = window_num + 1;//Since enumerate() is zero-indexed
-        let g_fline = chunk_window[0].0[0];
-        let g_dline = chunk_window[0].0[1];
-        let g_rline = chunk_window[0].0[2];
-        let g_eline = chunk_window[0].0[3];
-        let g_mline = chunk_window[0].0[4];
-        let g_wline = chunk_window[0].0[5];
-        let t_wline = chunk_window[0].1;
-
-        let g_fline_next    = chunk_window[1].0[0];
-        let g_dline_next    = chunk_window[1].0[1];
-        let _g_rline_next   = chunk_window[1].0[2];
-        let g_eline_next    = chunk_window[1].0[3];
-        let _g_mline_next   = chunk_window[1].0[4];
-        let _g_wline_next   = chunk_window[1].0[5];
-        let _t_wline_next   = chunk_window[1].1;
+//Reads the rest of the trace, stopping with the file and line number of the first line that can't be parsed
+fn read_trace(trace: ParsedLineIterator) -> Result<std::vec::IntoIter<(Stamp, ParsedLine)>> {
+    trace.read_stamped().map(Vec::into_iter).map_err(|e| println!("\x1b[1;31m{}\x1b[0m", e))
+}
 
-        //////////////////////////////////////////////////////////////////////////////////////////////////////
-        //Pipeline updating logic
-        //////////////////////////////////////////////////////////////////////////////////////////////////////
-        if squash_fetch_and_decode_next_cycle {
-            pipeline.f = StageState::default();
-            pipeline.d = StageState::default();
-            squash_fetch_and_decode_next_cycle = false;
+//Reads [W] lines from the board until it's sent one per golden cycle (or goes quiet), saving them if asked to
+fn capture_board(device_path: &str, command_line: &CommandLine, golden_cycle_count: usize) -> Result<BoardTrace> {
+    let baud = match command_line.value("--baud").map(str::parse::<u32>) {
+        Some(Ok(baud))  => Some(baud),
+        Some(Err(e))    => {
+            println!("\x1b[1;31mBad --baud: {}\x1b[0m", e);
+            return Err(());
+        },
+        None            => None,
+    };
+    let idle_timeout = match command_line.value("--idle-timeout").map(str::parse::<f64>) {
+        Some(Ok(seconds)) if seconds.is_finite() && (seconds > 0.0) => std::time::Duration::from_secs_f64(seconds),
+        Some(_) => {
+            println!("\x1b[1;31mBad --idle-timeout, it should be a positive number of seconds\x1b[0m");
+            return Err(());
+        },
+        None    => std::time::Duration::from_secs(3),
+    };
+
+    let source = match capture::open_source(device_path, baud) {
+        Ok(source)  => source,
+        Err(e)      => {
+            println!("\x1b[1;31mError opening {} to capture from: {}\x1b[0m", device_path, e);
+            return Err(());
         }
+    };
 
-        //Check if fetch and decode stalled, in which case we shouldn't touch fetch or decode and should squash execute
-        let fetch_and_decode_stalled = if let (ParsedLine::D{pc: g_d_pc, ..}, ParsedLine::E{pc: g_e_pc, ..}) = (g_dline, g_eline) {
-            //Don't check if E is a bubble because it could be we're stalling multiple cycles
-            //We do need to check D though because if the PCs just happen to match but were squashed we're not actually stalling
-            if !pipeline.d.is_bubble() {
-                g_d_pc == g_e_pc
-            } else {
-                false
-            }
-        } else {
-            println!("\x1b[1;31mWeirdness in golden trace, are your arguments to pd6diff correct?\x1b[0m");
-            false
-        };
+    println!("\x1b[1mWaiting for [W] lines from the board (reset it now if it's already run)...\x1b[0m");
+    let settings = CaptureSettings {
+        idle_timeout,
+        max_lines: Some(golden_cycle_count),
+    };
+    let capture = Capture::run(source, &settings);
 
-        //Similar check for if the stall is happening next cycle
-        let fetch_and_decode_stalled_next_cycle = if let (ParsedLine::D{pc: g_d_pc_next, ..}, ParsedLine::E{pc: g_e_pc_next, ..}) = (g_dline_next, g_eline_next) {
-            if !pipeline.f.is_bubble() {//Look at if F is a bubble because we care if decode is a bubble NEXT cycle
-                g_d_pc_next == g_e_pc_next
-            } else {
-                false
-            }
-        } else {
-            println!("\x1b[1;31mWeirdness in golden trace, are your arguments to pd6diff correct?\x1b[0m");
-            false
-        };
+    for problem in &capture.problems {
+        println!("\x1b[1;33mCapture warning: {}\x1b[0m", problem);
+    }
+    let end_color = if let CaptureEnd::ReadError(_) = capture.end { "\x1b[1;31m" } else { "\x1b[1;32m" };
+    println!("{}Captured {} [W] line(s) in {:.1}s, stopping because {}\x1b[0m", end_color, capture.lines.len(), capture.elapsed.as_secs_f64(), capture.end.reason());
+    if !capture.problems.is_empty() {
+        println!("\x1b[1;33m{} chunk(s) of the board's output couldn't be used; if any were [W] lines, the comparison will be out of step after them\x1b[0m", capture.problems.len());
+    }
 
-        if !fetch_and_decode_stalled_next_cycle {
-            if let Err(InstrNotPresentReason::StallSoInstrWordNotAvailable) = pipeline.f.instr {
-                //No longer stalled, need to populate the instruction now that we should have it
-                //(in the next cycle we have it that is, which is within lookahead range)
-                if let ParsedLine::F{instr: g_instr_next, ..} = g_fline_next {
-                    pipeline.f.instr = Ok(g_instr_next.into());
-                } else {
-                    println!("\x1b[1;31mWeirdness in golden trace, are your arguments to pd6diff correct?\x1b[0m");
-                }
-            }
+    if let Some(save_path) = command_line.value("--save") {
+        match capture.save(save_path, device_path) {
+            Ok(())  => println!("Saved the capture to \x1b[1;37m{}\x1b[0m", save_path),
+            Err(e)  => println!("\x1b[1;31mError saving the capture to {}: {}\x1b[0m", save_path, e),
         }
+    }
 
-        if fetch_and_decode_stalled {
-            pipeline.dumb_advance_with_stalled_fetch_and_decode();
-        } else if let (ParsedLine::F{pc: g_pc, ..}, ParsedLine::F{instr: g_instr_next, ..}) = (g_fline, g_fline_next) {
-            //Need to look at the fetch stage a cycle in the future to get the instruction word
-            //because in PD6, imemory has one cycle of additional latency.
-
-            if g_pc == 0 {
-                println!("PC in golden trace became 00000000, assuming we've reached the end!");
-                println!("\x1b[90m(This is expected for simple-programs golden traces, since if you");
-                println!("look at their assembly, when they return from main, since `ra` is initialized");
-                println!("to 0 by our hardware, but never by their code, the PC naturally becomes 0.");
-                println!("Technically a bug in their test programs, but it's a nice end-of-code flag for us!)\x1b[0m");
-
-                break;
-            }
+    if capture.lines.is_empty() {
+        println!("\x1b[1;31mNothing to compare, the board didn't send any [W] lines\x1b[0m");
+        return Err(());
+    }
 
-            if fetch_and_decode_stalled_next_cycle {
-                pipeline.dumb_advance(g_pc, Err(InstrNotPresentReason::StallSoInstrWordNotAvailable));
-            } else {
-                if g_instr_next == 0 {
-                    println!("Encountered illegal instruction in golden trace, assuming we've reached the end!");
-                    println!("\x1b[90m(This is expected for individual-instruction golden traces, since we simply");
-                    println!("implement ecall as a NOP, and since these traces end in an ecall, we thus run");
-                    println!("into the data afterwards in memory, interpreting it as an instruction)\x1b[0m");
-                    break;
-                }
+    //When a line was received isn't a simulation time, so it's not the line's stamp
+    Ok(BoardTrace {
+        lines:          capture.lines.iter().map(|captured_line| (Stamp::default(), captured_line.line)).collect(),
+        problem_lines:  capture.problems.iter().map(CaptureProblem::next_line).collect(),
+    })
+}
 
-                pipeline.dumb_advance(g_pc, Ok(g_instr_next.into()));
-            }
-        } else {
-            println!("\x1b[1;31mWeirdness in golden trace, are your arguments to pd6diff correct?\x1b[0m");
-        }
+fn wline_pc(line: Option<ParsedLine>) -> Option<Logic<u32>> {
+    match line {
+        Some(ParsedLine::W{pc, ..}) => Some(pc),
+        _                           => None,
+    }
+}
 
-        //If execute is processing a branch and the branch taken flag is set, or this is an
-        //unconditional jump, squash fetch and decode next cycle
-        if let Ok(instr) = pipeline.e.instr.as_ref() {
-            if instr.is_btype() {
-                if let ParsedLine::E{branch_taken: g_branch_taken, ..} = g_eline {
-                    //It seems that branch taken in their traces for PD5 is now also set for
-                    //unconditional branches? Weird that that wasn't the case for PD4...
-                    squash_fetch_and_decode_next_cycle = g_branch_taken;
-                }
-            } else if instr.is_uncond_jump() {
-                squash_fetch_and_decode_next_cycle = true;
-            }
+//Given the [W] PCs from the current golden cycle and line of yours onwards, whether lines were lost or
+//repeated right here. Only when the current PCs differ, and only if skipping a few lines makes the
+//next few PCs match up exactly (otherwise it's a real PC mismatch, ex. a wrong branch)
+fn find_misalignment(golden_wpcs: &[Option<Logic<u32>>], test_wpcs: &[Option<Logic<u32>>]) -> Option<Misalignment> {
+    let lined_up = |golden_skip: usize, test_skip: usize| {
+        let golden_wpcs = golden_wpcs.get(golden_skip..(golden_skip + RESYNC_MATCH_LEN));
+        let test_wpcs   = test_wpcs.get(test_skip..(test_skip + RESYNC_MATCH_LEN));
+        match (golden_wpcs, test_wpcs) {
+            (Some(golden_wpcs), Some(test_wpcs))    => golden_wpcs.iter().zip(test_wpcs).all(|(g_pc, t_pc)| g_pc.is_some() && (g_pc == t_pc)),
+            _                                       => false,
         }
+    };
 
-        //////////////////////////////////////////////////////////////////////////////////////////////////////
-        //Error handling used by line checking below
-        //////////////////////////////////////////////////////////////////////////////////////////////////////
-        let mut chunk_error_count = 0;
-        let mut print_error = |message| {
-            if chunk_error_count == 0 {
-                println!(
-                    "At least one error on clock cycle #{} containing lines {} thru {} (inclusive):",
-                    window_num,
-                    window_num * 6 - 5,
-                    window_num * 6
-                );
-                println!("  \x1b[1;33mGolden\x1b[0m                                      | \x1b[1mYours\x1b[0m");
-                println!("  \x1b[1;33m  {}\x1b[0m                     |   \x1b[1m(not available in board trace)\x1b[0m", g_fline);
-                println!("  \x1b[1;33m  {}\x1b[0m |   \x1b[1m(not available in board trace)\x1b[0m", g_dline);
-                println!("  \x1b[1;33m  {}\x1b[0m               |   \x1b[1m(not available in board trace)\x1b[0m", g_rline);
-                println!("  \x1b[1;33m  {}\x1b[0m                   |   \x1b[1m(not available in board trace)\x1b[0m", g_eline);
-                println!("  \x1b[1;33m  {}\x1b[0m        |   \x1b[1m(not available in board trace)\x1b[0m", g_mline);
-                println!("  \x1b[1;33m  {}\x1b[0m                |   \x1b[1m{}\x1b[0m", g_wline, t_wline);
-                println!("  \x1b[1;33mGolden Disassembly:");
-                println!("    \x1b[1;33m[F]     is processing {}\x1b[0m", pipeline.f.dis());
-                println!("    \x1b[1;33m[D]/[R] is processing {}\x1b[0m", pipeline.d.dis());
-                println!("    \x1b[1;33m[E]     is processing {}\x1b[0m", pipeline.e.dis());
-                println!("    \x1b[1;33m[M]     is processing {}\x1b[0m", pipeline.m.dis());
-                println!("    \x1b[1;33m[W]     is processing {}\x1b[0m", pipeline.w.dis());
-                println!("  \x1b[1;31mError(s):\x1b[0m");
-            }
-            chunk_error_count += 1;
-            println!("    \x1b[1;31mError {}: {}\x1b[0m", chunk_error_count, message);
-        };
+    match (golden_wpcs.first(), test_wpcs.first()) {
+        (Some(Some(g_pc)), Some(Some(t_pc))) if g_pc.is_known() && t_pc.is_known() && (g_pc != t_pc) => {},
+        _ => return None,
+    }
 
-        //////////////////////////////////////////////////////////////////////////////////////////////////////
-        //[W] Line Checking
-        //////////////////////////////////////////////////////////////////////////////////////////////////////
-        if !pipeline.w.is_bubble() {
-            let instr = pipeline.w.instr.as_ref().unwrap();
-            if let (
-                ParsedLine::W{pc: g_pc, we: g_we, addr_rd: g_addr_rd, data_rd: g_data_rd},
-                ParsedLine::W{pc: t_pc, we: t_we, addr_rd: t_addr_rd, data_rd: t_data_rd}
-            ) = (g_wline, t_wline) {
-                if g_pc != t_pc {
-                    print_error("[W] PCs do not match!");
-                }
-                assert_eq!(g_pc, pipeline.w.pc, "pd6diff bug or bad golden trace");
-
-                if !instr.is_fence() {
-                    if g_we != t_we {
-                        print_error("[W] Write enable line does not match!");
-                    }
-
-                    if let Some(jzj_addr_rd) = instr.get_rd() {
-                        if g_addr_rd != t_addr_rd {
-                            print_error("[W] RD addresses do not match!");
-                        }
-                        assert_eq!(g_addr_rd, jzj_addr_rd, "pd6diff bug or bad golden trace");
-
-                        if g_data_rd != t_data_rd {
-                            print_error("[W] RD data does not match!");
-                        }
-                    }
-                }
-            } else {
-                print_error("[W] Mismatched line types or bad traces! Something is VERY wrong!");
-            }
+    (1..=MAX_RESYNC_DISTANCE).find_map(|distance| {
+        if lined_up(distance, 0) {
+            Some(Misalignment::Dropped(distance))
+        } else if lined_up(0, distance) {
+            Some(Misalignment::Repeated(distance))
+        } else {
+            None
         }
+    })
+}
 
-        total_error_count += chunk_error_count;
-    }
+//Lines of yours where the capture is known to have gone wrong: right after a problem it reported, or
+//where the cycle stamps (if the board prints them) skip or go backwards
+fn capture_trouble(test_wlines: &[(Stamp, ParsedLine)], problem_lines: Vec<usize>) -> Vec<usize> {
+    let stamp_gaps = test_wlines.windows(2).enumerate().filter_map(|(index, pair)| match (pair[0].0.cycle, pair[1].0.cycle) {
+        (Some(cycle), Some(next_cycle)) if next_cycle != cycle + 1  => Some(index + 1),
+        _                                                           => None,
+    });
+    problem_lines.into_iter().chain(stamp_gaps).collect()
+}
 
-    total_error_count
+//Whether the capture went wrong close enough to a misalignment found at your line `test_num` to explain
+//it. A CPU bug can look just like lost or repeated lines (ex. an extra iteration of a short loop), so
+//without that it's reported as a normal mismatch instead. The mismatch may only show up a few lines
+//after the lost ones (ex. if they were for bubbles), hence the slack before it.
+fn explained_by_capture(trouble: &[usize], test_num: usize, misalignment: &Misalignment) -> bool {
+    let last = match misalignment {
+        Misalignment::Dropped(_)        => test_num,
+        Misalignment::Repeated(count)   => test_num + count,
+    };
+    trouble.iter().any(|line| (test_num.saturating_sub(RESYNC_MATCH_LEN)..=last).contains(line))
 }
 
 //Returns the number of errors
-fn compare_sim(golden: ParsedLineIterator, test: ParsedLineIterator) -> u32 {
-    let mut total_error_count   = 0;
-    let mut pipeline            = Pipeline::default();
+fn compare_board(golden_cycles: Vec<Cycle>, board_trace: BoardTrace, options: &CompareOptions) -> u32 {
+    let CompareOptions {rules, symbols, machine, layout, lint} = options;
+    let mut totals              = ReportTotals::default();
+    let mut tracker             = PipelineTracker::new(layout, machine);
+    let mut reference           = ReferenceLog::new();
 
-    //TODO for better performance, avoid collecting here (too bad array_chunks() is unstable)
-    let lines_vec: Vec<(ParsedLine, ParsedLine)>    = golden.zip(test).collect();
-    let line_chunks: Vec<_>                         = lines_vec.chunks(6).collect();//[F], [D], [R], [E], [M], [W]
-    let line_chunks_windowed                        = line_chunks.windows(2);
+    //golden has [F], [D], [R], [E], [M], [W]
+    //test only has [W] when in board mode
 
-    let mut squash_fetch_and_decode_next_cycle = false;
+    //Only the PCs are used to tell whether the two traces are still lined up
+    let golden_wpcs: Vec<_> = golden_cycles.iter().map(|cycle| wline_pc(cycle.line(Stage::W))).collect();
+    let test_wlines         = board_trace.lines;
+    let test_wpcs: Vec<_>   = test_wlines.iter().map(|(_, line)| wline_pc(Some(*line))).collect();
+    let trouble             = capture_trouble(&test_wlines, board_trace.problem_lines);
+
+    //Your trace doesn't always have exactly one line per golden cycle, if lines were lost or repeated on the way from the board
+    let mut test_index          = 0;
+    let mut skipped_cycles      = 0;//Golden cycles left whose lines are missing from your trace
+
+    let mut linter = lint.then(Linter::new);
+
+    //Some pipelines need to look a few cycles ahead (ex. for imemory latency)
+    for (window_num, golden_window) in golden_cycles.windows(layout.lookahead() + 1).enumerate() {
+        if test_index >= test_wlines.len() {
+            break;
+        }
 
-    for (window_num, chunk_window) in line_chunks_windowed.enumerate() {
         //Convenient aliases
-        let window_num = window_num + 1;//Since enumerate() is zero-indexed
-        let (g_fline, t_fline)  = chunk_window[0][0];
-        let (g_dline, t_dline)  = chunk_window[0][1];
-        let (g_rline, t_rline)  = chunk_window[0][2];
-        let (g_eline, t_eline)  = chunk_window[0][3];
-        let (g_mline, t_mline)  = chunk_window[0][4];
-        let (g_wline, t_wline)  = chunk_window[0][5];
-
-        let (g_fline_next, t_fline_next)    = chunk_window[1][0];
-        let (g_dline_next, _t_dline_next)   = chunk_window[1][1];
-        let (g_rline_next, t_rline_next)    = chunk_window[1][2];
-        let (g_eline_next, _t_eline_next)   = chunk_window[1][3];
-        let (_g_mline_next, _t_mline_next)  = chunk_window[1][4];
-        let (_g_wline_next, _t_wline_next)  = chunk_window[1][5];
+        let window_num  = window_num + 1;//Since enumerate() is zero-indexed
+        let g_cycle     = &golden_window[0];
+        let g_fline     = g_cycle.line(Stage::F);
+        let g_wline     = g_cycle.line(Stage::W);
+
+        warn_about_golden_cycle(window_num, g_cycle, machine);
 
         //////////////////////////////////////////////////////////////////////////////////////////////////////
         //Pipeline updating logic
         //////////////////////////////////////////////////////////////////////////////////////////////////////
-        if squash_fetch_and_decode_next_cycle {
-            pipeline.f = StageState::default();
-            pipeline.d = StageState::default();
-            squash_fetch_and_decode_next_cycle = false;
-        }
-
-        //Check if fetch and decode stalled, in which case we shouldn't touch fetch or decode and should squash execute
-        let fetch_and_decode_stalled = if let (ParsedLine::D{pc: g_d_pc, ..}, ParsedLine::E{pc: g_e_pc, ..}) = (g_dline, g_eline) {
-            //Don't check if E is a bubble because it could be we're stalling multiple cycles
-            //We do need to check D though because if the PCs just happen to match but were squashed we're not actually stalling
-            if !pipeline.d.is_bubble() {
-                g_d_pc == g_e_pc
-            } else {
-                false
-            }
-        } else {
-            println!("\x1b[1;31mWeirdness in golden trace, are your arguments to pd6diff correct?\x1b[0m");
-            false
-        };
+        let update = tracker.update(golden_window);
 
-        //Similar check for if the stall is happening next cycle
-        let fetch_and_decode_stalled_next_cycle = if let (ParsedLine::D{pc: g_d_pc_next, ..}, ParsedLine::E{pc: g_e_pc_next, ..}) = (g_dline_next, g_eline_next) {
-            if !pipeline.f.is_bubble() {//Look at if F is a bubble because we care if decode is a bubble NEXT cycle
-                g_d_pc_next == g_e_pc_next
-            } else {
-                false
-            }
-        } else {
+        if update.weird {
             println!("\x1b[1;31mWeirdness in golden trace, are your arguments to pd6diff correct?\x1b[0m");
-            false
-        };
-
-        if !fetch_and_decode_stalled_next_cycle {
-            if let Err(InstrNotPresentReason::StallSoInstrWordNotAvailable) = pipeline.f.instr {
-                //No longer stalled, need to populate the instruction now that we should have it
-                //(in the next cycle we have it that is, which is within lookahead range)
-                if let ParsedLine::F{instr: g_instr_next, ..} = g_fline_next {
-                    pipeline.f.instr = Ok(g_instr_next.into());
-                } else {
-                    println!("\x1b[1;31mWeirdness in golden trace, are your arguments to pd6diff correct?\x1b[0m");
-                }
-            }
         }
 
-        if fetch_and_decode_stalled {
-            pipeline.dumb_advance_with_stalled_fetch_and_decode();
-        } else if let (ParsedLine::F{pc: g_pc, ..}, ParsedLine::F{instr: g_instr_next, ..}) = (g_fline, g_fline_next) {
-            //Need to look at the fetch stage a cycle in the future to get the instruction word
-            //because in PD6, imemory has one cycle of additional latency.
-
-            if g_pc == 0 {
-                println!("PC in golden trace became 00000000, assuming we've reached the end!");
-                println!("\x1b[90m(This is expected for simple-programs golden traces, since if you");
-                println!("look at their assembly, when they return from main, since `ra` is initialized");
-                println!("to 0 by our hardware, but never by their code, the PC naturally becomes 0.");
-                println!("Technically a bug in their test programs, but it's a nice end-of-code flag for us!)\x1b[0m");
-
-                break;
-            }
-
-            if fetch_and_decode_stalled_next_cycle {
-                pipeline.dumb_advance(g_pc, Err(InstrNotPresentReason::StallSoInstrWordNotAvailable));
-            } else {
-                if g_instr_next == 0 {
-                    println!("Encountered illegal instruction in golden trace, assuming we've reached the end!");
-                    println!("\x1b[90m(This is expected for individual-instruction golden traces, since we simply");
-                    println!("implement ecall as a NOP, and since these traces end in an ecall, we thus run");
-                    println!("into the data afterwards in memory, interpreting it as an instruction)\x1b[0m");
-                    break;
-                }
-
-                pipeline.dumb_advance(g_pc, Ok(g_instr_next.into()));
-            }
-        } else {
-            println!("\x1b[1;31mWeirdness in golden trace, are your arguments to pd6diff correct?\x1b[0m");
+        if let Some(end_condition) = &update.end_of_test {
+            totals.end_of_test(end_condition);
+            break;
         }
 
-        //If execute is processing a branch and the branch taken flag is set, or this is an
-        //unconditional jump, squash fetch and decode next cycle
-        if let Ok(instr) = pipeline.e.instr.as_ref() {
-            if instr.is_btype() {
-                if let ParsedLine::E{branch_taken: g_branch_taken, ..} = g_eline {
-                    //It seems that branch taken in their traces for PD5 is now also set for
-                    //unconditional branches? Weird that that wasn't the case for PD4...
-                    squash_fetch_and_decode_next_cycle = g_branch_taken;
-                }
-            } else if instr.is_uncond_jump() {
-                squash_fetch_and_decode_next_cycle = true;
-            }
+        if let (Some(redirect), Some(ParsedLine::F{pc: g_pc, ..})) = (update.fetch_redirect.as_ref(), g_fline) {
+            check_golden_redirect(redirect, g_pc.value());
         }
 
+        let w = tracker.stage_for(Stage::W);
+
         //////////////////////////////////////////////////////////////////////////////////////////////////////
-        //Error handling used by line checking below
+        //Getting back in step with your trace if lines were lost or repeated
         //////////////////////////////////////////////////////////////////////////////////////////////////////
-        let mut chunk_error_count = 0;
-        let mut print_error = |message| {
-            if chunk_error_count == 0 {
-                println!(
-                    "At least one error on clock cycle #{} containing lines {} thru {} (inclusive):",
-                    window_num,
-                    window_num * 6 - 5,
-                    window_num * 6
-                );
-                println!("  \x1b[1;33mGolden\x1b[0m                                      | \x1b[1mYours\x1b[0m");
-                println!("  \x1b[1;33m  {}\x1b[0m                     |   \x1b[1m{}\x1b[0m", g_fline, t_fline);
-                println!("  \x1b[1;33m  {}\x1b[0m |   \x1b[1m{}\x1b[0m", g_dline, t_dline);
-                println!("  \x1b[1;33m  {}\x1b[0m               |   \x1b[1m{}\x1b[0m", g_rline, t_rline);
-                println!("  \x1b[1;33m  {}\x1b[0m                   |   \x1b[1m{}\x1b[0m", g_eline, t_eline);
-                println!("  \x1b[1;33m  {}\x1b[0m        |   \x1b[1m{}\x1b[0m", g_mline, t_mline);
-                println!("  \x1b[1;33m  {}\x1b[0m                |   \x1b[1m{}\x1b[0m", g_wline, t_wline);
-                println!("  \x1b[1;33mGolden Disassembly:");
-                println!("    \x1b[1;33m[F]     is processing {}\x1b[0m", pipeline.f.dis());
-                println!("    \x1b[1;33m[D]/[R] is processing {}\x1b[0m", pipeline.d.dis());
-                println!("    \x1b[1;33m[E]     is processing {}\x1b[0m", pipeline.e.dis());
-                println!("    \x1b[1;33m[M]     is processing {}\x1b[0m", pipeline.m.dis());
-                println!("    \x1b[1;33m[W]     is processing {}\x1b[0m", pipeline.w.dis());
-                println!("  \x1b[1;31mError(s):\x1b[0m");
+        let mut capture_problem = None;
+        if (skipped_cycles == 0) && w.instr.as_ref().is_ok_and(|instr| rules.must_match(instr, Field::WPc)) {
+            let (golden_num, test_num) = (window_num - 1, test_index);//Zero-indexed
+            match find_misalignment(&golden_wpcs[golden_num..], &test_wpcs[test_num..]).filter(|misalignment| explained_by_capture(&trouble, test_num, misalignment)) {
+                Some(Misalignment::Dropped(count)) => {
+                    skipped_cycles = count;
+                    capture_problem = Some(format!(
+                        "Your trace is missing the [W] line(s) for golden clock cycle(s) #{} thru #{} (your line {} lines up with cycle #{}), so they weren't checked",
+                        window_num, window_num + count - 1, test_num + 1, window_num + count
+                    ));
+                },
+                Some(Misalignment::Repeated(count)) => {
+                    test_index += count;
+                    capture_problem = Some(format!(
+                        "Lines {} thru {} of your trace don't line up with the golden trace (repeated or garbled on the way from the board?), so they were skipped",
+                        test_num + 1, test_num + count
+                    ));
+                },
+                None => {},
             }
-            chunk_error_count += 1;
-            println!("    \x1b[1;31mError {}: {}\x1b[0m", chunk_error_count, message);
-        };
+        }
+        let (t_stamp, t_wline) = test_wlines[test_index];
+
+        let checking = skipped_cycles == 0;
+        skipped_cycles = skipped_cycles.saturating_sub(1);
 
         //////////////////////////////////////////////////////////////////////////////////////////////////////
-        //[F] Line Checking
+        //Error handling used by line checking below
         //////////////////////////////////////////////////////////////////////////////////////////////////////
-        if let (
-            ParsedLine::F{pc: g_pc, ..},
-            ParsedLine::F{pc: t_pc, ..},
-            ParsedLine::F{instr: g_instr_next, ..},
-            ParsedLine::F{instr: t_instr_next, ..}
-        ) = (g_fline, t_fline, g_fline_next, t_fline_next) {
-            if g_pc != t_pc {
-                print_error("[F] PCs do not match (this is probably the start of your problem right here)!");
-            }
-            assert_eq!(g_pc, pipeline.f.pc, "pd6diff bug or bad golden trace");
+        let lines           = ReportLines::Board{golden: g_cycle, yours: t_wline, line_num: test_index + 1, stamp: t_stamp};
+        let mut report      = CycleReport::new(window_num, lines, &tracker, layout, symbols, &mut totals);
+        let mut print_error = |class: ErrorClass, message: &str| report.print(class, message);
 
-            if !squash_fetch_and_decode_next_cycle {
-                if g_instr_next != t_instr_next {
-                    print_error("[F] Fetched instructions do not match (on the next cycle)!");
-                }
-            }
-        } else {
-            print_error("[F] Mismatched line types or bad traces! Something is VERY wrong!");
+        if let Some(message) = capture_problem {
+            print_error(ErrorClass::Capture, &message);
         }
 
         //////////////////////////////////////////////////////////////////////////////////////////////////////
-        //[D] and [R] Line Checking
+        //[W] Line Checking
         //////////////////////////////////////////////////////////////////////////////////////////////////////
-        if !pipeline.d.is_bubble() {
-            let instr = pipeline.d.instr.as_ref().unwrap();
-
+        if let (true, Ok(instr)) = (checking, w.instr.as_ref()) {
             if let (
-                ParsedLine::D{pc: g_pc, opcode: g_opcode, rd: g_rd, rs1: g_rs1, rs2: g_rs2, funct3: g_funct3, funct7: g_funct7, imm: g_imm, shamt: g_shamt},
-                ParsedLine::D{pc: t_pc, opcode: t_opcode, rd: t_rd, rs1: t_rs1, rs2: t_rs2, funct3: t_funct3, funct7: t_funct7, imm: t_imm, shamt: t_shamt}
-            ) = (g_dline, t_dline) {
-                if g_pc != t_pc {
-                    print_error("[D] PCs do not match!");
-                }
-                assert_eq!(g_pc, pipeline.d.pc, "pd6diff bug or bad golden trace");
-
-                if !instr.is_fence() {
-                    if g_opcode != t_opcode {
-                        print_error("[D] Opcodes do not match!");
-                    }
-                }
-
-                //We sometimes don't do comparisons if they are don't cares
-
-                if let Some(jzj_rd) = instr.get_rd() {
-                    if g_rd != t_rd {
-                        print_error("[D] RDs do not match!");
-                    }
-                    assert_eq!(g_rd, jzj_rd, "pd6diff bug or bad golden trace");
-                }
-
-                if let Some(jzj_rs1) = instr.get_rs1() {
-                    if g_rs1 != t_rs1 {
-                        print_error("[D] RS1s do not match!");
-                    }
-                    assert_eq!(g_rs1, jzj_rs1, "pd6diff bug or bad golden trace");
-                }
-
-                if let Some(jzj_rs2) = instr.get_rs2() {
-                    if g_rs2 != t_rs2 {
-                        print_error("[D] RS2s do not match!");
-                    }
-                    assert_eq!(g_rs2, jzj_rs2, "pd6diff bug or bad golden trace");
+                Some(ParsedLine::W{pc: g_pc, we: g_we, addr_rd: g_addr_rd, data_rd: g_data_rd}),
+                ParsedLine::W{pc: t_pc, we: t_we, addr_rd: t_addr_rd, data_rd: t_data_rd}
+            ) = (g_wline, t_wline) {
+                if rules.must_match(instr, Field::WPc) {
+                    check_field!(print_error, g_pc, t_pc, "[W] PCs do not match!");
                 }
-
-                if let Some(jzj_funct3) = instr.get_funct3() {
-                    if g_funct3 != t_funct3 {
-                        print_error("[D] Funct3s do not match!");
-                    }
-                    assert_eq!(g_funct3, jzj_funct3, "pd6diff bug or bad golden trace");
+                if let Some(message) = reference.check_pc(Stage::W, g_pc, w.pc) {
+                    print_error(ErrorClass::Reference, &message);
                 }
 
-                if let Some(jzj_funct7) = instr.get_funct7() {
-                    if g_funct7 != t_funct7 {
-                        print_error("[D] Funct7s do not match!");
-                    }
-                    assert_eq!(g_funct7, jzj_funct7, "pd6diff bug or bad golden trace");
+                if rules.must_match(instr, Field::WWe) {
+                    check_field!(print_error, g_we, t_we, "[W] Write enable line does not match!");
                 }
 
-                if let Some(jzj_imm) = instr.get_imm() {
-                    if g_imm != t_imm {
-                        print_error("[D] IMMs do not match!");
-                    }
-                    assert_eq!(g_imm, jzj_imm as u32, "pd6diff bug or bad golden trace");
+                if rules.must_match(instr, Field::WAddrRd) {
+                    check_field!(print_error, g_addr_rd, t_addr_rd, "[W] RD addresses do not match!");
                 }
-
-                if let Some(jzj_shamt) = instr.get_shamt() {
-                    if g_shamt != t_shamt {
-                        print_error("[D] SHAMTs do not match!");
-                    }
-                    assert_eq!(g_shamt, jzj_shamt, "pd6diff bug or bad golden trace");
-                }
-            } else {
-                print_error("[D] Mismatched line types or bad traces! Something is VERY wrong!");
-            }
-
-            if let (
-                ParsedLine::R{addr_rs1: g_addr_rs1, addr_rs2: g_addr_rs2, ..},
-                ParsedLine::R{addr_rs1: t_addr_rs1, addr_rs2: t_addr_rs2, ..},
-                ParsedLine::R{data_rs1: g_data_rs1_next, data_rs2: g_data_rs2_next, ..},
-                ParsedLine::R{data_rs1: t_data_rs1_next, data_rs2: t_data_rs2_next, ..}
-            ) = (g_rline, t_rline, g_rline_next, t_rline_next) {
-                if let Some(jzj_rs1) = instr.get_rs1() {
-                    if g_addr_rs1 != t_addr_rs1 {
-                        print_error("[R] RS1 addresses do not match!");
-                    }
-                    assert_eq!(g_addr_rs1, jzj_rs1, "pd6diff bug or bad golden trace");
-
-                    if g_data_rs1_next != t_data_rs1_next {
-                        print_error("[R] RS1 data does not match (on the next cycle)!");
-                    }
+                if let Some(message) = instr.get_rd().and_then(|jzj_addr_rd| reference.check_decode(instr, Field::WAddrRd, g_addr_rd.widen(), jzj_addr_rd as u32)) {
+                    print_error(ErrorClass::Reference, &message);
                 }
 
-                if let Some(jzj_rs2) = instr.get_rs2() {
-                    if g_addr_rs2 != t_addr_rs2 {
-                        print_error("[R] RS2 addresses do not match!");
-                    }
-                    assert_eq!(g_addr_rs2, jzj_rs2, "pd6diff bug or bad golden trace");
-
-                    if g_data_rs2_next != t_data_rs2_next {
-                        print_error("[R] RS2 data does not match! (on the next cycle)");
-                    }
+                if rules.must_match(instr, Field::WDataRd) {
+                    let message = match w.csr_read {
+                        Some((csr, old_value)) if g_data_rd == old_value => format!("[W] RD data does not match! (expected the old value of {}, {:08x})", csr.name(), old_value),
+                        _ => format!("[W] RD data does not match!{}", hints::load_data_hint(instr.assume_uncompressed(), w.mem_addr, g_data_rd, t_data_rd)),
+                    };
+                    check_field!(print_error, g_data_rd, t_data_rd, &message);
                 }
-            } else {
-                print_error("[R] Mismatched line types or bad traces! Something is VERY wrong!");
+            } else if g_wline.is_some() {
+                print_error(ErrorClass::Structure, &format!("Expected a [W] line, got \"{}\"", t_wline));
             }
         }
 
         //////////////////////////////////////////////////////////////////////////////////////////////////////
-        //[E] Line Checking
+        //Linting of your don't care values (optional)
         //////////////////////////////////////////////////////////////////////////////////////////////////////
-        if !pipeline.e.is_bubble() {
-            let instr = pipeline.e.instr.as_ref().unwrap();
-
-            if let (
-                ParsedLine::E{pc: g_pc, alu_result: g_alu_result, branch_taken: g_branch_taken},
-                ParsedLine::E{pc: t_pc, alu_result: t_alu_result, branch_taken: t_branch_taken}
-            ) = (g_eline, t_eline) {
-                if g_pc != t_pc {
-                    print_error("[E] PCs do not match!");
-                }
-                assert_eq!(g_pc, pipeline.e.pc, "pd6diff bug or bad golden trace");
-
-                if !instr.is_fence() && !instr.is_system() {
-                    if g_alu_result != t_alu_result {
-                        print_error("[E] ALU results do not match!");
-                    }
-                }
+        if let (true, Some(linter)) = (checking, linter.as_mut()) {
+            let warnings = linter.lint_line(&t_wline, None, w.instr.as_ref().ok(), rules, false);
 
-                if instr.is_btype() {
-                    if g_branch_taken != t_branch_taken {
-                        print_error("[E] Branch taken line does not match!");
-                    }
+            if !warnings.is_empty() {
+                println!("\x1b[1;33mLint warning(s) on clock cycle #{} (not counted as errors):\x1b[0m", window_num);
+                for warning in warnings {
+                    println!("    \x1b[33mWarning: {}\x1b[0m", warning);
                 }
-            } else {
-                print_error("[E] Mismatched line types or bad traces! Something is VERY wrong!");
             }
         }
 
-        //////////////////////////////////////////////////////////////////////////////////////////////////////
-        //[M] Line Checking
-        //////////////////////////////////////////////////////////////////////////////////////////////////////
-        if !pipeline.m.is_bubble() {
-            let instr = pipeline.m.instr.as_ref().unwrap();
-            if let (
-                ParsedLine::M{pc: g_pc, addr: g_addr, read_not_write: g_read_not_write, access_size: g_access_size, memory_wdata: g_memory_wdata},
-                ParsedLine::M{pc: t_pc, addr: t_addr, read_not_write: t_read_not_write, access_size: t_access_size, memory_wdata: t_memory_wdata}
-            ) = (g_mline, t_mline) {
-                if g_pc != t_pc {
-                    print_error("[M] PCs do not match!");
-                }
-                assert_eq!(g_pc, pipeline.m.pc, "pd6diff bug or bad golden trace");
+        if checking {
+            test_index += 1;
+        }
 
-                if g_read_not_write != t_read_not_write {
-                    print_error("[M] Read-not-write line does not match!");
-                }
+        //End-of-test conditions that happen during a cycle (so that cycle is still checked)
+        if totals.end_after_cycle(window_num, g_cycle, &tracker, machine) {
+            break;
+        }
+    }
 
-                if instr.is_memory() {
-                    if g_addr != t_addr {
-                        print_error("[M] Addresses do not match!");
-                    }
+    totals.summarize(&tracker, machine, Whose::Golden, linter.as_ref(), Some(&reference))
+}
 
-                    if g_access_size != t_access_size {
-                        print_error("[M] Access sizes do not match!");
-                    }
-                }
+//Checks a golden_board trace's [W] lines are the same as the golden_sim trace's, returning the number of differences
+fn compare_golden_board(golden_cycles: &[Cycle], board_lines: Vec<(Stamp, ParsedLine)>) -> u32 {
+    let sim_wlines: Vec<_>      = golden_cycles.iter().enumerate().filter_map(|(cycle_num, cycle)| cycle.line(Stage::W).map(|line| (cycle_num + 1, line))).collect();
+    let board_wlines: Vec<_>    = board_lines.into_iter().map(|(_, line)| line).filter(|line| matches!(line, ParsedLine::W{..})).collect();
 
-                if instr.is_stype() {
-                    if g_memory_wdata != t_memory_wdata {
-                        print_error("[M] Memory write data does not match!");
-                    }
-                }
-            } else {
-                print_error("[M] Mismatched line types or bad traces! Something is VERY wrong!");
-            }
+    let mut difference_count = 0;
+    for (line_num, ((cycle_num, sim_wline), board_wline)) in sim_wlines.iter().zip(board_wlines.iter()).enumerate() {
+        if same_wline(sim_wline, board_wline) {
+            continue;
         }
-
-        //////////////////////////////////////////////////////////////////////////////////////////////////////
-        //[W] Line Checking
-        //////////////////////////////////////////////////////////////////////////////////////////////////////
-        if !pipeline.w.is_bubble() {
-            let instr = pipeline.w.instr.as_ref().unwrap();
-            if let (
-                ParsedLine::W{pc: g_pc, we: g_we, addr_rd: g_addr_rd, data_rd: g_data_rd},
-                ParsedLine::W{pc: t_pc, we: t_we, addr_rd: t_addr_rd, data_rd: t_data_rd}
-            ) = (g_wline, t_wline) {
-                if g_pc != t_pc {
-                    print_error("[W] PCs do not match!");
-                }
-                assert_eq!(g_pc, pipeline.w.pc, "pd6diff bug or bad golden trace");
-
-                if !instr.is_fence() {
-                    if g_we != t_we {
-                        print_error("[W] Write enable line does not match!");
-                    }
-
-                    if let Some(jzj_addr_rd) = instr.get_rd() {
-                        if g_addr_rd != t_addr_rd {
-                            print_error("[W] RD addresses do not match!");
-                        }
-                        assert_eq!(g_addr_rd, jzj_addr_rd, "pd6diff bug or bad golden trace");
-
-                        if g_data_rd != t_data_rd {
-                            print_error("[W] RD data does not match!");
-                        }
-                    }
-                }
-            } else {
-                print_error("[W] Mismatched line types or bad traces! Something is VERY wrong!");
-            }
+        difference_count += 1;
+        if difference_count <= MAX_BOARD_DIFFS_SHOWN {
+            println!("\x1b[1;31m[W] line #{} of the golden board trace doesn't match golden sim cycle #{} ({}):\x1b[0m", line_num + 1, cycle_num, golden_cycles[cycle_num - 1].line_range());
+            println!("  \x1b[1;33mSim:   {}\x1b[0m", sim_wline);
+            println!("  \x1b[1;33mBoard: {}\x1b[0m", board_wline);
         }
+    }
+    if difference_count > MAX_BOARD_DIFFS_SHOWN {
+        println!("\x1b[1;31m...and {} more difference(s) not shown\x1b[0m", difference_count - MAX_BOARD_DIFFS_SHOWN);
+    }
 
-        total_error_count += chunk_error_count;
+    if sim_wlines.len() != board_wlines.len() {
+        println!("\x1b[1;31mThe golden sim trace has {} [W] line(s) but the golden board trace has {}\x1b[0m", sim_wlines.len(), board_wlines.len());
+        difference_count += 1;
     }
 
-    total_error_count
+    if difference_count == 0 {
+        println!("\x1b[1;32mAll {} [W] lines match!\x1b[0m", sim_wlines.len());
+    }
+
+    difference_count as u32
+}
+
+fn same_wline(a: &ParsedLine, b: &ParsedLine) -> bool {
+    match (a, b) {
+        (ParsedLine::W{pc: a_pc, we: a_we, addr_rd: a_addr_rd, data_rd: a_data_rd}, ParsedLine::W{pc: b_pc, we: b_we, addr_rd: b_addr_rd, data_rd: b_data_rd}) => {
+            (a_pc == b_pc) && (a_we == b_we) && (a_addr_rd == b_addr_rd) && (a_data_rd == b_data_rd)
+        },
+        _ => false,
+    }
 }
 
 /* ------------------------------------------------------------------------------------------------
  * Tests
  * --------------------------------------------------------------------------------------------- */
 
-//TODO
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    //A run of instructions one after the other
+    fn wpcs(count: u32) -> Vec<Option<Logic<u32>>> {
+        (0..count).map(|i| Some(Logic::known(0x01000000 + (i * 4)))).collect()
+    }
+
+    #[test]
+    fn lined_up() {
+        assert_eq!(find_misalignment(&wpcs(8), &wpcs(8)), None);
+    }
+
+    #[test]
+    fn dropped_lines() {
+        let golden = wpcs(8);
+        assert_eq!(find_misalignment(&golden, &golden[1..]), Some(Misalignment::Dropped(1)));
+        assert_eq!(find_misalignment(&golden, &golden[3..]), Some(Misalignment::Dropped(3)));
+    }
+
+    #[test]
+    fn repeated_lines() {
+        let golden   = wpcs(8);
+        let mut test = golden.clone();
+        test.insert(0, Some(Logic::known(0x00001234)));//Garbled
+        assert_eq!(find_misalignment(&golden, &test), Some(Misalignment::Repeated(1)));
+
+        let test: Vec<_> = golden[..2].iter().chain(&golden).copied().collect();
+        assert_eq!(find_misalignment(&golden[2..], &test[2..]), Some(Misalignment::Repeated(2)));
+    }
+
+    #[test]
+    fn unknown_or_missing_pc() {
+        let golden = wpcs(8);
+        let mut test = golden[1..].to_vec();
+        test[0] = None;
+        assert_eq!(find_misalignment(&golden, &test), None);
+
+        test[0] = Some(Logic::from_hex("0100000x").unwrap());
+        assert_eq!(find_misalignment(&golden, &test), None);
+    }
+
+    #[test]
+    fn too_far_to_resync() {
+        let golden = wpcs((MAX_RESYNC_DISTANCE + RESYNC_MATCH_LEN + 2) as u32);
+        assert_eq!(find_misalignment(&golden, &golden[(MAX_RESYNC_DISTANCE + 1)..]), None);
+
+        //Or not enough lines left to be sure
+        assert_eq!(find_misalignment(&golden[..RESYNC_MATCH_LEN], &golden[1..RESYNC_MATCH_LEN]), None);
+    }
+
+    #[test]
+    fn no_resync() {
+        let golden = wpcs(8);
+        let test: Vec<_> = golden.iter().map(|pc| pc.map(|pc| Logic::known(pc.value() + 0x100))).collect();
+        assert_eq!(find_misalignment(&golden, &test), None);
+    }
+}
 
 /* ------------------------------------------------------------------------------------------------
  * Benchmarks
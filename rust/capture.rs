@@ -0,0 +1,324 @@
+/*
+ * File:    capture.rs
+ * Brief:   Captures [W] lines from a board's serial output
+ *
+ * Copyright (C) 2024 John Jekel
+ * See the LICENSE file at the root of the project for licensing info.
+ *
+ * In board mode the FPGA prints a [W] line per cycle over the UART. Logging that with a terminal
+ * program tends to lose or mangle lines (boot messages, noise when the board is reset, two lines run
+ * together when a newline is lost), so this reads the raw bytes itself instead: a serial device, or
+ * a pty or plain file when testing locally.
+ *
+ * Lines are framed on \n or \r, and every [W] in a frame starts a new line, so text in front of a
+ * line is thrown away and lines that ran together are split back apart. Each line has to have exactly
+ * the fields a [W] line should (at their full width, so a line cut short doesn't sneak through);
+ * anything else is kept as a problem to report rather than being compared. Lines are timestamped
+ * with when they were received, relative to the start of the capture.
+ *
+ * There's no serial port crate here, so the port's baud rate is set up with stty (or beforehand by
+ * hand). Reading happens on its own thread so the capture can stop after the board goes quiet.
+ *
+*/
+
+/*!
+ * Captures [W] lines from a board's serial output
+*/
+
+/* ------------------------------------------------------------------------------------------------
+ * Submodules
+ * --------------------------------------------------------------------------------------------- */
+
+//TODO (includes "mod ..." and "pub mod ...")
+
+/* ------------------------------------------------------------------------------------------------
+ * Uses
+ * --------------------------------------------------------------------------------------------- */
+
+use crate::{ParsedLine, STAMP_HEADER};
+use crate::logic::{Logic, LogicBits};
+
+use std::fs::File;
+use std::io::{self, Read, Write};
+use std::path::Path;
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::time::{Duration, Instant};
+
+/* ------------------------------------------------------------------------------------------------
+ * Macros
+ * --------------------------------------------------------------------------------------------- */
+
+//TODO (also pub(crate) use the_macro statements here too)
+
+/* ------------------------------------------------------------------------------------------------
+ * Constants
+ * --------------------------------------------------------------------------------------------- */
+
+const W_TAG:            &[u8] = b"[W]";
+const MAX_FRAME_LEN:    usize = 256;//A [W] line is 26 characters, so anything this long without a newline is noise
+const READ_CHUNK_LEN:   usize = 1024;
+
+/* ------------------------------------------------------------------------------------------------
+ * Static Variables
+ * --------------------------------------------------------------------------------------------- */
+
+//TODO
+
+/* ------------------------------------------------------------------------------------------------
+ * Types
+ * --------------------------------------------------------------------------------------------- */
+
+pub struct CaptureSettings {
+    pub idle_timeout:   Duration,//Only counts once the first [W] line has shown up, so there's time to reset the board
+    pub max_lines:      Option<usize>,
+}
+
+#[derive(Copy, Clone, Debug)]
+pub struct CapturedLine {
+    pub received:   Duration,//Since the capture started
+    pub line:       ParsedLine,
+}
+
+//`next_line` is the index in Capture::lines of the first line captured after the problem
+#[derive(Clone, Debug)]
+pub enum CaptureProblem {
+    Garbage{received: Duration, next_line: usize, text: String},//Thrown away, no [W] line here
+    Rejected{received: Duration, next_line: usize, text: String, reason: String},//Looked like a [W] line, but wasn't a valid one
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum CaptureEnd {
+    EndOfStream,
+    Idle(Duration),
+    Enough(usize),
+    ReadError(String),
+}
+
+pub struct Capture {
+    pub lines:      Vec<CapturedLine>,
+    pub problems:   Vec<CaptureProblem>,
+    pub end:        CaptureEnd,
+    pub elapsed:    Duration,
+}
+
+struct LineFramer {
+    partial:    Vec<u8>,
+}
+
+/* ------------------------------------------------------------------------------------------------
+ * Associated Functions and Methods
+ * --------------------------------------------------------------------------------------------- */
+
+impl Capture {
+    //Reads until the stream ends, goes quiet or we have enough lines
+    pub fn run(mut source: impl Read + Send + 'static, settings: &CaptureSettings) -> Capture {
+        let (sender, receiver) = mpsc::channel::<io::Result<Vec<u8>>>();
+        std::thread::spawn(move || {
+            let mut buffer = [0u8; READ_CHUNK_LEN];
+            loop {
+                let message = match source.read(&mut buffer) {
+                    Ok(0)                                               => break,
+                    Ok(len)                                             => Ok(buffer[..len].to_vec()),
+                    Err(e) if e.kind() == io::ErrorKind::Interrupted    => continue,
+                    Err(e)                                              => Err(e),
+                };
+                let failed = message.is_err();
+                if sender.send(message).is_err() || failed {
+                    break;//Either the capture is over or we can't read any more
+                }
+            }
+        });
+
+        let mut capture = Capture {
+            lines:      Vec::new(),
+            problems:   Vec::new(),
+            end:        CaptureEnd::EndOfStream,
+            elapsed:    Duration::ZERO,
+        };
+        let mut framer  = LineFramer { partial: Vec::with_capacity(MAX_FRAME_LEN) };
+        let start       = Instant::now();
+
+        loop {
+            let message = if capture.lines.is_empty() {
+                receiver.recv().map_err(|_| RecvTimeoutError::Disconnected)
+            } else {
+                receiver.recv_timeout(settings.idle_timeout)
+            };
+
+            match message {
+                Ok(Ok(bytes)) => framer.push(&bytes, start.elapsed(), &mut capture),
+                Ok(Err(e)) => {
+                    capture.end = CaptureEnd::ReadError(e.to_string());
+                    break;
+                },
+                Err(RecvTimeoutError::Timeout) => {
+                    capture.end = CaptureEnd::Idle(settings.idle_timeout);
+                    break;
+                },
+                Err(RecvTimeoutError::Disconnected) => {
+                    capture.end = CaptureEnd::EndOfStream;
+                    break;
+                },
+            }
+
+            if let Some(max_lines) = settings.max_lines.filter(|max_lines| capture.lines.len() >= *max_lines) {
+                capture.lines.truncate(max_lines);
+                capture.end = CaptureEnd::Enough(max_lines);
+                break;
+            }
+        }
+
+        if !matches!(capture.end, CaptureEnd::Enough(_)) {
+            framer.finish(start.elapsed(), &mut capture);//The last line may not have had a newline
+        }
+        capture.elapsed = start.elapsed();
+        capture
+    }
+
+    //Saves the lines as a board trace, with when each was received in front (which isn't a simulation
+    //time, so it gets its own stamp column that the comparison ignores)
+    pub fn save(&self, path: impl AsRef<Path>, source: &str) -> io::Result<()> {
+        let mut file = io::BufWriter::new(File::create(path)?);
+        writeln!(file, "# Captured from {} by pd6boarddiff", source)?;
+        writeln!(file, "#! {}: received_us", STAMP_HEADER)?;
+        for captured_line in &self.lines {
+            writeln!(file, "{} {}", captured_line.received.as_micros(), captured_line.line)?;
+        }
+        file.flush()
+    }
+}
+
+impl CaptureProblem {
+    pub const fn next_line(&self) -> usize {
+        match self {
+            CaptureProblem::Garbage{next_line, ..} | CaptureProblem::Rejected{next_line, ..} => *next_line,
+        }
+    }
+}
+
+impl CaptureEnd {
+    pub fn reason(&self) -> String {
+        match self {
+            CaptureEnd::EndOfStream     => String::from("the stream ended"),
+            CaptureEnd::Idle(timeout)   => format!("nothing was received for {:.1}s", timeout.as_secs_f64()),
+            CaptureEnd::Enough(lines)   => format!("{} line(s) were captured, as many as the golden trace has cycles", lines),
+            CaptureEnd::ReadError(e)    => format!("reading failed: {}", e),
+        }
+    }
+}
+
+impl LineFramer {
+    fn push(&mut self, bytes: &[u8], received: Duration, capture: &mut Capture) {
+        for byte in bytes {
+            if (*byte == b'\n') || (*byte == b'\r') {
+                self.finish(received, capture);
+            } else {
+                self.partial.push(*byte);
+                if self.partial.len() >= MAX_FRAME_LEN {
+                    self.finish(received, capture);
+                }
+            }
+        }
+    }
+
+    //Splits the frame so far into [W] lines
+    fn finish(&mut self, received: Duration, capture: &mut Capture) {
+        let frame = std::mem::take(&mut self.partial);
+        let starts: Vec<usize> = (0..frame.len()).filter(|index| frame[*index..].starts_with(W_TAG)).collect();
+
+        let leading = &frame[..starts.first().copied().unwrap_or(frame.len())];
+        if !leading.iter().all(u8::is_ascii_whitespace) {
+            capture.problems.push(CaptureProblem::Garbage{received, next_line: capture.lines.len(), text: escape(leading)});
+        }
+
+        for (start_num, start) in starts.iter().enumerate() {
+            let end     = starts.get(start_num + 1).copied().unwrap_or(frame.len());
+            let bytes   = &frame[*start..end];
+
+            let parsed = if bytes.iter().all(|byte| byte.is_ascii_graphic() || (*byte == b' ') || (*byte == b'\t')) {
+                parse_wline(std::str::from_utf8(bytes).unwrap())
+            } else {
+                Err(String::from("it has bytes that aren't printable text in it"))
+            };
+
+            match parsed {
+                Ok(line)    => capture.lines.push(CapturedLine{received, line}),
+                Err(reason) => capture.problems.push(CaptureProblem::Rejected{received, next_line: capture.lines.len(), text: escape(bytes), reason}),
+            }
+        }
+    }
+}
+
+/* ------------------------------------------------------------------------------------------------
+ * Traits And Default Implementations
+ * --------------------------------------------------------------------------------------------- */
+
+//TODO
+
+/* ------------------------------------------------------------------------------------------------
+ * Trait Implementations
+ * --------------------------------------------------------------------------------------------- */
+
+impl std::fmt::Display for CaptureProblem {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CaptureProblem::Garbage{received, text, ..}         => write!(f, "at {:.3}s: threw away \"{}\"", received.as_secs_f64(), text),
+            CaptureProblem::Rejected{received, text, reason, ..} => write!(f, "at {:.3}s: rejected \"{}\" because {}", received.as_secs_f64(), text, reason),
+        }
+    }
+}
+
+/* ------------------------------------------------------------------------------------------------
+ * Functions
+ * --------------------------------------------------------------------------------------------- */
+
+//With a baud rate, the device is set up as a raw serial port first
+pub fn open_source(path: &str, baud: Option<u32>) -> io::Result<File> {
+    if let Some(baud) = baud {
+        let device_flag = if cfg!(target_os = "macos") { "-f" } else { "-F" };
+        let status = std::process::Command::new("stty").args([device_flag, path, &baud.to_string(), "raw", "-echo"]).status()?;
+        if !status.success() {
+            return Err(io::Error::other(format!("stty couldn't set {} to {} baud", path, baud)));
+        }
+    }
+    File::open(path)
+}
+
+fn parse_wline(text: &str) -> std::result::Result<ParsedLine, String> {
+    let fields: Vec<&str> = text.split_whitespace().skip(1).collect();
+    let [pc, we, addr_rd, data_rd] = fields[..] else {
+        return Err(format!("it has {} field(s) after the [W] instead of 4", fields.len()));
+    };
+
+    Ok(ParsedLine::W {
+        pc:         parse_field(pc, 8, "PC")?,
+        we:         parse_field(we, 1, "write enable")?,
+        addr_rd:    parse_field(addr_rd, 2, "RD address")?,
+        data_rd:    parse_field(data_rd, 8, "RD data")?,
+    })
+}
+
+fn parse_field<T: LogicBits>(token: &str, digits: usize, name: &str) -> std::result::Result<Logic<T>, String> {
+    let digit_count = token.strip_prefix("0x").unwrap_or(token).chars().filter(|c| *c != '_').count();
+    if digit_count != digits {
+        return Err(format!("the {} should be {} digit(s), not \"{}\"", name, digits, token));
+    }
+    Logic::from_hex(token).ok_or_else(|| format!("the {} \"{}\" isn't a hex value", name, token))
+}
+
+//Bytes as text we can print, with anything unprintable escaped
+fn escape(bytes: &[u8]) -> String {
+    bytes.iter().flat_map(|byte| std::ascii::escape_default(*byte)).map(char::from).collect()
+}
+
+/* ------------------------------------------------------------------------------------------------
+ * Tests
+ * --------------------------------------------------------------------------------------------- */
+
+//TODO
+
+/* ------------------------------------------------------------------------------------------------
+ * Benchmarks
+ * --------------------------------------------------------------------------------------------- */
+
+//TODO
@@ -0,0 +1,145 @@
+/*
+ * File:    cli.rs
+ * Brief:   Tiny command line parser shared by the checkers
+ *
+ * Copyright (C) 2024 John Jekel
+ * See the LICENSE file at the root of the project for licensing info.
+ *
+ * Splits the arguments into positional arguments (ex. the trace paths) and
+ * `--option value` / `--flag` style options. Nothing fancy, we just don't want
+ * to drag in a dependency for this.
+ *
+*/
+
+/*!
+ * Tiny command line parser shared by the checkers
+*/
+
+/* ------------------------------------------------------------------------------------------------
+ * Submodules
+ * --------------------------------------------------------------------------------------------- */
+
+//TODO (includes "mod ..." and "pub mod ...")
+
+/* ------------------------------------------------------------------------------------------------
+ * Uses
+ * --------------------------------------------------------------------------------------------- */
+
+//TODO
+
+/* ------------------------------------------------------------------------------------------------
+ * Macros
+ * --------------------------------------------------------------------------------------------- */
+
+//TODO (also pub(crate) use the_macro statements here too)
+
+/* ------------------------------------------------------------------------------------------------
+ * Constants
+ * --------------------------------------------------------------------------------------------- */
+
+//TODO
+
+/* ------------------------------------------------------------------------------------------------
+ * Static Variables
+ * --------------------------------------------------------------------------------------------- */
+
+//TODO
+
+/* ------------------------------------------------------------------------------------------------
+ * Types
+ * --------------------------------------------------------------------------------------------- */
+
+#[derive(Debug, Default)]
+pub struct CommandLine {
+    positional: Vec<String>,
+    values:     Vec<(String, String)>,
+    flags:      Vec<String>,
+}
+
+/* ------------------------------------------------------------------------------------------------
+ * Associated Functions and Methods
+ * --------------------------------------------------------------------------------------------- */
+
+impl CommandLine {
+    //value_options take an argument (`--rules path` or `--rules=path`), flag_options don't (`--strict`)
+    pub fn from_env(value_options: &[&str], flag_options: &[&str]) -> Result<CommandLine, String> {
+        Self::parse(std::env::args().skip(1), value_options, flag_options)
+    }
+
+    pub fn parse(args: impl IntoIterator<Item = String>, value_options: &[&str], flag_options: &[&str]) -> Result<CommandLine, String> {
+        let mut command_line = CommandLine::default();
+        let mut args = args.into_iter();
+
+        while let Some(arg) = args.next() {
+            if !arg.starts_with("--") {
+                command_line.positional.push(arg);
+                continue;
+            }
+
+            let (name, inline_value) = match arg.split_once('=') {
+                Some((name, value)) => (name.to_string(), Some(value.to_string())),
+                None                => (arg.clone(), None),
+            };
+
+            if value_options.contains(&name.as_str()) {
+                let value = match inline_value {
+                    Some(value) => value,
+                    None        => args.next().ok_or(format!("Option {} expects a value", name))?,
+                };
+                command_line.values.push((name, value));
+            } else if flag_options.contains(&name.as_str()) {
+                if inline_value.is_some() {
+                    return Err(format!("Option {} doesn't take a value", name));
+                }
+                command_line.flags.push(name);
+            } else {
+                return Err(format!("Unknown option {}", name));
+            }
+        }
+
+        Ok(command_line)
+    }
+
+    pub fn positional(&self) -> &[String] {
+        &self.positional
+    }
+
+    //If an option is given more than once, the last one wins
+    pub fn value(&self, name: &str) -> Option<&str> {
+        self.values.iter().rev().find(|(n, _)| n == name).map(|(_, value)| value.as_str())
+    }
+
+    pub fn flag(&self, name: &str) -> bool {
+        self.flags.iter().any(|n| n == name)
+    }
+}
+
+/* ------------------------------------------------------------------------------------------------
+ * Traits And Default Implementations
+ * --------------------------------------------------------------------------------------------- */
+
+//TODO
+
+/* ------------------------------------------------------------------------------------------------
+ * Trait Implementations
+ * --------------------------------------------------------------------------------------------- */
+
+//TODO
+
+/* ------------------------------------------------------------------------------------------------
+ * Functions
+ * --------------------------------------------------------------------------------------------- */
+
+//TODO
+
+/* ------------------------------------------------------------------------------------------------
+ * Tests
+ * --------------------------------------------------------------------------------------------- */
+
+//TODO
+
+/* ------------------------------------------------------------------------------------------------
+ * Benchmarks
+ * --------------------------------------------------------------------------------------------- */
+
+//TODO
@@ -0,0 +1,797 @@
+/*
+ * File:    compare.rs
+ * Brief:   The cycle by cycle comparison of a simulation trace against a golden one
+ *
+ * Copyright (C) 2024 John Jekel
+ * See the LICENSE file at the root of the project for licensing info.
+ *
+ * This is the heart of pd6simdiff, pulled out of it so the same comparison can be driven a cycle at
+ * a time by something other than a pair of trace files (the lockstep library a testbench calls into
+ * as it simulates). Give check_cycle() a window of golden cycles and the matching window of yours
+ * each cycle (as long as the pipeline layout's lookahead needs), and it prints the same report
+ * pd6simdiff always has for any errors on the first cycle of the window. finish() prints the summary.
+ *
+ * CompareOptions loads what the comparison needs from the options every checker shares (--rules,
+ * --elf, --machine, --pipeline and so on), and compare_sim() runs a whole pair of traces through a
+ * SimComparer, for pd5diff and pd6simdiff.
+ *
+ * The report itself is shared with the other checkers (board mode, self-check and validate-golden):
+ * a CycleReport prints a cycle's errors under a description of the cycle, and ReportTotals keeps
+ * count across cycles and prints the summary at the end.
+ *
+*/
+
+/*!
+ * The cycle by cycle comparison of a simulation trace against a golden one
+*/
+
+/* ------------------------------------------------------------------------------------------------
+ * Submodules
+ * --------------------------------------------------------------------------------------------- */
+
+//TODO (includes "mod ..." and "pub mod ...")
+
+/* ------------------------------------------------------------------------------------------------
+ * Uses
+ * --------------------------------------------------------------------------------------------- */
+
+use crate::{check_field, ErrorClass, ParsedLine, Stage, Stamp};
+use crate::cli::CommandLine;
+use crate::csr::Redirect;
+use crate::cycle::Cycle;
+use crate::hints;
+use crate::layout::PipelineLayout;
+use crate::lint::Linter;
+use crate::machine::{EndCondition, MachineConfig};
+use crate::muldiv::MulDivOp;
+use crate::reference::ReferenceLog;
+use crate::rules::{Field, RuleTable};
+use crate::symbols::SymbolTable;
+use crate::tracker::PipelineTracker;
+
+/* ------------------------------------------------------------------------------------------------
+ * Macros
+ * --------------------------------------------------------------------------------------------- */
+
+//TODO (also pub(crate) use the_macro statements here too)
+
+/* ------------------------------------------------------------------------------------------------
+ * Constants
+ * --------------------------------------------------------------------------------------------- */
+
+//TODO
+
+/* ------------------------------------------------------------------------------------------------
+ * Static Variables
+ * --------------------------------------------------------------------------------------------- */
+
+//TODO
+
+/* ------------------------------------------------------------------------------------------------
+ * Types
+ * --------------------------------------------------------------------------------------------- */
+
+//Everything about a comparison that comes from the options
+pub struct CompareOptions {
+    pub rules:      RuleTable,
+    pub symbols:    SymbolTable,
+    pub machine:    MachineConfig,
+    pub layout:     PipelineLayout,
+    pub lint:       bool,
+}
+
+//Owns everything it needs, so it can be kept around between calls from a testbench
+pub struct SimComparer {
+    tool:               &'static str,//What the report calls the program doing the checking (ex. "pd6diff")
+    options:            CompareOptions,
+    tracker:            PipelineTracker,//Following the golden trace
+    reference:          ReferenceLog,
+    linter:             Option<Linter>,//Only with --lint
+    previous_t_cycle:   Option<Cycle>,//For the linter
+    cycle_num:          usize,//Of the last cycle checked, starting at 1
+    totals:             ReportTotals,
+}
+
+//Whose lines a cycle's report shows (side by side if there are two)
+pub enum ReportLines<'a> {
+    Both{golden: &'a Cycle, yours: &'a Cycle},
+    Board{golden: &'a Cycle, yours: ParsedLine, line_num: usize, stamp: Stamp},//Only your [W] line, from line `line_num` of your trace
+    Yours(&'a Cycle),//Your trace checked against itself
+    Golden(&'a Cycle),//The golden trace on its own, so what's found are problems with it rather than your errors
+}
+
+//Whose trace the pipeline tracker follows
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Whose {
+    Golden,
+    Yours,
+}
+
+//One cycle's errors, printed under a description of the cycle the first time there is one
+pub struct CycleReport<'a> {
+    cycle_num:      usize,
+    lines:          ReportLines<'a>,
+    tracker:        &'a PipelineTracker,
+    layout:         &'a PipelineLayout,
+    symbols:        &'a SymbolTable,
+    totals:         &'a mut ReportTotals,
+    message_count:  u32,//Including ones that aren't counted as errors
+    error_count:    u32,
+}
+
+//What a whole report found, for the summary at the end
+#[derive(Default)]
+pub struct ReportTotals {
+    pub error_count:                u32,
+    pub unknown_value_error_count:  u32,
+    pub capture_problem_count:      u32,
+    pub reached_end_of_test:        bool,
+}
+
+//What checking one cycle found
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct CycleVerdict {
+    pub error_count:    u32,
+    pub end_of_test:    bool,//Nothing after this cycle should be checked
+}
+
+/* ------------------------------------------------------------------------------------------------
+ * Associated Functions and Methods
+ * --------------------------------------------------------------------------------------------- */
+
+impl CompareOptions {
+    //Loads what --rules, --elf, --objdump, --machine, --end-of-test, --predictor, --pipeline and --lint ask
+    //for, and prints the settings that matter. `default_pipeline` is for when there's no --pipeline (ex. "pd5").
+    pub fn from_command_line(command_line: &CommandLine, default_pipeline: &str) -> Result<CompareOptions, String> {
+        let rules   = RuleTable::from_option(command_line.value("--rules"))?;
+        let symbols = SymbolTable::load(command_line.value("--elf"), command_line.value("--objdump"))?;
+        let machine = MachineConfig::from_options(command_line.value("--machine"), command_line.value("--end-of-test"), command_line.value("--predictor"), &symbols)?;
+        let layout  = PipelineLayout::from_name_or_path(command_line.value("--pipeline").unwrap_or(default_pipeline))?;
+
+        println!("End-of-test policy: \x1b[1;36m{}\x1b[0m", machine.end_of_test_policy());
+        println!("Branch predictor: \x1b[1;36m{}\x1b[0m", machine.predictor);
+        println!("Pipeline: \x1b[1;36m{}\x1b[0m", layout);
+
+        Ok(CompareOptions {rules, symbols, machine, layout, lint: command_line.flag("--lint")})
+    }
+}
+
+impl SimComparer {
+    pub fn new(tool: &'static str, options: CompareOptions) -> SimComparer {
+        SimComparer {
+            tool,
+            tracker:                    PipelineTracker::new(&options.layout, &options.machine),
+            reference:                  ReferenceLog::new(),
+            linter:                     options.lint.then(Linter::new),
+            options,
+            previous_t_cycle:           None,
+            cycle_num:                  0,
+            totals:                     ReportTotals::default(),
+        }
+    }
+
+    //How many cycles each window passed to check_cycle() needs (more than one if the pipeline needs to look
+    //a few cycles ahead, ex. for imemory latency)
+    pub fn window_size(&self) -> usize {
+        self.options.layout.lookahead() + 1
+    }
+
+    //Checks the first cycle of each window, printing a report if there were any errors
+    pub fn check_cycle(&mut self, golden_window: &[Cycle], test_window: &[Cycle]) -> CycleVerdict {
+        //Convenient aliases
+        let CompareOptions {rules, symbols, machine, layout, ..} = &self.options;
+        let (tracker, reference, linter, previous_t_cycle, totals) = (&mut self.tracker, &mut self.reference, &mut self.linter, &mut self.previous_t_cycle, &mut self.totals);
+        self.cycle_num += 1;
+        let cycle_num           = self.cycle_num;
+        let (g_cycle, t_cycle)  = (&golden_window[0], &test_window[0]);
+        let (g_fline, t_fline)  = (g_cycle.line(Stage::F), t_cycle.line(Stage::F));
+        let (g_dline, t_dline)  = (g_cycle.line(Stage::D), t_cycle.line(Stage::D));
+        let (g_rline, t_rline)  = (g_cycle.line(Stage::R), t_cycle.line(Stage::R));
+        let (g_eline, t_eline)  = (g_cycle.line(Stage::E), t_cycle.line(Stage::E));
+        let (g_mline, t_mline)  = (g_cycle.line(Stage::M), t_cycle.line(Stage::M));
+        let (g_wline, t_wline)  = (g_cycle.line(Stage::W), t_cycle.line(Stage::W));
+
+        //Where the instruction word and register data for this cycle show up
+        let (g_fline_word, t_fline_word) = (golden_window[layout.imem_latency].line(Stage::F), test_window[layout.imem_latency].line(Stage::F));
+        let (g_rline_data, t_rline_data) = (golden_window[layout.regfile_latency].line(Stage::R), test_window[layout.regfile_latency].line(Stage::R));
+
+        warn_about_golden_cycle(cycle_num, g_cycle, machine);
+
+        //////////////////////////////////////////////////////////////////////////////////////////////////////
+        //Pipeline updating logic
+        //////////////////////////////////////////////////////////////////////////////////////////////////////
+        let update = tracker.update(golden_window);
+        let tracker = &*tracker;
+
+        if update.weird {
+            println!("\x1b[1;31mWeirdness in golden trace, are your arguments to {} correct?\x1b[0m", self.tool);
+        }
+
+        if let Some(end_condition) = &update.end_of_test {
+            totals.end_of_test(end_condition);
+            return CycleVerdict{error_count: 0, end_of_test: true};
+        }
+
+        if let (Some(redirect), Some(ParsedLine::F{pc: g_pc, ..})) = (update.fetch_redirect.as_ref(), g_fline) {
+            check_golden_redirect(redirect, g_pc.value());
+        }
+
+        let f = tracker.stage_for(Stage::F);
+        let d = tracker.stage_for(Stage::D);
+        let r = tracker.stage_for(Stage::R);
+        let e = tracker.stage_for(Stage::E);
+        let m = tracker.stage_for(Stage::M);
+        let w = tracker.stage_for(Stage::W);
+
+        //////////////////////////////////////////////////////////////////////////////////////////////////////
+        //Error handling used by line checking below
+        //////////////////////////////////////////////////////////////////////////////////////////////////////
+        let mut report = CycleReport::new(cycle_num, ReportLines::Both{golden: g_cycle, yours: t_cycle}, tracker, layout, symbols, totals);
+        let mut print_error = |class: ErrorClass, message: &str| report.print(class, message);
+
+        //////////////////////////////////////////////////////////////////////////////////////////////////////
+        //Missing or repeated lines in your trace
+        //////////////////////////////////////////////////////////////////////////////////////////////////////
+        for problem in &t_cycle.problems {
+            print_error(ErrorClass::Structure, &problem.to_string());
+        }
+
+        //////////////////////////////////////////////////////////////////////////////////////////////////////
+        //[F] Line Checking
+        //////////////////////////////////////////////////////////////////////////////////////////////////////
+        if let (
+            Some(ParsedLine::F{pc: g_pc, ..}),
+            Some(ParsedLine::F{pc: t_pc, ..}),
+            Some(ParsedLine::F{instr: g_instr, ..}),
+            Some(ParsedLine::F{instr: t_instr, ..})
+        ) = (g_fline, t_fline, g_fline_word, t_fline_word) {
+            let f_must_match = |field| f.instr.as_ref().map_or(true, |instr| rules.must_match(instr, field));
+
+            if f_must_match(Field::FPc) {
+                let message = match update.fetch_redirect.as_ref() {
+                    Some(redirect)  => format!("[F] PCs do not match (expected {}, this is probably the start of your problem right here)!", redirect),
+                    None            => String::from("[F] PCs do not match (this is probably the start of your problem right here)!"),
+                };
+                check_field!(print_error, g_pc, t_pc, &message);
+            }
+            if let Some(message) = reference.check_pc(Stage::F, g_pc, f.pc) {
+                print_error(ErrorClass::Reference, &message);
+            }
+
+            //With imemory latency, the word for a fetch that's about to be flushed may never show up
+            let word_will_show_up = (layout.imem_latency == 0) || !tracker.squash_pending();
+            if word_will_show_up && f_must_match(Field::FInstr) {
+                check_field!(print_error, g_instr, t_instr, &format!("[F] Fetched instructions do not match{}!", latency_note(layout.imem_latency)));
+            }
+        }
+
+        //////////////////////////////////////////////////////////////////////////////////////////////////////
+        //[D] Line Checking
+        //////////////////////////////////////////////////////////////////////////////////////////////////////
+        if let Ok(instr) = d.instr.as_ref() {
+            if let (
+                Some(ParsedLine::D{pc: g_pc, opcode: g_opcode, rd: g_rd, rs1: g_rs1, rs2: g_rs2, funct3: g_funct3, funct7: g_funct7, imm: g_imm, shamt: g_shamt}),
+                Some(ParsedLine::D{pc: t_pc, opcode: t_opcode, rd: t_rd, rs1: t_rs1, rs2: t_rs2, funct3: t_funct3, funct7: t_funct7, imm: t_imm, shamt: t_shamt})
+            ) = (g_dline, t_dline) {
+                if rules.must_match(instr, Field::DPc) {
+                    check_field!(print_error, g_pc, t_pc, "[D] PCs do not match!");
+                }
+                if let Some(message) = reference.check_pc(Stage::D, g_pc, d.pc) {
+                    print_error(ErrorClass::Reference, &message);
+                }
+
+                if rules.must_match(instr, Field::DOpcode) {
+                    check_field!(print_error, g_opcode, t_opcode, &format!("[D] Opcodes do not match!{}", hints::decode_hint(instr.assume_uncompressed(), Field::DOpcode, g_opcode.widen(), t_opcode.widen())));
+                }
+
+                //We sometimes don't do comparisons if they are don't cares
+
+                if rules.must_match(instr, Field::DRd) {
+                    check_field!(print_error, g_rd, t_rd, &format!("[D] RDs do not match!{}", hints::decode_hint(instr.assume_uncompressed(), Field::DRd, g_rd.widen(), t_rd.widen())));
+                }
+                if let Some(message) = instr.get_rd().and_then(|jzj_rd| reference.check_decode(instr, Field::DRd, g_rd.widen(), jzj_rd as u32)) {
+                    print_error(ErrorClass::Reference, &message);
+                }
+
+                if rules.must_match(instr, Field::DRs1) {
+                    check_field!(print_error, g_rs1, t_rs1, &format!("[D] RS1s do not match!{}", hints::decode_hint(instr.assume_uncompressed(), Field::DRs1, g_rs1.widen(), t_rs1.widen())));
+                }
+                if let Some(message) = instr.get_rs1().and_then(|jzj_rs1| reference.check_decode(instr, Field::DRs1, g_rs1.widen(), jzj_rs1 as u32)) {
+                    print_error(ErrorClass::Reference, &message);
+                }
+
+                if rules.must_match(instr, Field::DRs2) {
+                    check_field!(print_error, g_rs2, t_rs2, &format!("[D] RS2s do not match!{}", hints::decode_hint(instr.assume_uncompressed(), Field::DRs2, g_rs2.widen(), t_rs2.widen())));
+                }
+                if let Some(message) = instr.get_rs2().and_then(|jzj_rs2| reference.check_decode(instr, Field::DRs2, g_rs2.widen(), jzj_rs2 as u32)) {
+                    print_error(ErrorClass::Reference, &message);
+                }
+
+                if rules.must_match(instr, Field::DFunct3) {
+                    check_field!(print_error, g_funct3, t_funct3, &format!("[D] Funct3s do not match!{}", hints::decode_hint(instr.assume_uncompressed(), Field::DFunct3, g_funct3.widen(), t_funct3.widen())));
+                }
+                if let Some(message) = instr.get_funct3().and_then(|jzj_funct3| reference.check_decode(instr, Field::DFunct3, g_funct3.widen(), jzj_funct3 as u32)) {
+                    print_error(ErrorClass::Reference, &message);
+                }
+
+                if rules.must_match(instr, Field::DFunct7) {
+                    check_field!(print_error, g_funct7, t_funct7, &format!("[D] Funct7s do not match!{}", hints::decode_hint(instr.assume_uncompressed(), Field::DFunct7, g_funct7.widen(), t_funct7.widen())));
+                }
+                if let Some(message) = instr.get_funct7().and_then(|jzj_funct7| reference.check_decode(instr, Field::DFunct7, g_funct7.widen(), jzj_funct7 as u32)) {
+                    print_error(ErrorClass::Reference, &message);
+                }
+
+                if rules.must_match(instr, Field::DImm) {
+                    check_field!(print_error, g_imm, t_imm, &format!("[D] IMMs do not match!{}", hints::decode_hint(instr.assume_uncompressed(), Field::DImm, g_imm.widen(), t_imm.widen())));
+                }
+                if let Some(message) = instr.get_imm().and_then(|jzj_imm| reference.check_decode(instr, Field::DImm, g_imm.widen(), jzj_imm as u32)) {
+                    print_error(ErrorClass::Reference, &message);
+                }
+
+                if rules.must_match(instr, Field::DShamt) {
+                    check_field!(print_error, g_shamt, t_shamt, &format!("[D] SHAMTs do not match!{}", hints::decode_hint(instr.assume_uncompressed(), Field::DShamt, g_shamt.widen(), t_shamt.widen())));
+                }
+                if let Some(message) = instr.get_shamt().and_then(|jzj_shamt| reference.check_decode(instr, Field::DShamt, g_shamt.widen(), jzj_shamt as u32)) {
+                    print_error(ErrorClass::Reference, &message);
+                }
+            }
+        }
+
+        //////////////////////////////////////////////////////////////////////////////////////////////////////
+        //[R] Line Checking
+        //////////////////////////////////////////////////////////////////////////////////////////////////////
+        if let Ok(instr) = r.instr.as_ref() {
+            if let (
+                Some(ParsedLine::R{addr_rs1: g_addr_rs1, addr_rs2: g_addr_rs2, ..}),
+                Some(ParsedLine::R{addr_rs1: t_addr_rs1, addr_rs2: t_addr_rs2, ..}),
+                Some(ParsedLine::R{data_rs1: g_data_rs1, data_rs2: g_data_rs2, ..}),
+                Some(ParsedLine::R{data_rs1: t_data_rs1, data_rs2: t_data_rs2, ..})
+            ) = (g_rline, t_rline, g_rline_data, t_rline_data) {
+                if rules.must_match(instr, Field::RAddrRs1) {
+                    check_field!(print_error, g_addr_rs1, t_addr_rs1, "[R] RS1 addresses do not match!");
+                }
+                if let Some(message) = instr.get_rs1().and_then(|jzj_rs1| reference.check_decode(instr, Field::RAddrRs1, g_addr_rs1.widen(), jzj_rs1 as u32)) {
+                    print_error(ErrorClass::Reference, &message);
+                }
+
+                if rules.must_match(instr, Field::RDataRs1) {
+                    check_field!(print_error, g_data_rs1, t_data_rs1, &format!("[R] RS1 data does not match{}!", latency_note(layout.regfile_latency)));
+                }
+
+                if rules.must_match(instr, Field::RAddrRs2) {
+                    check_field!(print_error, g_addr_rs2, t_addr_rs2, "[R] RS2 addresses do not match!");
+                }
+                if let Some(message) = instr.get_rs2().and_then(|jzj_rs2| reference.check_decode(instr, Field::RAddrRs2, g_addr_rs2.widen(), jzj_rs2 as u32)) {
+                    print_error(ErrorClass::Reference, &message);
+                }
+
+                if rules.must_match(instr, Field::RDataRs2) {
+                    check_field!(print_error, g_data_rs2, t_data_rs2, &format!("[R] RS2 data does not match{}!", latency_note(layout.regfile_latency)));
+                }
+            }
+        }
+
+        //////////////////////////////////////////////////////////////////////////////////////////////////////
+        //[E] Line Checking
+        //////////////////////////////////////////////////////////////////////////////////////////////////////
+        if let Ok(instr) = e.instr.as_ref() {
+            if let (
+                Some(ParsedLine::E{pc: g_pc, alu_result: g_alu_result, branch_taken: g_branch_taken}),
+                Some(ParsedLine::E{pc: t_pc, alu_result: t_alu_result, branch_taken: t_branch_taken})
+            ) = (g_eline, t_eline) {
+                if rules.must_match(instr, Field::EPc) {
+                    check_field!(print_error, g_pc, t_pc, "[E] PCs do not match!");
+                }
+                if let Some(message) = reference.check_pc(Stage::E, g_pc, e.pc) {
+                    print_error(ErrorClass::Reference, &message);
+                }
+
+                if rules.must_match(instr, Field::EAluResult) {
+                    let message = match (MulDivOp::of(instr), e.operands) {
+                        (Some(op), Some((rs1, rs2))) if g_alu_result == op.evaluate(rs1, rs2) => format!("[E] ALU results do not match! (expected {})", op.explain(rs1, rs2)),
+                        (None, operands) => format!("[E] ALU results do not match!{}", hints::alu_hint(instr.assume_uncompressed(), e.pc, operands, g_alu_result, t_alu_result)),
+                        _ => String::from("[E] ALU results do not match!"),
+                    };
+                    check_field!(print_error, g_alu_result, t_alu_result, &message);
+                }
+
+                if rules.must_match(instr, Field::EBranchTaken) {
+                    //Where your CPU fetched the cycle after deciding
+                    let t_next_fetch = match test_window.get(1).and_then(|cycle| cycle.line(Stage::F)) {
+                        Some(ParsedLine::F{pc: t_next_pc, ..}) if t_next_pc.is_known() => Some(t_next_pc.value()),
+                        _ => None,
+                    };
+                    let hint = hints::branch_hint(instr.assume_uncompressed(), e.pc, e.operands, e.regfile_operands, g_branch_taken, t_branch_taken, t_next_fetch);
+                    check_field!(print_error, g_branch_taken, t_branch_taken, &format!("[E] Branch taken line does not match!{}", hint));
+                }
+            }
+        }
+
+        //////////////////////////////////////////////////////////////////////////////////////////////////////
+        //[M] Line Checking
+        //////////////////////////////////////////////////////////////////////////////////////////////////////
+        if let Ok(instr) = m.instr.as_ref() {
+            if let (
+                Some(ParsedLine::M{pc: g_pc, addr: g_addr, read_not_write: g_read_not_write, access_size: g_access_size, memory_wdata: g_memory_wdata}),
+                Some(ParsedLine::M{pc: t_pc, addr: t_addr, read_not_write: t_read_not_write, access_size: t_access_size, memory_wdata: t_memory_wdata})
+            ) = (g_mline, t_mline) {
+                if rules.must_match(instr, Field::MPc) {
+                    check_field!(print_error, g_pc, t_pc, "[M] PCs do not match!");
+                }
+                if let Some(message) = reference.check_pc(Stage::M, g_pc, m.pc) {
+                    print_error(ErrorClass::Reference, &message);
+                }
+
+                if rules.must_match(instr, Field::MReadNotWrite) {
+                    check_field!(print_error, g_read_not_write, t_read_not_write, "[M] Read-not-write line does not match!");
+                }
+
+                if rules.must_match(instr, Field::MAddr) {
+                    let hint = hints::mem_addr_hint(instr.assume_uncompressed(), m.operands, machine, g_addr, t_addr);
+                    check_field!(print_error, g_addr, t_addr, &format!("[M] Addresses do not match!{}", hint));
+                }
+
+                if rules.must_match(instr, Field::MAccessSize) {
+                    let hint = hints::access_size_hint(instr.assume_uncompressed(), g_access_size.widen(), t_access_size.widen());
+                    check_field!(print_error, g_access_size, t_access_size, &format!("[M] Access sizes do not match!{}", hint));
+                }
+
+                if rules.must_match(instr, Field::MMemoryWdata) {
+                    let g_addr = g_addr.is_known().then(|| g_addr.value());
+                    let hint = hints::store_data_hint(instr.assume_uncompressed(), m.operands, g_addr, g_memory_wdata, t_memory_wdata);
+                    check_field!(print_error, g_memory_wdata, t_memory_wdata, &format!("[M] Memory write data does not match!{}", hint));
+                }
+            }
+        }
+
+        //////////////////////////////////////////////////////////////////////////////////////////////////////
+        //[W] Line Checking
+        //////////////////////////////////////////////////////////////////////////////////////////////////////
+        if let Ok(instr) = w.instr.as_ref() {
+            if let (
+                Some(ParsedLine::W{pc: g_pc, we: g_we, addr_rd: g_addr_rd, data_rd: g_data_rd}),
+                Some(ParsedLine::W{pc: t_pc, we: t_we, addr_rd: t_addr_rd, data_rd: t_data_rd})
+            ) = (g_wline, t_wline) {
+                if rules.must_match(instr, Field::WPc) {
+                    check_field!(print_error, g_pc, t_pc, "[W] PCs do not match!");
+                }
+                if let Some(message) = reference.check_pc(Stage::W, g_pc, w.pc) {
+                    print_error(ErrorClass::Reference, &message);
+                }
+
+                if rules.must_match(instr, Field::WWe) {
+                    check_field!(print_error, g_we, t_we, "[W] Write enable line does not match!");
+                }
+
+                if rules.must_match(instr, Field::WAddrRd) {
+                    check_field!(print_error, g_addr_rd, t_addr_rd, "[W] RD addresses do not match!");
+                }
+                if let Some(message) = instr.get_rd().and_then(|jzj_addr_rd| reference.check_decode(instr, Field::WAddrRd, g_addr_rd.widen(), jzj_addr_rd as u32)) {
+                    print_error(ErrorClass::Reference, &message);
+                }
+
+                if rules.must_match(instr, Field::WDataRd) {
+                    let message = match w.csr_read {
+                        Some((csr, old_value)) if g_data_rd == old_value => format!("[W] RD data does not match! (expected the old value of {}, {:08x})", csr.name(), old_value),
+                        _ => format!("[W] RD data does not match!{}", hints::load_data_hint(instr.assume_uncompressed(), w.mem_addr, g_data_rd, t_data_rd)),
+                    };
+                    check_field!(print_error, g_data_rd, t_data_rd, &message);
+                }
+            }
+        }
+
+        //////////////////////////////////////////////////////////////////////////////////////////////////////
+        //Linting of your don't care values (optional)
+        //////////////////////////////////////////////////////////////////////////////////////////////////////
+        if let Some(linter) = linter.as_mut() {
+            //[R] is skipped if its data lags behind decode
+            let linted_tags = Stage::ALL.into_iter().filter(|tag| (*tag != Stage::R) || (layout.regfile_latency == 0));
+
+            let mut warnings = Vec::new();
+            for tag in linted_tags {
+                if let Some(t_line) = t_cycle.line(tag) {
+                    let previous_t_line = previous_t_cycle.as_ref().and_then(|previous_t_cycle| previous_t_cycle.line(tag));
+                    let instr           = tracker.stage_for(tag).instr.as_ref().ok();
+                    warnings.extend(linter.lint_line(&t_line, previous_t_line.as_ref(), instr, rules, update.holds(layout.stage_of(tag))));
+                }
+            }
+
+            if !warnings.is_empty() {
+                println!("\x1b[1;33mLint warning(s) on clock cycle #{} (not counted as errors):\x1b[0m", cycle_num);
+                for warning in warnings {
+                    println!("    \x1b[33mWarning: {}\x1b[0m", warning);
+                }
+            }
+        }
+
+        *previous_t_cycle = Some(t_cycle.clone());
+
+        //End-of-test conditions that happen during a cycle (so that cycle is still checked)
+        let error_count = report.error_count();
+        let end_of_test = totals.end_after_cycle(cycle_num, g_cycle, tracker, machine);
+
+        CycleVerdict {
+            error_count,
+            end_of_test,
+        }
+    }
+
+    //Prints the summary at the end, returning the total number of errors
+    pub fn finish(self) -> u32 {
+        self.totals.summarize(&self.tracker, &self.options.machine, Whose::Golden, self.linter.as_ref(), Some(&self.reference))
+    }
+}
+
+impl<'a> CycleReport<'a> {
+    pub fn new(cycle_num: usize, lines: ReportLines<'a>, tracker: &'a PipelineTracker, layout: &'a PipelineLayout, symbols: &'a SymbolTable, totals: &'a mut ReportTotals) -> CycleReport<'a> {
+        CycleReport {
+            cycle_num,
+            lines,
+            tracker,
+            layout,
+            symbols,
+            totals,
+            message_count:  0,
+            error_count:    0,
+        }
+    }
+
+    //Just the errors, not the other messages
+    pub const fn error_count(&self) -> u32 {
+        self.error_count
+    }
+
+    pub fn print(&mut self, class: ErrorClass, message: &str) {
+        let validating_golden = matches!(self.lines, ReportLines::Golden(_));
+        let noun = if validating_golden || !class.counts_as_error() {"problem"} else {"error"};
+        if self.message_count == 0 {
+            self.print_header(noun);
+        }
+        self.message_count += 1;
+
+        if !class.counts_as_error() {
+            if class == ErrorClass::Capture {
+                self.totals.capture_problem_count += 1;
+            }
+            println!("    \x1b[1;33mNot counted ({}): {}\x1b[0m", class, message);
+            return;
+        }
+
+        self.error_count += 1;
+        self.totals.error_count += 1;
+        let noun = if validating_golden {"Problem"} else {"Error"};
+        if (class == ErrorClass::Mismatch) || (validating_golden && (class != ErrorClass::Structure)) {
+            println!("    \x1b[1;31m{} {}: {}\x1b[0m", noun, self.error_count, message);
+        } else {
+            if (class == ErrorClass::UnknownValue) && !validating_golden {
+                self.totals.unknown_value_error_count += 1;
+            }
+            println!("    \x1b[1;31m{} {} ({}): {}\x1b[0m", noun, self.error_count, class, message);
+        }
+    }
+
+    //`noun` is what the first message is (ex. "error")
+    fn print_header(&self, noun: &str) {
+        let cycle_num = self.cycle_num;
+        match &self.lines {
+            ReportLines::Both{golden, yours} => {
+                if golden.line_range() == yours.line_range() {
+                    println!("At least one {} on clock cycle #{} containing {} (inclusive):", noun, cycle_num, golden.line_range());
+                } else {
+                    println!("At least one {} on clock cycle #{} containing {} of the golden trace and {} of yours (inclusive):", noun, cycle_num, golden.line_range(), yours.line_range());
+                }
+                if !golden.stamp.is_empty() || !yours.stamp.is_empty() {
+                    println!("  \x1b[90mThat's {} in the golden trace and {} in yours\x1b[0m", golden.stamp, yours.stamp);
+                }
+                println!("  \x1b[1;33mGolden\x1b[0m                                      | \x1b[1mYours\x1b[0m");
+                println!("  \x1b[1;33m  {}\x1b[0m                     |   \x1b[1m{}\x1b[0m", golden.display_line(Stage::F), yours.display_line(Stage::F));
+                println!("  \x1b[1;33m  {}\x1b[0m |   \x1b[1m{}\x1b[0m", golden.display_line(Stage::D), yours.display_line(Stage::D));
+                println!("  \x1b[1;33m  {}\x1b[0m               |   \x1b[1m{}\x1b[0m", golden.display_line(Stage::R), yours.display_line(Stage::R));
+                println!("  \x1b[1;33m  {}\x1b[0m                   |   \x1b[1m{}\x1b[0m", golden.display_line(Stage::E), yours.display_line(Stage::E));
+                println!("  \x1b[1;33m  {}\x1b[0m        |   \x1b[1m{}\x1b[0m", golden.display_line(Stage::M), yours.display_line(Stage::M));
+                println!("  \x1b[1;33m  {}\x1b[0m                |   \x1b[1m{}\x1b[0m", golden.display_line(Stage::W), yours.display_line(Stage::W));
+            },
+            ReportLines::Board{golden, yours, line_num, stamp} => {
+                println!("At least one {} on clock cycle #{} containing golden trace {} and line {} of yours (inclusive):", noun, cycle_num, golden.line_range(), line_num);
+                if !golden.stamp.is_empty() || !stamp.is_empty() {
+                    println!("  \x1b[90mThat's {} in the golden trace and {} in yours\x1b[0m", golden.stamp, stamp);
+                }
+                println!("  \x1b[1;33mGolden\x1b[0m                                      | \x1b[1mYours\x1b[0m");
+                println!("  \x1b[1;33m  {}\x1b[0m                     |   \x1b[1m(not available in board trace)\x1b[0m", golden.display_line(Stage::F));
+                println!("  \x1b[1;33m  {}\x1b[0m |   \x1b[1m(not available in board trace)\x1b[0m", golden.display_line(Stage::D));
+                println!("  \x1b[1;33m  {}\x1b[0m               |   \x1b[1m(not available in board trace)\x1b[0m", golden.display_line(Stage::R));
+                println!("  \x1b[1;33m  {}\x1b[0m                   |   \x1b[1m(not available in board trace)\x1b[0m", golden.display_line(Stage::E));
+                println!("  \x1b[1;33m  {}\x1b[0m        |   \x1b[1m(not available in board trace)\x1b[0m", golden.display_line(Stage::M));
+                println!("  \x1b[1;33m  {}\x1b[0m                |   \x1b[1m{}\x1b[0m", golden.display_line(Stage::W), yours);
+            },
+            ReportLines::Yours(yours) => {
+                println!("At least one {} on clock cycle #{} containing {} (inclusive):", noun, cycle_num, yours.line_range());
+                if !yours.stamp.is_empty() {
+                    println!("  \x1b[90mThat's {} in your trace\x1b[0m", yours.stamp);
+                }
+                println!("  \x1b[1mYours\x1b[0m");
+                for tag in Stage::ALL {
+                    println!("  \x1b[1m  {}\x1b[0m", yours.display_line(tag));
+                }
+            },
+            ReportLines::Golden(golden) => {
+                println!("At least one {} on clock cycle #{} containing {} (inclusive):", noun, cycle_num, golden.line_range());
+                if !golden.stamp.is_empty() {
+                    println!("  \x1b[90mThat's {} in the golden trace\x1b[0m", golden.stamp);
+                }
+                println!("  \x1b[1;33mGolden\x1b[0m");
+                for tag in Stage::ALL {
+                    println!("  \x1b[1;33m  {}\x1b[0m", golden.display_line(tag));
+                }
+            },
+        }
+
+        //The disassembly is of whichever trace the tracker is following
+        if let ReportLines::Yours(_) = self.lines {
+            println!("  \x1b[1mDisassembly (going by your trace):");
+            for (stage_num, stage) in self.tracker.stages().iter().enumerate() {
+                println!("    \x1b[1m{:<7} is processing {}\x1b[0m", self.layout.label(stage_num), stage.dis(self.symbols));
+            }
+        } else {
+            println!("  \x1b[1;33mGolden Disassembly:");
+            for (stage_num, stage) in self.tracker.stages().iter().enumerate() {
+                println!("    \x1b[1;33m{:<7} is processing {}\x1b[0m", self.layout.label(stage_num), stage.dis(self.symbols));
+            }
+        }
+        println!("  \x1b[1;31m{}(s):\x1b[0m", if noun == "error" {"Error"} else {"Problem"});
+    }
+}
+
+impl ReportTotals {
+    //For an end-of-test condition the tracker noticed before the cycle was checked
+    pub fn end_of_test(&mut self, end_condition: &EndCondition) {
+        report_end_of_test(end_condition);
+        self.reached_end_of_test = true;
+    }
+
+    //Whether an end-of-test condition was met during a cycle (so that cycle was still checked), reporting
+    //it if so. `cycle` is from the trace the tracker is following.
+    pub fn end_after_cycle(&mut self, cycle_num: usize, cycle: &Cycle, tracker: &PipelineTracker, machine: &MachineConfig) -> bool {
+        let m               = tracker.stage_for(Stage::M);
+        let retired_instr   = tracker.stage_for(Stage::W).instr.as_ref().ok().map(|instr| instr.assume_uncompressed());
+        let store_addr = match (m.instr.as_ref().ok(), cycle.line(Stage::M)) {
+            (Some(instr), Some(ParsedLine::M{addr, ..})) if instr.is_stype() => Some(addr.value()),
+            _ => None,
+        };
+
+        match machine.end_after_cycle(cycle_num, retired_instr, store_addr) {
+            Some(end_condition) => {
+                self.end_of_test(end_condition);
+                true
+            },
+            None => false,
+        }
+    }
+
+    //Prints the summary at the end, returning the total number of errors. `whose` is whose trace the
+    //tracker followed.
+    pub fn summarize(&self, tracker: &PipelineTracker, machine: &MachineConfig, whose: Whose, linter: Option<&Linter>, reference: Option<&ReferenceLog>) -> u32 {
+        if !self.reached_end_of_test {
+            report_end_of_trace(machine);
+        }
+
+        let whose_trace = match whose {
+            Whose::Golden   => "the golden trace",
+            Whose::Yours    => "your trace",
+        };
+        println!("Branch prediction ({}): {} in {}", machine.predictor, tracker.branch_stats(), whose_trace);
+
+        if let Some(linter) = linter {
+            println!("\x1b[1;33mLint found {} suspicious don't-care value(s) in your trace (not counted as errors)\x1b[0m", linter.warning_count());
+        }
+
+        if self.unknown_value_error_count > 0 {
+            let expected = match whose {
+                Whose::Golden   => "the golden trace has a real value",
+                Whose::Yours    => "the rest of it says what they should be",
+            };
+            println!("\x1b[1;31m{} of the errors were X/Z values in your trace where {}\x1b[0m", self.unknown_value_error_count, expected);
+        }
+
+        if self.capture_problem_count > 0 {
+            println!("\x1b[1;33m{} capture problem(s) (lines lost or repeated on the way from the board, not problems with your CPU; not counted as errors)\x1b[0m", self.capture_problem_count);
+        }
+
+        if let Some(suspect) = reference.and_then(ReferenceLog::summary) {
+            println!("\x1b[1;33mReference inconsistencies found: {} (the golden trace disagreeing with the decoder or pipeline model, not problems with your CPU; not counted as errors); {}\x1b[0m", reference.map_or(0, ReferenceLog::count), suspect);
+        }
+
+        self.error_count
+    }
+}
+
+/* ------------------------------------------------------------------------------------------------
+ * Traits And Default Implementations
+ * --------------------------------------------------------------------------------------------- */
+
+//TODO
+
+/* ------------------------------------------------------------------------------------------------
+ * Trait Implementations
+ * --------------------------------------------------------------------------------------------- */
+
+//TODO
+
+/* ------------------------------------------------------------------------------------------------
+ * Functions
+ * --------------------------------------------------------------------------------------------- */
+
+//Checks your whole trace against the golden one, returning the number of errors. `tool` is what the
+//report calls the program doing the checking (ex. "pd5diff").
+pub fn compare_sim(tool: &'static str, golden_cycles: &[Cycle], test_cycles: &[Cycle], options: CompareOptions) -> u32 {
+    let mut comparer = SimComparer::new(tool, options);
+
+    //Some pipelines need to look a few cycles ahead (ex. for imemory latency)
+    let window_size     = comparer.window_size();
+    let cycles_windowed = golden_cycles.windows(window_size).zip(test_cycles.windows(window_size));
+
+    for (golden_window, test_window) in cycles_windowed {
+        if comparer.check_cycle(golden_window, test_window).end_of_test {
+            break;
+        }
+    }
+
+    comparer.finish()
+}
+
+pub fn report_end_of_test(end_condition: &EndCondition) {
+    println!("{}, assuming we've reached the end!", end_condition.reason());
+    if let Some(explanation) = end_condition.explanation() {
+        println!("\x1b[90m{}\x1b[0m", explanation);
+    }
+}
+
+pub fn report_end_of_trace(machine: &MachineConfig) {
+    if machine.end_of_test.contains(&EndCondition::Eof) {
+        report_end_of_test(&EndCondition::Eof);
+    } else {
+        println!("\x1b[1;33mReached the end of the trace without meeting any end-of-test condition ({})\x1b[0m", machine.end_of_test_policy());
+    }
+}
+
+//Warnings about a golden cycle that don't stop it being checked
+pub fn warn_about_golden_cycle(cycle_num: usize, g_cycle: &Cycle, machine: &MachineConfig) {
+    for problem in &g_cycle.problems {
+        println!("\x1b[1;33mWarning: golden trace cycle #{} ({}): {}\x1b[0m", cycle_num, g_cycle.line_range(), problem);
+    }
+
+    if cycle_num == 1 {
+        if let Some(ParsedLine::F{pc: g_pc, ..}) = g_cycle.line(Stage::F) {
+            if g_pc != machine.reset_pc {
+                println!("\x1b[1;33mWarning: golden trace starts at PC {:08x} but the machine config resets to {:08x}, is that the right config?\x1b[0m", g_pc, machine.reset_pc);
+            }
+        }
+    }
+}
+
+pub fn check_golden_redirect(redirect: &Redirect, g_pc: u32) {
+    if redirect.target.is_some_and(|target| target != g_pc) {
+        println!("\x1b[1;33mWarning: expected {} in the golden trace, but it fetched {:08x} instead; is `traps = on` right for this trace?\x1b[0m", redirect, g_pc);
+    }
+}
+
+//ex. " (on the next cycle)", for lines where the golden data shows up late
+fn latency_note(latency: usize) -> String {
+    match latency {
+        0 => String::new(),
+        1 => String::from(" (on the next cycle)"),
+        _ => format!(" ({} cycles later)", latency),
+    }
+}
+
+/* ------------------------------------------------------------------------------------------------
+ * Tests
+ * --------------------------------------------------------------------------------------------- */
+
+//TODO
+
+/* ------------------------------------------------------------------------------------------------
+ * Benchmarks
+ * --------------------------------------------------------------------------------------------- */
+
+//TODO
@@ -0,0 +1,437 @@
+/*
+ * File:    csr.rs
+ * Brief:   Zicsr CSRs and synchronous traps
+ *
+ * Copyright (C) 2024 John Jekel
+ * See the LICENSE file at the root of the project for licensing info.
+ *
+ * For teams that go beyond the course's "ecall is a NOP" core. We model the handful of CSRs a
+ * simple M-mode-only core needs (cycle, instret, mscratch, mtvec, mepc and mcause) and, if the
+ * machine config says `traps = on`, the synchronous traps (ecall, ebreak, illegal instruction,
+ * misaligned fetch/load/store) and mret.
+ *
+ * Like branches, traps and mret are taken when the instruction reaches [E]: the checkers then
+ * expect [F] and [D] to be squashed and fetch to be redirected to mtvec (or mepc for mret) on
+ * the next cycle. The trapping instruction itself still flows down the pipeline, so whatever the
+ * golden trace shows for it in [M] and [W] (ex. we = 0) is what your trace needs to show too.
+ *
+ * The counters depend on exactly when your core reads them, so we can't say what they should be;
+ * the checkers just compare them against the golden trace like any other register write.
+ *
+*/
+
+/*!
+ * Zicsr CSRs and synchronous traps
+*/
+
+/* ------------------------------------------------------------------------------------------------
+ * Submodules
+ * --------------------------------------------------------------------------------------------- */
+
+//TODO (includes "mod ..." and "pub mod ...")
+
+/* ------------------------------------------------------------------------------------------------
+ * Uses
+ * --------------------------------------------------------------------------------------------- */
+
+use crate::machine::MachineConfig;
+use crate::rules::InstrClass;
+
+use riscv_tools::*;
+
+/* ------------------------------------------------------------------------------------------------
+ * Macros
+ * --------------------------------------------------------------------------------------------- */
+
+//TODO (also pub(crate) use the_macro statements here too)
+
+/* ------------------------------------------------------------------------------------------------
+ * Constants
+ * --------------------------------------------------------------------------------------------- */
+
+const OPCODE_SYSTEM:    u32 = 0b1110011;
+
+const ECALL_WORD:       u32 = 0x00000073;
+const EBREAK_WORD:      u32 = 0x00100073;
+const MRET_WORD:        u32 = 0x30200073;
+const WFI_WORD:         u32 = 0x10500073;
+
+/* ------------------------------------------------------------------------------------------------
+ * Static Variables
+ * --------------------------------------------------------------------------------------------- */
+
+//TODO
+
+/* ------------------------------------------------------------------------------------------------
+ * Types
+ * --------------------------------------------------------------------------------------------- */
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Csr {
+    Cycle,
+    Cycleh,
+    Instret,
+    Instreth,
+    Mscratch,
+    Mtvec,
+    Mepc,
+    Mcause,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum CsrOpKind {
+    Write,
+    Set,
+    Clear,
+}
+
+//csrrw, csrrs, csrrc and their immediate versions
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct CsrInstr {
+    kind:       CsrOpKind,
+    immediate:  bool,
+    addr:       u32,
+    rd:         u32,
+    source:     u32,//rs1, or the 5-bit immediate for the immediate versions
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum TrapCause {
+    InstrAddrMisaligned,
+    IllegalInstr,
+    Breakpoint,
+    LoadAddrMisaligned,
+    StoreAddrMisaligned,
+    Ecall,
+}
+
+//Where fetch should go after a trap or mret, and why
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Redirect {
+    pub target: Option<u32>,//None if mtvec/mepc hold something we couldn't work out
+    from_pc:    u32,
+    cause:      Option<TrapCause>,//None for mret
+}
+
+//What we know about the CSRs as of the instruction in [E]
+#[derive(Debug, Clone)]
+pub struct CsrFile {
+    traps:      bool,
+    mscratch:   Option<u32>,
+    mtvec:      Option<u32>,
+    mepc:       Option<u32>,
+    mcause:     Option<u32>,
+}
+
+/* ------------------------------------------------------------------------------------------------
+ * Associated Functions and Methods
+ * --------------------------------------------------------------------------------------------- */
+
+impl Csr {
+    pub const ALL: [Csr; 8] = [
+        Csr::Cycle,
+        Csr::Cycleh,
+        Csr::Instret,
+        Csr::Instreth,
+        Csr::Mscratch,
+        Csr::Mtvec,
+        Csr::Mepc,
+        Csr::Mcause,
+    ];
+
+    pub fn from_addr(addr: u32) -> Option<Csr> {
+        Csr::ALL.into_iter().find(|csr| csr.addr() == addr)
+    }
+
+    pub const fn addr(&self) -> u32 {
+        match self {
+            Csr::Cycle      => 0xc00,
+            Csr::Cycleh     => 0xc80,
+            Csr::Instret    => 0xc02,
+            Csr::Instreth   => 0xc82,
+            Csr::Mscratch   => 0x340,
+            Csr::Mtvec      => 0x305,
+            Csr::Mepc       => 0x341,
+            Csr::Mcause     => 0x342,
+        }
+    }
+
+    pub const fn name(&self) -> &'static str {
+        match self {
+            Csr::Cycle      => "cycle",
+            Csr::Cycleh     => "cycleh",
+            Csr::Instret    => "instret",
+            Csr::Instreth   => "instreth",
+            Csr::Mscratch   => "mscratch",
+            Csr::Mtvec      => "mtvec",
+            Csr::Mepc       => "mepc",
+            Csr::Mcause     => "mcause",
+        }
+    }
+
+    //The top two address bits being set means read-only (the counters, in our case)
+    pub const fn is_read_only(&self) -> bool {
+        (self.addr() >> 10) == 0b11
+    }
+}
+
+impl CsrInstr {
+    pub fn decode(word: u32) -> Option<CsrInstr> {
+        if (word & 0x7f) != OPCODE_SYSTEM {
+            return None;
+        }
+
+        let funct3 = (word >> 12) & 0b111;
+        let kind = match funct3 & 0b11 {
+            0b01 => CsrOpKind::Write,
+            0b10 => CsrOpKind::Set,
+            0b11 => CsrOpKind::Clear,
+            _    => return None,//ecall, ebreak, mret, etc. (or the reserved funct3 100)
+        };
+
+        Some(CsrInstr {
+            kind,
+            immediate:  (funct3 & 0b100) != 0,
+            addr:       word >> 20,
+            rd:         (word >> 7) & 0x1f,
+            source:     (word >> 15) & 0x1f,
+        })
+    }
+
+    pub fn of(instr: &Instruction) -> Option<CsrInstr> {
+        CsrInstr::decode(instr.assume_uncompressed())
+    }
+
+    pub fn csr(&self) -> Option<Csr> {
+        Csr::from_addr(self.addr)
+    }
+
+    pub const fn name(&self) -> &'static str {
+        match (self.kind, self.immediate) {
+            (CsrOpKind::Write, false)   => "csrrw",
+            (CsrOpKind::Set, false)     => "csrrs",
+            (CsrOpKind::Clear, false)   => "csrrc",
+            (CsrOpKind::Write, true)    => "csrrwi",
+            (CsrOpKind::Set, true)      => "csrrsi",
+            (CsrOpKind::Clear, true)    => "csrrci",
+        }
+    }
+
+    //ex. "csrrw x5, mtvec, x6" or "csrrsi x0, 0x7c0, 3"
+    pub fn disassemble(word: u32) -> Option<String> {
+        let instr = CsrInstr::decode(word)?;
+        let csr = instr.csr().map_or(format!("0x{:03x}", instr.addr), |csr| csr.name().to_string());
+        if instr.immediate {
+            Some(format!("{} x{}, {}, {}", instr.name(), instr.rd, csr, instr.source))
+        } else {
+            Some(format!("{} x{}, {}, x{}", instr.name(), instr.rd, csr, instr.source))
+        }
+    }
+
+    //csrrs/csrrc with x0 (or an immediate of 0) only read, which is how you read the counters
+    const fn writes(&self) -> bool {
+        matches!(self.kind, CsrOpKind::Write) || (self.source != 0)
+    }
+
+    pub fn is_illegal(&self) -> bool {
+        match self.csr() {
+            Some(csr)   => csr.is_read_only() && self.writes(),
+            None        => true,
+        }
+    }
+
+    //`rs1_value` is ignored for the immediate versions
+    fn new_value(&self, old_value: u32, rs1_value: u32) -> u32 {
+        let operand = if self.immediate { self.source } else { rs1_value };
+        match self.kind {
+            CsrOpKind::Write    => operand,
+            CsrOpKind::Set      => old_value | operand,
+            CsrOpKind::Clear    => old_value & !operand,
+        }
+    }
+}
+
+impl TrapCause {
+    //What ends up in mcause
+    pub const fn code(&self) -> u32 {
+        match self {
+            TrapCause::InstrAddrMisaligned  => 0,
+            TrapCause::IllegalInstr         => 2,
+            TrapCause::Breakpoint           => 3,
+            TrapCause::LoadAddrMisaligned   => 4,
+            TrapCause::StoreAddrMisaligned  => 6,
+            TrapCause::Ecall                => 11,
+        }
+    }
+
+    pub const fn name(&self) -> &'static str {
+        match self {
+            TrapCause::InstrAddrMisaligned  => "misaligned jump/branch target",
+            TrapCause::IllegalInstr         => "illegal instruction",
+            TrapCause::Breakpoint           => "ebreak",
+            TrapCause::LoadAddrMisaligned   => "misaligned load",
+            TrapCause::StoreAddrMisaligned  => "misaligned store",
+            TrapCause::Ecall                => "ecall",
+        }
+    }
+
+    //Whether the instruction traps, given what the golden [E] stage computed for it. For loads and
+    //stores `alu_result` is the address, and for jumps and taken branches it's the target (None if
+    //it was X/Z in the golden trace, in which case we assume it's fine).
+    pub fn of(word: u32, alu_result: Option<u32>, branch_taken: bool) -> Option<TrapCause> {
+        match word {
+            ECALL_WORD              => return Some(TrapCause::Ecall),
+            EBREAK_WORD             => return Some(TrapCause::Breakpoint),
+            MRET_WORD | WFI_WORD    => return None,
+            _                       => {},
+        }
+
+        let funct3 = (word >> 12) & 0b111;
+        match InstrClass::of(&Instruction::from(word)) {
+            InstrClass::Unknown | InstrClass::System => Some(TrapCause::IllegalInstr),//Anything in SYSTEM besides the above and CSR instructions
+            InstrClass::Csr => CsrInstr::decode(word).filter(|csr_instr| csr_instr.is_illegal()).map(|_| TrapCause::IllegalInstr),
+            InstrClass::Load => {
+                //lh/lhu need halfword alignment, lw needs word alignment
+                let alignment = 1 << (funct3 & 0b11);
+                alu_result.filter(|addr| (addr % alignment) != 0).map(|_| TrapCause::LoadAddrMisaligned)
+            },
+            InstrClass::Store => {
+                let alignment = 1 << (funct3 & 0b11);
+                alu_result.filter(|addr| (addr % alignment) != 0).map(|_| TrapCause::StoreAddrMisaligned)
+            },
+            InstrClass::Jal | InstrClass::Jalr => {
+                //jalr clears the lowest bit of the target itself, so only bit 1 matters
+                alu_result.filter(|target| (target & 0b10) != 0).map(|_| TrapCause::InstrAddrMisaligned)
+            },
+            InstrClass::Branch if branch_taken => {
+                alu_result.filter(|target| (target & 0b10) != 0).map(|_| TrapCause::InstrAddrMisaligned)
+            },
+            _ => None,
+        }
+    }
+}
+
+impl CsrFile {
+    pub fn new(machine: &MachineConfig) -> CsrFile {
+        CsrFile {
+            traps:      machine.traps,
+            mscratch:   Some(0),
+            mtvec:      Some(machine.mtvec),
+            mepc:       Some(0),
+            mcause:     Some(0),
+        }
+    }
+
+    //None for the counters, or if a CSR was written with a value we couldn't work out
+    pub fn read(&self, csr: Csr) -> Option<u32> {
+        match csr {
+            Csr::Cycle | Csr::Cycleh | Csr::Instret | Csr::Instreth => None,
+            Csr::Mscratch   => self.mscratch,
+            Csr::Mtvec      => self.mtvec,
+            Csr::Mepc       => self.mepc,
+            Csr::Mcause     => self.mcause,
+        }
+    }
+
+    fn slot(&mut self, csr: Csr) -> Option<&mut Option<u32>> {
+        match csr {
+            Csr::Cycle | Csr::Cycleh | Csr::Instret | Csr::Instreth => None,
+            Csr::Mscratch   => Some(&mut self.mscratch),
+            Csr::Mtvec      => Some(&mut self.mtvec),
+            Csr::Mepc       => Some(&mut self.mepc),
+            Csr::Mcause     => Some(&mut self.mcause),
+        }
+    }
+
+    //Performs a CSR instruction as it enters [E], returning the CSR and its old value (which is what
+    //should be written to rd in [W]) if we know it. `rs1_value` is what the golden [R] stage read.
+    pub fn access(&mut self, instr: &CsrInstr, rs1_value: Option<u32>) -> Option<(Csr, u32)> {
+        if instr.is_illegal() {
+            return None;//Traps instead
+        }
+        let csr = instr.csr()?;
+        let old_value = self.read(csr);
+
+        if instr.writes() {
+            let rs1_value = if instr.immediate || (instr.source == 0) { Some(0) } else { rs1_value };
+            let new_value = old_value.zip(rs1_value).map(|(old_value, rs1_value)| instr.new_value(old_value, rs1_value));
+            if let Some(slot) = self.slot(csr) {
+                //mepc can't hold a misaligned address
+                *slot = if csr == Csr::Mepc { new_value.map(|value| value & !0b11) } else { new_value };
+            }
+        }
+
+        old_value.map(|old_value| (csr, old_value))
+    }
+
+    //Call once for each instruction as it enters [E]. If traps are enabled and it traps (or is an
+    //mret), updates mepc/mcause and returns where fetch should go next.
+    pub fn redirect(&mut self, pc: u32, word: u32, alu_result: Option<u32>, branch_taken: bool) -> Option<Redirect> {
+        if !self.traps {
+            return None;
+        }
+
+        if word == MRET_WORD {
+            return Some(Redirect {
+                target:     self.mepc,
+                from_pc:    pc,
+                cause:      None,
+            });
+        }
+
+        let cause = TrapCause::of(word, alu_result, branch_taken)?;
+        self.mepc   = Some(pc);
+        self.mcause = Some(cause.code());
+        Some(Redirect {
+            target:     self.mtvec.map(|mtvec| mtvec & !0b11),//Synchronous traps always go to the base, even in vectored mode
+            from_pc:    pc,
+            cause:      Some(cause),
+        })
+    }
+}
+
+/* ------------------------------------------------------------------------------------------------
+ * Traits And Default Implementations
+ * --------------------------------------------------------------------------------------------- */
+
+//TODO
+
+/* ------------------------------------------------------------------------------------------------
+ * Trait Implementations
+ * --------------------------------------------------------------------------------------------- */
+
+//ex. "a trap to mtvec (01000100) because of the ecall @PC 0100001c"
+impl std::fmt::Display for Redirect {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let target = self.target.map_or(String::from("unknown"), |target| format!("{:08x}", target));
+        match self.cause {
+            Some(cause) => write!(f, "a trap to mtvec ({}) because of the {} @PC {:08x}", target, cause.name(), self.from_pc),
+            None        => write!(f, "a return to mepc ({}) because of the mret @PC {:08x}", target, self.from_pc),
+        }
+    }
+}
+
+/* ------------------------------------------------------------------------------------------------
+ * Functions
+ * --------------------------------------------------------------------------------------------- */
+
+//ex. "csrrw x5, mtvec, x6", or "mret", for what riscv_tools doesn't know how to disassemble
+pub fn disassemble(word: u32) -> Option<String> {
+    match word {
+        MRET_WORD   => Some(String::from("mret")),
+        WFI_WORD    => Some(String::from("wfi")),
+        _           => CsrInstr::disassemble(word),
+    }
+}
+
+/* ------------------------------------------------------------------------------------------------
+ * Tests
+ * --------------------------------------------------------------------------------------------- */
+
+//TODO
+
+/* ------------------------------------------------------------------------------------------------
+ * Benchmarks
+ * --------------------------------------------------------------------------------------------- */
+
+//TODO
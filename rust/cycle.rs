@@ -0,0 +1,431 @@
+/*
+ * File:    cycle.rs
+ * Brief:   Groups trace lines into clock cycles by their tags
+ *
+ * Copyright (C) 2024 John Jekel
+ * See the LICENSE file at the root of the project for licensing info.
+ *
+ * Every cycle of a pipeline trace should have one [F], [D], [R], [E], [M] and [W] line, but the
+ * order depends on the order of your $display statements, and a line can go missing (ex. a
+ * $display behind an if) or show up twice. So rather than taking the lines six at a time, the
+ * order tags show up in is learned from the first cycle, and a new cycle starts whenever a tag
+ * comes earlier in that order than the line before it. The lines can be in any order as long as
+ * it's the same every cycle (a tag the first cycle didn't have is slotted in by pipeline order).
+ *
+ * A missing tag or a repeated one is recorded as a problem with the cycle (only the first of a
+ * repeated tag is kept), instead of throwing off every cycle after it.
+ *
+ * If the lines are stamped with a cycle counter or simulation time, each cycle takes the stamp of
+ * its first line, and a cycle counter that skips ahead or goes backwards is recorded as dropped or
+ * repeated cycles. With only times, the clock period is taken to be the smallest gap seen so far.
+ *
+ * Your trace may also start a cycle or two later than the golden one depending on exactly when reset
+ * is deasserted, so find_reset_offset() works out how many cycles to skip at the start of it from the
+ * first few fetch PCs.
+ *
+*/
+
+/*!
+ * Groups trace lines into clock cycles by their tags
+*/
+
+/* ------------------------------------------------------------------------------------------------
+ * Submodules
+ * --------------------------------------------------------------------------------------------- */
+
+//TODO (includes "mod ..." and "pub mod ...")
+
+/* ------------------------------------------------------------------------------------------------
+ * Uses
+ * --------------------------------------------------------------------------------------------- */
+
+use crate::{ParsedLine, Stage, Stamp};
+use crate::logic::Logic;
+
+use std::iter::Peekable;
+
+/* ------------------------------------------------------------------------------------------------
+ * Macros
+ * --------------------------------------------------------------------------------------------- */
+
+//TODO (also pub(crate) use the_macro statements here too)
+
+/* ------------------------------------------------------------------------------------------------
+ * Constants
+ * --------------------------------------------------------------------------------------------- */
+
+pub const RESET_ALIGN_LEN:  usize = 8;//How many fetch PCs in a row have to match
+pub const MAX_RESET_OFFSET: usize = 4;//How many extra cycles at the start of your trace we'll look past
+
+/* ------------------------------------------------------------------------------------------------
+ * Static Variables
+ * --------------------------------------------------------------------------------------------- */
+
+//TODO
+
+/* ------------------------------------------------------------------------------------------------
+ * Types
+ * --------------------------------------------------------------------------------------------- */
+
+#[derive(Clone)]
+pub struct Cycle {
+    lines:          [Option<ParsedLine>; 6],//Indexed like Stage::ALL
+    pub first_line: usize,//Counting trace lines only (not comments or headers), starting at 1
+    pub last_line:  usize,
+    pub stamp:      Stamp,
+    pub problems:   Vec<StructuralProblem>,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum StructuralProblem {
+    Missing(Stage),
+    Duplicate(Stage),
+    DroppedCycles(u64),//How many
+    RepeatedCycle,
+}
+
+//Puts a cycle together a line at a time, for CycleIterator and for cycles that don't come from a trace
+//file (ex. pushed in by a testbench as it simulates)
+pub struct CycleBuilder {
+    cycle:      Cycle,
+    duplicated: [bool; 6],//Indexed like Stage::ALL
+}
+
+pub struct CycleIterator<I: Iterator<Item = (Stamp, ParsedLine)>> {
+    lines:          Peekable<I>,
+    order:          Vec<Stage>,//The order tags show up in within a cycle
+    learned:        bool,//Whether we've seen a whole cycle yet (and so order is complete, barring missing tags)
+    line_num:       usize,
+    previous_stamp: Option<Stamp>,
+    period:         Option<u64>,//Smallest gap between times seen so far
+}
+
+/* ------------------------------------------------------------------------------------------------
+ * Associated Functions and Methods
+ * --------------------------------------------------------------------------------------------- */
+
+impl Cycle {
+    pub const fn line(&self, tag: Stage) -> Option<ParsedLine> {
+        self.lines[tag.index()]
+    }
+
+    //For reports, ex. "[R] (missing)" if there wasn't one
+    pub fn display_line(&self, tag: Stage) -> String {
+        match self.line(tag) {
+            Some(line)  => line.to_string(),
+            None        => format!("{} (missing)", tag.tag()),
+        }
+    }
+
+    //ex. "lines 7 thru 12"
+    pub fn line_range(&self) -> String {
+        format!("lines {} thru {}", self.first_line, self.last_line)
+    }
+}
+
+impl CycleBuilder {
+    //first_line is the number the cycle's first line will have (starting at 1)
+    pub fn new(first_line: usize, stamp: Stamp) -> CycleBuilder {
+        CycleBuilder {
+            cycle: Cycle {
+                lines:      [None; 6],
+                first_line,
+                last_line:  first_line - 1,
+                stamp,
+                problems:   Vec::new(),
+            },
+            duplicated: [false; 6],
+        }
+    }
+
+    //Only the first line with a given tag is kept
+    pub fn push(&mut self, line: ParsedLine) {
+        let index = line.stage().index();
+        if self.cycle.lines[index].is_some() {
+            self.duplicated[index] = true;
+        } else {
+            self.cycle.lines[index] = Some(line);
+        }
+        self.cycle.last_line += 1;
+    }
+
+    //stamp_problem is anything wrong with the cycle's stamp, which only the caller can tell
+    pub fn build(mut self, stamp_problem: Option<StructuralProblem>) -> Cycle {
+        self.cycle.problems.extend(stamp_problem);
+        for tag in Stage::ALL {
+            if self.cycle.lines[tag.index()].is_none() {
+                self.cycle.problems.push(StructuralProblem::Missing(tag));
+            } else if self.duplicated[tag.index()] {
+                self.cycle.problems.push(StructuralProblem::Duplicate(tag));
+            }
+        }
+        self.cycle
+    }
+}
+
+impl<I: Iterator<Item = (Stamp, ParsedLine)>> CycleIterator<I> {
+    pub fn new(lines: I) -> CycleIterator<I> {
+        CycleIterator {
+            lines:          lines.peekable(),
+            order:          Vec::with_capacity(6),
+            learned:        false,
+            line_num:       0,
+            previous_stamp: None,
+            period:         None,
+        }
+    }
+
+    //Whether the cycle with this stamp comes right after the previous one
+    fn check_stamp(&mut self, stamp: Stamp) -> Option<StructuralProblem> {
+        let previous_stamp = self.previous_stamp.replace(stamp)?;
+
+        if let (Some(cycle), Some(previous_cycle)) = (stamp.cycle, previous_stamp.cycle) {
+            return match cycle.cmp(&(previous_cycle + 1)) {
+                std::cmp::Ordering::Less    => Some(StructuralProblem::RepeatedCycle),
+                std::cmp::Ordering::Equal   => None,
+                std::cmp::Ordering::Greater => Some(StructuralProblem::DroppedCycles(cycle - previous_cycle - 1)),
+            };
+        }
+
+        let (time, previous_time) = (stamp.time?, previous_stamp.time?);
+        if time <= previous_time {
+            return Some(StructuralProblem::RepeatedCycle);
+        }
+        let gap     = time - previous_time;
+        let period  = self.period.map_or(gap, |period| period.min(gap));
+        self.period = Some(period);
+        (gap >= period * 2).then_some(StructuralProblem::DroppedCycles(gap / period - 1))
+    }
+
+    //Where the tag goes in the order, learning it if we haven't seen it before
+    fn position_of(&mut self, tag: Stage, previous_tag: Option<Stage>) -> usize {
+        if let Some(position) = self.order.iter().position(|known_tag| *known_tag == tag) {
+            if previous_tag.is_some() {
+                self.learned = true;//Seen something twice, so the first cycle is over
+            }
+            return position;
+        }
+
+        let position = if self.learned {
+            self.order.iter().position(|known_tag| known_tag.index() > tag.index()).unwrap_or(self.order.len())
+        } else {
+            self.order.len()
+        };
+        self.order.insert(position, tag);
+        position
+    }
+}
+
+/* ------------------------------------------------------------------------------------------------
+ * Traits And Default Implementations
+ * --------------------------------------------------------------------------------------------- */
+
+//TODO
+
+/* ------------------------------------------------------------------------------------------------
+ * Trait Implementations
+ * --------------------------------------------------------------------------------------------- */
+
+impl<I: Iterator<Item = (Stamp, ParsedLine)>> Iterator for CycleIterator<I> {
+    type Item = Cycle;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let mut builder         = CycleBuilder::new(self.line_num + 1, Stamp::default());
+        let mut previous_tag    = None;
+
+        while let Some(tag) = self.lines.peek().map(|(_, line)| line.stage()) {
+            let position = self.position_of(tag, previous_tag);
+            if previous_tag.is_some_and(|previous_tag| position < self.position_of(previous_tag, None)) {
+                break;//Wrapped around to the start of the next cycle
+            }
+
+            let (stamp, line) = self.lines.next().unwrap();
+            self.line_num += 1;
+            if previous_tag.is_none() {
+                builder.cycle.stamp = stamp;
+            }
+            builder.push(line);
+            previous_tag = Some(tag);
+        }
+
+        previous_tag?;//No lines left
+
+        let stamp = builder.cycle.stamp;
+        let stamp_problem = if stamp.is_empty() { None } else { self.check_stamp(stamp) };
+        Some(builder.build(stamp_problem))
+    }
+}
+
+impl std::fmt::Display for StructuralProblem {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            StructuralProblem::Missing(tag)     => write!(f, "No {} line on this cycle", tag.tag()),
+            StructuralProblem::Duplicate(tag)   => write!(f, "More than one {} line on this cycle (only the first was used)", tag.tag()),
+            StructuralProblem::DroppedCycles(n) => write!(f, "{} cycle(s) missing right before this one, going by the cycle/time stamps", n),
+            StructuralProblem::RepeatedCycle    => write!(f, "This cycle's stamp isn't after the previous cycle's, so it may be a repeat"),
+        }
+    }
+}
+
+/* ------------------------------------------------------------------------------------------------
+ * Functions
+ * --------------------------------------------------------------------------------------------- */
+
+//How many cycles at the start of test to skip so its first fetch PCs match golden's, or None if no small
+//offset lines them up (ex. your CPU fetches the wrong thing right away)
+pub fn find_reset_offset(golden: &[Cycle], test: &[Cycle]) -> Option<usize> {
+    let align_len   = RESET_ALIGN_LEN.min(golden.len());
+    let golden_pcs  = golden[..align_len].iter().map(fetch_pc).collect::<Option<Vec<_>>>()?;
+    if golden_pcs.is_empty() {
+        return None;
+    }
+
+    (0..=MAX_RESET_OFFSET).find(|offset| {
+        test.get(*offset..(offset + align_len)).is_some_and(|test| test.iter().map(fetch_pc).eq(golden_pcs.iter().copied().map(Some)))
+    })
+}
+
+//How many cycles at the start of your trace to skip so it lines up with the golden one, saying what was decided
+pub fn align_reset(golden_cycles: &[Cycle], test_cycles: &[Cycle], exact_start: bool) -> usize {
+    match (find_reset_offset(golden_cycles, test_cycles), exact_start) {
+        (Some(0), _)            => 0,
+        (Some(offset), false)   => {
+            println!("\x1b[1;33mYour trace has {} extra cycle(s) at the start (going by the first {} fetch PCs), so they're skipped; pass --exact-start if they shouldn't be\x1b[0m", offset, RESET_ALIGN_LEN);
+            offset
+        },
+        (Some(offset), true)    => {
+            println!("\x1b[1;33mYour trace looks like it has {} extra cycle(s) at the start, but --exact-start was passed so they're compared anyway\x1b[0m", offset);
+            0
+        },
+        (None, _)               => {
+            println!("\x1b[90mThe first {} fetch PCs don't line up for any start offset, so comparing from the first cycle of each trace\x1b[0m", RESET_ALIGN_LEN);
+            0
+        },
+    }
+}
+
+fn fetch_pc(cycle: &Cycle) -> Option<Logic<u32>> {
+    match cycle.line(Stage::F) {
+        Some(ParsedLine::F{pc, ..}) if pc.is_known() => Some(pc),
+        _ => None,
+    }
+}
+
+/* ------------------------------------------------------------------------------------------------
+ * Tests
+ * --------------------------------------------------------------------------------------------- */
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const F: &str = "[F] 01000000 00000013";
+    const D: &str = "[D] 01000000 13 00 00 00 0 00 00000000 00";
+    const R: &str = "[R] 00 00 00000000 00000000";
+    const E: &str = "[E] 01000000 00000000 0";
+    const M: &str = "[M] 01000000 00000000 0 2 00000000";
+    const W: &str = "[W] 01000000 0 00 00000000";
+
+    fn cycles(lines: &[&str]) -> Vec<Cycle> {
+        CycleIterator::new(lines.iter().map(|line| (Stamp::default(), ParsedLine::parse(line).unwrap()))).collect()
+    }
+
+    //Each cycle only needs an [F] line for find_reset_offset()
+    fn fetch_cycles(pcs: &[u32]) -> Vec<Cycle> {
+        pcs.iter().map(|pc| {
+            let mut builder = CycleBuilder::new(1, Stamp::default());
+            builder.push(ParsedLine::parse(&format!("[F] {:08x} 00000013", pc)).unwrap());
+            builder.build(None)
+        }).collect()
+    }
+
+    fn line_ranges(cycles: &[Cycle]) -> Vec<(usize, usize)> {
+        cycles.iter().map(|cycle| (cycle.first_line, cycle.last_line)).collect()
+    }
+
+    #[test]
+    fn position_of_learns_the_order() {
+        let mut iterator = CycleIterator::new(std::iter::empty());
+        assert_eq!(iterator.position_of(Stage::F, None), 0);
+        assert_eq!(iterator.position_of(Stage::W, Some(Stage::F)), 1);
+        assert!(!iterator.learned);
+
+        //Seeing a tag again partway through a cycle means the first one is over
+        assert_eq!(iterator.position_of(Stage::F, Some(Stage::W)), 0);
+        assert!(iterator.learned);
+
+        //Once learned, new tags go before the first known tag that comes after them rather than on the end
+        assert_eq!(iterator.position_of(Stage::R, Some(Stage::F)), 1);
+        assert_eq!(iterator.order, [Stage::F, Stage::R, Stage::W]);
+    }
+
+    #[test]
+    fn reordered_cycles() {
+        let cycles = cycles(&[W, M, E, R, D, F, W, M, E, R, D, F]);
+        assert_eq!(line_ranges(&cycles), [(1, 6), (7, 12)]);
+        assert!(cycles.iter().all(|cycle| cycle.problems.is_empty()));
+        assert_eq!(cycles[1].display_line(Stage::F), F);
+    }
+
+    #[test]
+    fn missing_first_tag() {
+        let cycles = cycles(&[F, D, R, E, M, W, D, R, E, M, W, F, D, R, E, M, W]);
+        assert_eq!(line_ranges(&cycles), [(1, 6), (7, 11), (12, 17)]);
+        assert_eq!(cycles[1].problems, [StructuralProblem::Missing(Stage::F)]);
+        assert!(cycles[2].problems.is_empty());
+    }
+
+    #[test]
+    fn missing_last_tag() {
+        let cycles = cycles(&[F, D, R, E, M, W, F, D, R, E, M, F, D, R, E, M, W]);
+        assert_eq!(line_ranges(&cycles), [(1, 6), (7, 11), (12, 17)]);
+        assert_eq!(cycles[1].problems, [StructuralProblem::Missing(Stage::W)]);
+        assert!(cycles[2].problems.is_empty());
+    }
+
+    #[test]
+    fn missing_tag_in_first_cycle() {
+        let cycles = cycles(&[F, D, E, M, W, F, D, R, E, M, W]);
+        assert_eq!(line_ranges(&cycles), [(1, 5), (6, 11)]);
+        assert_eq!(cycles[0].problems, [StructuralProblem::Missing(Stage::R)]);
+        assert!(cycles[1].problems.is_empty());
+    }
+
+    #[test]
+    fn duplicated_tag_in_first_cycle() {
+        let cycles = cycles(&[F, D, D, R, E, M, W, F, D, R, E, M, W]);
+        assert_eq!(line_ranges(&cycles), [(1, 7), (8, 13)]);
+        assert_eq!(cycles[0].problems, [StructuralProblem::Duplicate(Stage::D)]);
+        assert!(cycles[1].problems.is_empty());
+    }
+
+    #[test]
+    fn reset_offset_of_0() {
+        let pcs: Vec<u32> = (0..10).map(|i| 0x01000000 + (i * 4)).collect();
+        assert_eq!(find_reset_offset(&fetch_cycles(&pcs), &fetch_cycles(&pcs)), Some(0));
+    }
+
+    #[test]
+    fn reset_offset_of_1() {
+        let golden: Vec<u32> = (0..10).map(|i| 0x01000000 + (i * 4)).collect();
+        let test: Vec<u32> = std::iter::once(0).chain(golden.iter().copied()).collect();
+        assert_eq!(find_reset_offset(&fetch_cycles(&golden), &fetch_cycles(&test)), Some(1));
+    }
+
+    #[test]
+    fn no_reset_offset() {
+        let golden: Vec<u32> = (0..10).map(|i| 0x01000000 + (i * 4)).collect();
+        let test: Vec<u32> = golden.iter().map(|pc| pc + 0x100).collect();
+        assert_eq!(find_reset_offset(&fetch_cycles(&golden), &fetch_cycles(&test)), None);
+
+        //Too far in to look past
+        let test: Vec<u32> = std::iter::repeat_n(0, MAX_RESET_OFFSET + 1).chain(golden.iter().copied()).collect();
+        assert_eq!(find_reset_offset(&fetch_cycles(&golden), &fetch_cycles(&test)), None);
+    }
+}
+
+/* ------------------------------------------------------------------------------------------------
+ * Benchmarks
+ * --------------------------------------------------------------------------------------------- */
+
+//TODO
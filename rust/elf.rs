@@ -0,0 +1,269 @@
+/*
+ * File:    elf.rs
+ * Brief:   Minimal reader for 32-bit little-endian RISC-V ELF files
+ *
+ * Copyright (C) 2024 John Jekel
+ * See the LICENSE file at the root of the project for licensing info.
+ *
+ * Only what the tools need: the entry point, the loadable segments and the symbol table.
+ * Anything we don't understand (relocations, dynamic linking, debug info) is ignored, which
+ * is fine for the statically-linked bare-metal programs we run on our cores.
+ *
+*/
+
+/*!
+ * Minimal reader for 32-bit little-endian RISC-V ELF files
+*/
+
+/* ------------------------------------------------------------------------------------------------
+ * Submodules
+ * --------------------------------------------------------------------------------------------- */
+
+//TODO (includes "mod ..." and "pub mod ...")
+
+/* ------------------------------------------------------------------------------------------------
+ * Uses
+ * --------------------------------------------------------------------------------------------- */
+
+use std::path::Path;
+
+/* ------------------------------------------------------------------------------------------------
+ * Macros
+ * --------------------------------------------------------------------------------------------- */
+
+//TODO (also pub(crate) use the_macro statements here too)
+
+/* ------------------------------------------------------------------------------------------------
+ * Constants
+ * --------------------------------------------------------------------------------------------- */
+
+const ELF_MAGIC:        [u8; 4] = [0x7f, b'E', b'L', b'F'];
+const ELFCLASS32:       u8  = 1;
+const ELFDATA2LSB:      u8  = 1;
+const EM_RISCV:         u16 = 0xf3;
+
+const PT_LOAD:          u32 = 1;
+const SHT_SYMTAB:       u32 = 2;
+const SHN_UNDEF:        u16 = 0;
+
+const STT_NOTYPE:       u8 = 0;
+const STT_OBJECT:       u8 = 1;
+const STT_FUNC:         u8 = 2;
+
+const FILE_HEADER_SIZE: usize = 0x34;
+const SYMBOL_SIZE:      usize = 16;
+
+/* ------------------------------------------------------------------------------------------------
+ * Static Variables
+ * --------------------------------------------------------------------------------------------- */
+
+//TODO
+
+/* ------------------------------------------------------------------------------------------------
+ * Types
+ * --------------------------------------------------------------------------------------------- */
+
+#[derive(Debug)]
+pub struct Elf {
+    entry:      u32,
+    segments:   Vec<Segment>,
+    symbols:    Vec<Symbol>,
+}
+
+//A PT_LOAD segment. `data` is what's in the file; anything past it up to `mem_size` is zero (ex. .bss)
+#[derive(Debug)]
+pub struct Segment {
+    pub addr:       u32,
+    pub mem_size:   u32,
+    pub data:       Vec<u8>,
+    pub executable: bool,
+}
+
+#[derive(Debug, Clone)]
+pub struct Symbol {
+    pub name:   String,
+    pub addr:   u32,
+    pub size:   u32,//0 if the toolchain didn't say (ex. labels in assembly files)
+    pub kind:   SymbolKind,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum SymbolKind {
+    Function,
+    Object,
+    Label,//STT_NOTYPE, which is what hand-written assembly labels like `_start` usually are
+}
+
+/* ------------------------------------------------------------------------------------------------
+ * Associated Functions and Methods
+ * --------------------------------------------------------------------------------------------- */
+
+impl Elf {
+    pub fn from_path(path: impl AsRef<Path>) -> Result<Elf, String> {
+        let bytes = std::fs::read(path.as_ref()).map_err(|e| e.to_string())?;
+        Elf::from_bytes(&bytes)
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Elf, String> {
+        if bytes.len() < FILE_HEADER_SIZE || bytes[0..4] != ELF_MAGIC {
+            return Err("Not an ELF file".to_string());
+        }
+        if bytes[4] != ELFCLASS32 {
+            return Err("Not a 32-bit ELF file (is this an RV64 binary?)".to_string());
+        }
+        if bytes[5] != ELFDATA2LSB {
+            return Err("Not a little-endian ELF file".to_string());
+        }
+        if read_u16(bytes, 0x12)? != EM_RISCV {
+            return Err("Not a RISC-V ELF file".to_string());
+        }
+
+        let entry       = read_u32(bytes, 0x18)?;
+        let phoff       = read_u32(bytes, 0x1c)? as usize;
+        let shoff       = read_u32(bytes, 0x20)? as usize;
+        let phentsize   = read_u16(bytes, 0x2a)? as usize;
+        let phnum       = read_u16(bytes, 0x2c)? as usize;
+        let shentsize   = read_u16(bytes, 0x2e)? as usize;
+        let shnum       = read_u16(bytes, 0x30)? as usize;
+
+        let mut segments = Vec::new();
+        for ii in 0..phnum {
+            let header = phoff + (ii * phentsize);
+            if read_u32(bytes, header)? != PT_LOAD {
+                continue;
+            }
+
+            let offset      = read_u32(bytes, header + 0x04)? as usize;
+            let paddr       = read_u32(bytes, header + 0x0c)?;
+            let file_size   = read_u32(bytes, header + 0x10)? as usize;
+            let mem_size    = read_u32(bytes, header + 0x14)?;
+            let flags       = read_u32(bytes, header + 0x18)?;
+
+            //Physical address since that's where the loader (ie. our testbench) actually puts it
+            segments.push(Segment {
+                addr:       paddr,
+                mem_size,
+                data:       slice(bytes, offset, file_size)?.to_vec(),
+                executable: (flags & 0x1) != 0,
+            });
+        }
+
+        let mut symbols = Vec::new();
+        for ii in 0..shnum {
+            let header = shoff + (ii * shentsize);
+            if read_u32(bytes, header + 0x04)? != SHT_SYMTAB {
+                continue;
+            }
+
+            let offset  = read_u32(bytes, header + 0x10)? as usize;
+            let size    = read_u32(bytes, header + 0x14)? as usize;
+            let link    = read_u32(bytes, header + 0x18)? as usize;
+
+            //The symbol table's sh_link is the index of its string table
+            let strtab_header   = shoff + (link * shentsize);
+            let strtab_offset   = read_u32(bytes, strtab_header + 0x10)? as usize;
+            let strtab_size     = read_u32(bytes, strtab_header + 0x14)? as usize;
+            let strtab          = slice(bytes, strtab_offset, strtab_size)?;
+
+            for symbol in slice(bytes, offset, size)?.chunks_exact(SYMBOL_SIZE) {
+                let name_offset = read_u32(symbol, 0x0)? as usize;
+                let addr        = read_u32(symbol, 0x4)?;
+                let size        = read_u32(symbol, 0x8)?;
+                let info        = symbol[0xc];
+                let shndx       = read_u16(symbol, 0xe)?;
+
+                if shndx == SHN_UNDEF {
+                    continue;
+                }
+
+                let kind = match info & 0xf {
+                    STT_FUNC    => SymbolKind::Function,
+                    STT_OBJECT  => SymbolKind::Object,
+                    STT_NOTYPE  => SymbolKind::Label,
+                    _           => continue,//Sections, files, TLS, etc.
+                };
+
+                let name = read_str(strtab, name_offset)?;
+                //Skip unnamed symbols and RISC-V mapping symbols ($x, $d) and compiler-local labels
+                if name.is_empty() || name.starts_with('$') || name.starts_with(".L") {
+                    continue;
+                }
+
+                symbols.push(Symbol {
+                    name: name.to_string(),
+                    addr,
+                    size,
+                    kind,
+                });
+            }
+        }
+
+        Ok(Elf {
+            entry,
+            segments,
+            symbols,
+        })
+    }
+
+    pub fn entry(&self) -> u32 {
+        self.entry
+    }
+
+    pub fn segments(&self) -> &[Segment] {
+        &self.segments
+    }
+
+    pub fn symbols(&self) -> &[Symbol] {
+        &self.symbols
+    }
+
+    pub fn symbol(&self, name: &str) -> Option<&Symbol> {
+        self.symbols.iter().find(|symbol| symbol.name == name)
+    }
+}
+
+/* ------------------------------------------------------------------------------------------------
+ * Traits And Default Implementations
+ * --------------------------------------------------------------------------------------------- */
+
+//TODO
+
+/* ------------------------------------------------------------------------------------------------
+ * Trait Implementations
+ * --------------------------------------------------------------------------------------------- */
+
+//TODO
+
+/* ------------------------------------------------------------------------------------------------
+ * Functions
+ * --------------------------------------------------------------------------------------------- */
+
+fn slice(bytes: &[u8], offset: usize, len: usize) -> Result<&[u8], String> {
+    bytes.get(offset..offset.saturating_add(len)).ok_or_else(|| "ELF file is truncated".to_string())
+}
+
+fn read_u16(bytes: &[u8], offset: usize) -> Result<u16, String> {
+    Ok(u16::from_le_bytes(slice(bytes, offset, 2)?.try_into().unwrap()))
+}
+
+fn read_u32(bytes: &[u8], offset: usize) -> Result<u32, String> {
+    Ok(u32::from_le_bytes(slice(bytes, offset, 4)?.try_into().unwrap()))
+}
+
+fn read_str(strtab: &[u8], offset: usize) -> Result<&str, String> {
+    let bytes   = strtab.get(offset..).ok_or_else(|| "Bad symbol name offset".to_string())?;
+    let len     = bytes.iter().position(|byte| *byte == 0).unwrap_or(bytes.len());
+    std::str::from_utf8(&bytes[..len]).map_err(|_| "Symbol name is not valid UTF-8".to_string())
+}
+
+/* ------------------------------------------------------------------------------------------------
+ * Tests
+ * --------------------------------------------------------------------------------------------- */
+
+//TODO
+
+/* ------------------------------------------------------------------------------------------------
+ * Benchmarks
+ * --------------------------------------------------------------------------------------------- */
+
+//TODO
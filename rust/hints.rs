@@ -0,0 +1,727 @@
+/*
+ * File:    hints.rs
+ * Brief:   Guesses at why a field of your trace doesn't match
+ *
+ * Copyright (C) 2024 John Jekel
+ * See the LICENSE file at the root of the project for licensing info.
+ *
+ * "IMMs do not match!" is true but not very helpful. Given the instruction word, a lot of decode bugs
+ * leave fingerprints: an immediate that's right except for the sign extension, or that's exactly what
+ * you'd get by pulling the bits out in the layout of a different format, or a register number that's
+ * really the bits of a neighbouring field. These are only guesses, so they're worded as such, and if
+ * nothing fits we just say which bits differ.
+ *
+ * Likewise for an ALU result, we try the other operations on the other operands the ALU could have
+ * been given (rs1/pc and rs2/imm/4) to find one that gives your result, starting with the ones
+ * closest to what the golden trace did (ex. sub instead of add, or rs2 instead of the imm).
+ *
+ * For a branch that went the wrong way, we redo the comparison with the real operands and check
+ * whether the signed/unsigned version of it, or the same comparison on what the register file held
+ * without forwarding, gives your decision; failing that it's the opposite condition.
+ *
+ * Loads and stores are broken down into byte lanes: store data that wasn't shifted into (or copied
+ * across) the lanes the address selects, load data that wasn't shifted down or was extended the wrong
+ * way, and access sizes or addresses that follow a different convention or another instruction's funct3.
+ *
+ * Without a golden trace to go on, the self-checker asks here what a decode field or ALU result should
+ * be for an instruction word, and what a load leaves in rd given the bytes it read.
+ *
+*/
+
+/*!
+ * Guesses at why a field of your trace doesn't match
+*/
+
+/* ------------------------------------------------------------------------------------------------
+ * Submodules
+ * --------------------------------------------------------------------------------------------- */
+
+//TODO (includes "mod ..." and "pub mod ...")
+
+/* ------------------------------------------------------------------------------------------------
+ * Uses
+ * --------------------------------------------------------------------------------------------- */
+
+use crate::logic::Logic;
+use crate::machine::MachineConfig;
+use crate::predictor;
+use crate::rules::Field;
+
+/* ------------------------------------------------------------------------------------------------
+ * Macros
+ * --------------------------------------------------------------------------------------------- */
+
+//TODO (also pub(crate) use the_macro statements here too)
+
+/* ------------------------------------------------------------------------------------------------
+ * Constants
+ * --------------------------------------------------------------------------------------------- */
+
+//Where each decode field lives in the instruction word, as (lsb, width)
+const FIELD_BITS: [(Field, u32, u32); 7] = [
+    (Field::DOpcode,    0,  7),
+    (Field::DRd,        7,  5),
+    (Field::DFunct3,    12, 3),
+    (Field::DRs1,       15, 5),
+    (Field::DRs2,       20, 5),
+    (Field::DShamt,     20, 5),
+    (Field::DFunct7,    25, 7),
+];
+
+/* ------------------------------------------------------------------------------------------------
+ * Static Variables
+ * --------------------------------------------------------------------------------------------- */
+
+//TODO
+
+/* ------------------------------------------------------------------------------------------------
+ * Types
+ * --------------------------------------------------------------------------------------------- */
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum AluOp {
+    Add,
+    Sub,
+    Sll,
+    Slt,
+    Sltu,
+    Xor,
+    Srl,
+    Sra,
+    Or,
+    And,
+    PassB,//ex. lui
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum AluInput {
+    Rs1,
+    Pc,
+    Rs2,
+    Imm,
+    Four,
+}
+
+//One thing the ALU could have done, ex. rs1 + imm
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+struct AluGuess {
+    op: AluOp,
+    a:  AluInput,
+    b:  AluInput,
+}
+
+//The values the ALU could have been given
+struct AluInputs {
+    rs1:    Option<u32>,
+    rs2:    Option<u32>,
+    pc:     u32,
+    imm:    Option<u32>,
+}
+
+//How an instruction's immediate is laid out
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum Format {
+    I,
+    S,
+    B,
+    U,
+    J,
+}
+
+/* ------------------------------------------------------------------------------------------------
+ * Associated Functions and Methods
+ * --------------------------------------------------------------------------------------------- */
+
+impl Format {
+    const ALL: [Format; 5] = [Format::I, Format::S, Format::B, Format::U, Format::J];
+
+    fn of(word: u32) -> Option<Format> {
+        match word & 0x7f {
+            0b0010011 | 0b0000011 | 0b1100111 | 0b1110011 | 0b0001111   => Some(Format::I),
+            0b0100011                                                   => Some(Format::S),
+            0b1100011                                                   => Some(Format::B),
+            0b0110111 | 0b0010111                                       => Some(Format::U),
+            0b1101111                                                   => Some(Format::J),
+            _                                                           => None,
+        }
+    }
+
+    //Bits of the immediate before sign extension (U-type doesn't get any)
+    const fn width(&self) -> u32 {
+        match self {
+            Format::I | Format::S   => 12,
+            Format::B               => 13,
+            Format::U               => 32,
+            Format::J               => 21,
+        }
+    }
+
+    //The immediate pulled out of the word in this format's layout
+    fn imm(&self, word: u32, sign_extend: bool) -> u32 {
+        let unextended = match self {
+            Format::I => word >> 20,
+            Format::S => ((word >> 25) << 5) | ((word >> 7) & 0x1f),
+            Format::B => ((word >> 31) << 12) | (((word >> 7) & 0x1) << 11) | (((word >> 25) & 0x3f) << 5) | (((word >> 8) & 0xf) << 1),
+            Format::U => word & 0xfffff000,
+            Format::J => ((word >> 31) << 20) | (((word >> 12) & 0xff) << 12) | (((word >> 20) & 0x1) << 11) | (((word >> 21) & 0x3ff) << 1),
+        };
+
+        if sign_extend && (self.width() < 32) {
+            let shift = 32 - self.width();
+            (((unextended << shift) as i32) >> shift) as u32
+        } else {
+            unextended
+        }
+    }
+}
+
+impl AluOp {
+    const ALL: [AluOp; 11] = [AluOp::Add, AluOp::Sub, AluOp::Sll, AluOp::Slt, AluOp::Sltu, AluOp::Xor, AluOp::Srl, AluOp::Sra, AluOp::Or, AluOp::And, AluOp::PassB];
+
+    //What an OP or OP-IMM instruction's funct3 (and funct7 bit 5) asks for
+    fn decode(word: u32, has_imm: bool) -> AluOp {
+        let alternate = ((word >> 30) & 0x1) == 1;
+        match (word >> 12) & 0b111 {
+            0b000 if alternate && !has_imm  => AluOp::Sub,
+            0b000                           => AluOp::Add,
+            0b001                           => AluOp::Sll,
+            0b010                           => AluOp::Slt,
+            0b011                           => AluOp::Sltu,
+            0b100                           => AluOp::Xor,
+            0b101 if alternate              => AluOp::Sra,
+            0b101                           => AluOp::Srl,
+            0b110                           => AluOp::Or,
+            _                               => AluOp::And,
+        }
+    }
+
+    fn evaluate(&self, a: u32, b: u32) -> u32 {
+        match self {
+            AluOp::Add      => a.wrapping_add(b),
+            AluOp::Sub      => a.wrapping_sub(b),
+            AluOp::Sll      => a << (b & 0x1f),
+            AluOp::Slt      => ((a as i32) < (b as i32)) as u32,
+            AluOp::Sltu     => (a < b) as u32,
+            AluOp::Xor      => a ^ b,
+            AluOp::Srl      => a >> (b & 0x1f),
+            AluOp::Sra      => ((a as i32) >> (b & 0x1f)) as u32,
+            AluOp::Or       => a | b,
+            AluOp::And      => a & b,
+            AluOp::PassB    => b,
+        }
+    }
+}
+
+impl AluInput {
+    const A_CHOICES: [AluInput; 2] = [AluInput::Rs1, AluInput::Pc];
+    const B_CHOICES: [AluInput; 3] = [AluInput::Rs2, AluInput::Imm, AluInput::Four];
+
+    const fn name(&self) -> &'static str {
+        match self {
+            AluInput::Rs1   => "rs1",
+            AluInput::Pc    => "pc",
+            AluInput::Rs2   => "rs2",
+            AluInput::Imm   => "imm",
+            AluInput::Four  => "4",
+        }
+    }
+}
+
+impl AluGuess {
+    //What the instruction should have the ALU do, as far as we can tell from the word alone
+    fn expected(word: u32) -> Option<AluGuess> {
+        let (op, a, b) = match word & 0x7f {
+            0b0110011                           => (AluOp::decode(word, false), AluInput::Rs1, AluInput::Rs2),
+            0b0010011                           => (AluOp::decode(word, true), AluInput::Rs1, AluInput::Imm),
+            0b0000011 | 0b0100011 | 0b1100111   => (AluOp::Add, AluInput::Rs1, AluInput::Imm),
+            0b0010111 | 0b1101111 | 0b1100011   => (AluOp::Add, AluInput::Pc, AluInput::Imm),
+            0b0110111                           => (AluOp::PassB, AluInput::Rs1, AluInput::Imm),
+            _                                   => return None,
+        };
+        Some(AluGuess{op, a, b})
+    }
+
+    fn evaluate(&self, inputs: &AluInputs) -> Option<u32> {
+        let a = match self.a {
+            AluInput::Rs1   => inputs.rs1?,
+            _               => inputs.pc,
+        };
+        let b = match self.b {
+            AluInput::Rs2   => inputs.rs2?,
+            AluInput::Imm   => inputs.imm?,
+            _               => 4,
+        };
+        Some(self.op.evaluate(a, b))
+    }
+
+    //How close another guess is to this one, for picking the likeliest mixup (smaller is closer). Mixing
+    //up rs1 and the pc only happens for a few instructions, so that's less likely than the others
+    fn distance(&self, other: &AluGuess) -> u32 {
+        (self.op != other.op) as u32 + ((self.a != other.a) as u32 * 2) + (self.b != other.b) as u32
+    }
+
+    fn all() -> impl Iterator<Item = AluGuess> {
+        AluOp::ALL.into_iter().flat_map(|op| {
+            AluInput::A_CHOICES.into_iter().flat_map(move |a| AluInput::B_CHOICES.into_iter().map(move |b| AluGuess{op, a, b}))
+        }).filter(|guess| (guess.op != AluOp::PassB) || (guess.a == AluInput::Rs1))//Only one of each pass-through
+    }
+}
+
+/* ------------------------------------------------------------------------------------------------
+ * Traits And Default Implementations
+ * --------------------------------------------------------------------------------------------- */
+
+//TODO
+
+/* ------------------------------------------------------------------------------------------------
+ * Trait Implementations
+ * --------------------------------------------------------------------------------------------- */
+
+impl std::fmt::Display for AluGuess {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let (a, b) = (self.a.name(), self.b.name());
+        match self.op {
+            AluOp::Add      => write!(f, "{} + {}", a, b),
+            AluOp::Sub      => write!(f, "{} - {}", a, b),
+            AluOp::Sll      => write!(f, "{} << {}", a, b),
+            AluOp::Slt      => write!(f, "{} < {} (signed)", a, b),
+            AluOp::Sltu     => write!(f, "{} < {} (unsigned)", a, b),
+            AluOp::Xor      => write!(f, "{} ^ {}", a, b),
+            AluOp::Srl      => write!(f, "{} >> {} (logical)", a, b),
+            AluOp::Sra      => write!(f, "{} >> {} (arithmetic)", a, b),
+            AluOp::Or       => write!(f, "{} | {}", a, b),
+            AluOp::And      => write!(f, "{} & {}", a, b),
+            AluOp::PassB    => write!(f, "just {}", b),
+        }
+    }
+}
+
+impl std::fmt::Display for Format {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Format::I => write!(f, "I-type"),
+            Format::S => write!(f, "S-type"),
+            Format::B => write!(f, "B-type"),
+            Format::U => write!(f, "U-type"),
+            Format::J => write!(f, "J-type"),
+        }
+    }
+}
+
+/* ------------------------------------------------------------------------------------------------
+ * Functions
+ * --------------------------------------------------------------------------------------------- */
+
+//For a [D] field that doesn't match, which bits are off and our best guess at why, ex.
+//" (bits 11:5 differ; your B-type imm looks like it was decoded as S-type)". Empty if they match or either is X/Z.
+pub fn decode_hint(word: u32, field: Field, golden: Logic<u32>, yours: Logic<u32>) -> String {
+    if !golden.is_known() || !yours.is_known() || (golden == yours) {
+        return String::new();
+    }
+    let (golden, yours) = (golden.value(), yours.value());
+
+    let guess = match field {
+        Field::DImm => imm_hint(word, golden, yours),
+        _           => field_bits_hint(word, field, yours),
+    };
+
+    match guess {
+        Some(guess) => format!(" ({}; {})", describe_bits(golden ^ yours), guess),
+        None        => format!(" ({})", describe_bits(golden ^ yours)),
+    }
+}
+
+//For an ALU result that doesn't match, which operation on which inputs gives your result, ex.
+//" (your result equals rs1 - imm; expected rs1 + imm, with rs1 = 00000010, imm = 00000004)".
+//operands are rs1 and rs2 as the golden trace read them, if we know them. Empty if nothing fits.
+pub fn alu_hint(word: u32, pc: u32, operands: Option<(u32, u32)>, golden: Logic<u32>, yours: Logic<u32>) -> String {
+    if !golden.is_known() || !yours.is_known() || (golden == yours) {
+        return String::new();
+    }
+    let (golden, yours) = (golden.value(), yours.value());
+
+    let inputs = AluInputs {
+        rs1:    operands.map(|(rs1, _)| rs1),
+        rs2:    operands.map(|(_, rs2)| rs2),
+        pc,
+        imm:    Format::of(word).map(|format| format.imm(word, true)),
+    };
+
+    //What the golden trace did, as close as possible to what the instruction says to do
+    let natural     = AluGuess::expected(word);
+    let expected    = AluGuess::all().filter(|guess| guess.evaluate(&inputs) == Some(golden)).min_by_key(|guess| {
+        natural.map_or(0, |natural| natural.distance(guess))
+    });
+
+    let yours_guess = AluGuess::all().filter(|guess| guess.evaluate(&inputs) == Some(yours)).min_by_key(|guess| {
+        expected.map_or(0, |expected| expected.distance(guess))
+    });
+    let Some(yours_guess) = yours_guess else {
+        return String::new();
+    };
+
+    let mut used = vec![yours_guess.a, yours_guess.b];
+    if let Some(expected) = expected {
+        used.extend([expected.a, expected.b]);
+    }
+    let mut values = Vec::new();
+    for (input, value) in [(AluInput::Rs1, inputs.rs1), (AluInput::Pc, Some(inputs.pc)), (AluInput::Rs2, inputs.rs2), (AluInput::Imm, inputs.imm)] {
+        if let (true, Some(value)) = (used.contains(&input), value) {
+            values.push(format!("{} = {:08x}", input.name(), value));
+        }
+    }
+
+    match expected {
+        Some(expected)  => format!(" (your result equals {}; expected {}, with {})", yours_guess, expected, values.join(", ")),
+        None            => format!(" (your result equals {}, with {})", yours_guess, values.join(", ")),
+    }
+}
+
+//For a branch taken flag that doesn't match, the comparison with the real operands, what may have
+//gone wrong, and where fetch should have gone vs. where yours went next (if we know). regfile_operands
+//are what the register file held without forwarding. Empty if it isn't a branch or jump.
+pub fn branch_hint(word: u32, pc: u32, operands: Option<(u32, u32)>, regfile_operands: (Option<u32>, Option<u32>), golden: Logic<bool>, yours: Logic<bool>, your_next_fetch: Option<u32>) -> String {
+    if !golden.is_known() || !yours.is_known() || (golden == yours) {
+        return String::new();
+    }
+    let (golden, yours) = (golden.value(), yours.value());
+
+    let is_branch = (word & 0x7f) == 0b1100011;
+    let mut parts = Vec::new();
+    match (is_branch, operands) {
+        (false, _) if predictor::taken(word, 0, 0).is_some() => parts.push(String::from("jumps are always taken")),
+        (false, _) => return String::new(),
+        (true, Some((rs1, rs2))) if predictor::taken(word, rs1, rs2) == Some(golden) => {
+            let funct3 = (word >> 12) & 0b111;
+            parts.push(format!("{} with rs1 = {:08x}, rs2 = {:08x} {}", branch_name(funct3), rs1, rs2, if golden { "is taken" } else { "isn't taken" }));
+
+            let with_funct3 = |funct3: u32| (word & !(0b111 << 12)) | (funct3 << 12);
+            let (stale_rs1, stale_rs2) = (regfile_operands.0.unwrap_or(rs1), regfile_operands.1.unwrap_or(rs2));
+            let signedness_swapped = funct3 ^ 0b010;
+
+            if matches!(funct3, 0b100..=0b111) && (predictor::taken(with_funct3(signedness_swapped), rs1, rs2) == Some(yours)) {
+                parts.push(format!("your decision matches {} instead, so check whether your comparison is signed", branch_name(signedness_swapped)));
+            } else if ((stale_rs1, stale_rs2) != (rs1, rs2)) && (predictor::taken(word, stale_rs1, stale_rs2) == Some(yours)) {
+                let mut stale = Vec::new();
+                if stale_rs1 != rs1 {
+                    stale.push(format!("rs1 = {:08x}", stale_rs1));
+                }
+                if stale_rs2 != rs2 {
+                    stale.push(format!("rs2 = {:08x}", stale_rs2));
+                }
+                parts.push(format!("your decision matches comparing the old register file values ({}), so check your forwarding", stale.join(", ")));
+            } else {
+                parts.push(format!("your decision is what {} would do (an inverted condition?)", branch_name(funct3 ^ 0b001)));
+            }
+        },
+        (true, _) => {},
+    }
+
+    let target = match (golden, word & 0x7f) {
+        (false, _)          => Some(pc.wrapping_add(4)),
+        (true, 0b1100011)   => Some(pc.wrapping_add(Format::B.imm(word, true))),
+        (true, 0b1101111)   => Some(pc.wrapping_add(Format::J.imm(word, true))),
+        (true, _)           => None,//jalr, which depends on rs1
+    };
+    match (target, your_next_fetch) {
+        (Some(target), Some(next_fetch)) if target != next_fetch => parts.push(format!("fetch should have gone to {:08x}, but yours went to {:08x}", target, next_fetch)),
+        (Some(target), _) => parts.push(format!("fetch should have gone to {:08x}", target)),
+        (None, _) => {},
+    }
+
+    format!(" ({})", parts.join("; "))
+}
+
+//For a load/store address that doesn't match. operands are rs1 and rs2 as the golden trace read them
+pub fn mem_addr_hint(word: u32, operands: Option<(u32, u32)>, machine: &MachineConfig, golden: Logic<u32>, yours: Logic<u32>) -> String {
+    if !golden.is_known() || !yours.is_known() || (golden == yours) {
+        return String::new();
+    }
+    let (golden, yours) = (golden.value(), yours.value());
+    let is_store = (word & 0x7f) == 0b0100011;
+
+    let guess = if yours == (golden & !0b11) {
+        Some(String::from("yours is rounded down to a word boundary, but the low 2 bits should be kept since they pick the byte lane"))
+    } else if yours == (golden >> 2) {
+        Some(String::from("yours looks like a word index (the address divided by 4) rather than a byte address"))
+    } else if let Some((rs1, _)) = operands {
+        let (other_format, other_name) = if is_store { (Format::I, "I-type") } else { (Format::S, "S-type") };
+        if yours == rs1 {
+            Some(String::from("yours is just rs1, without the imm added"))
+        } else if yours == rs1.wrapping_add(other_format.imm(word, true)) {
+            Some(format!("yours is rs1 plus the imm decoded as {}", other_name))
+        } else {
+            None
+        }
+    } else {
+        None
+    };
+
+    match guess {
+        Some(guess)                             => format!(" ({})", guess),
+        None if !machine.mem_contains(yours)    => format!(" (yours isn't even in memory, which is {:08x} thru {:08x})", machine.mem_base, machine.mem_base.wrapping_add(machine.mem_size).wrapping_sub(1)),
+        None                                    => String::new(),
+    }
+}
+
+//For an access size that doesn't match. The golden trace uses funct3[1:0] (0 for a byte, 1 for a
+//halfword, 2 for a word), so we check for the other ways of encoding it and for another funct3's size
+pub fn access_size_hint(word: u32, golden: Logic<u32>, yours: Logic<u32>) -> String {
+    if !golden.is_known() || !yours.is_known() || (golden == yours) {
+        return String::new();
+    }
+    let (golden, yours) = (golden.value(), yours.value());
+    let funct3 = (word >> 12) & 0b111;
+    let is_store = (word & 0x7f) == 0b0100011;
+    if golden != (funct3 & 0b11) {
+        return String::new();//Not the convention we know
+    }
+
+    if yours == funct3 {
+        format!(" (yours is the whole funct3, {:03b}, but the access size is only the low 2 bits of it; the top bit says whether a load is unsigned)", funct3)
+    } else if yours == (1 << golden) {
+        String::from(" (yours is the number of bytes, but the access size is 0 for a byte, 1 for a halfword and 2 for a word)")
+    } else if yours <= 0b10 {
+        format!(
+            " (yours is the access size for {}, but this is {}, so check how you map funct3 to the size)",
+            mem_name(yours, is_store), mem_name(funct3, is_store)
+        )
+    } else {
+        String::new()
+    }
+}
+
+//For store data that doesn't match, given the address it's going to: which way of putting rs2 into the
+//byte lanes the golden trace used, and which (if any) yours did
+pub fn store_data_hint(word: u32, operands: Option<(u32, u32)>, addr: Option<u32>, golden: Logic<u32>, yours: Logic<u32>) -> String {
+    if !golden.is_known() || !yours.is_known() || (golden == yours) {
+        return String::new();
+    }
+    let (golden, yours) = (golden.value(), yours.value());
+    let lanes = describe_lanes(golden, yours);
+    let (Some((_, rs2)), Some(addr)) = (operands, addr) else {
+        return format!(" ({})", lanes);
+    };
+
+    let funct3  = (word >> 12) & 0b111;
+    let width   = access_width(funct3);
+    let mask    = if width == 32 { u32::MAX } else { (1 << width) - 1 };
+    let shift   = (addr & 0b11) * 8;
+    let size    = match width { 8 => "byte", 16 => "halfword", _ => "word" };
+    let candidates = [
+        ((rs2 & mask) << shift,                         format!("the low {} of rs2 shifted into place for addr[1:0] = {}", size, addr & 0b11)),
+        (rs2 & mask,                                    format!("the low {} of rs2 on its own", size)),
+        (rs2,                                           String::from("all of rs2")),
+        ((rs2 & mask).wrapping_mul(u32::MAX / mask),    format!("the low {} of rs2 copied into every lane", size)),
+        (rs2 << shift,                                  format!("all of rs2 shifted left by addr[1:0] * 8 = {}", shift)),
+    ];
+
+    let describe = |value: u32| candidates.iter().find(|(candidate, _)| *candidate == value).map(|(_, description)| description.as_str());
+    match (describe(golden), describe(yours)) {
+        (Some(golden_description), Some(your_description)) => format!(" ({}; yours is {}, but it should be {}, with rs2 = {:08x})", lanes, your_description, golden_description, rs2),
+        _ => format!(" ({})", lanes),
+    }
+}
+
+//For data a load wrote to rd that doesn't match, given the address it read from (if we know). The golden
+//value tells us the bytes that were loaded, so we can check what the other loads would've made of them
+pub fn load_data_hint(word: u32, addr: Option<u32>, golden: Logic<u32>, yours: Logic<u32>) -> String {
+    if !golden.is_known() || !yours.is_known() || (golden == yours) || ((word & 0x7f) != 0b0000011) {
+        return String::new();
+    }
+    let (golden, yours) = (golden.value(), yours.value());
+    let lanes = describe_lanes(golden, yours);
+
+    let funct3  = (word >> 12) & 0b111;
+    let width   = access_width(funct3);
+
+    //Another load of no more bytes than this one, on the same bytes
+    let other_load = [0b000, 0b001, 0b010, 0b100, 0b101].into_iter().find(|other_funct3| {
+        (*other_funct3 != funct3) && (access_width(*other_funct3) <= width) && (load_result(*other_funct3, golden) == yours)
+    });
+    if let Some(other_funct3) = other_load {
+        let reason = if access_width(other_funct3) == width { "so check the sign extension" } else { "so check how you map funct3 to the size" };
+        return format!(" ({}; yours is what {} would load, but this is {}, {})", lanes, mem_name(other_funct3, false), mem_name(funct3, false), reason);
+    }
+
+    //The right bytes, but still in the byte lanes they were read from
+    let shift = addr.map_or(0, |addr| (addr & 0b11) * 8);
+    if (shift != 0) && (width < 32) && (((yours >> shift) & ((1 << width) - 1)) == (golden & ((1 << width) - 1))) {
+        return format!(" ({}; yours has the loaded bits in the byte lane(s) they came from, but they need shifting down by addr[1:0] * 8 = {} first)", lanes, shift);
+    }
+
+    format!(" ({})", lanes)
+}
+
+//What a decode field should hold for an instruction word, going by where the field's bits are (or the
+//instruction's immediate format). None for fields that aren't pulled straight from the word
+pub fn decoded_field(word: u32, field: Field) -> Option<u32> {
+    if field == Field::DImm {
+        return Format::of(word).map(|format| format.imm(word, true));
+    }
+    FIELD_BITS.iter().find(|(bits_field, _, _)| *bits_field == field).map(|(_, lsb, width)| (word >> lsb) & ((1 << width) - 1))
+}
+
+//What the ALU should produce for an instruction given rs1 and rs2, and how, ex. (00000014, "rs1 + imm").
+//None if the instruction doesn't use the ALU in a way we know
+pub fn expected_alu_result(word: u32, pc: u32, operands: (u32, u32)) -> Option<(u32, String)> {
+    let inputs = AluInputs {
+        rs1:    Some(operands.0),
+        rs2:    Some(operands.1),
+        pc,
+        imm:    Format::of(word).map(|format| format.imm(word, true)),
+    };
+    let guess = AluGuess::expected(word)?;
+    guess.evaluate(&inputs).map(|result| (result, guess.to_string()))
+}
+
+//How many bits a load or store accesses for its funct3
+pub const fn access_width(funct3: u32) -> u32 {
+    match funct3 & 0b11 {
+        0b00 => 8,
+        0b01 => 16,
+        _    => 32,
+    }
+}
+
+//What a load leaves in rd given what it read (in the low bits of `loaded`), with sign or zero extension
+pub const fn load_result(funct3: u32, loaded: u32) -> u32 {
+    let width = access_width(funct3);
+    match (width, (funct3 & 0b100) != 0) {
+        (32, _)     => loaded,
+        (_, true)   => loaded & ((1 << width) - 1),
+        (_, false)  => (((loaded << (32 - width)) as i32) >> (32 - width)) as u32,
+    }
+}
+
+//ex. "byte lanes 2 and 0 differ (golden 00 00 ab 00, yours 00 00 00 ab)"
+fn describe_lanes(golden: u32, yours: u32) -> String {
+    let differing: Vec<String> = (0..4).rev().filter(|lane| ((golden ^ yours) >> (lane * 8)) & 0xff != 0).map(|lane: u32| lane.to_string()).collect();
+    let bytes = |value: u32| value.to_be_bytes().iter().map(|byte| format!("{:02x}", byte)).collect::<Vec<_>>().join(" ");
+    let list = match differing.split_last() {
+        Some((last, rest)) if !rest.is_empty()  => format!("byte lanes {} and {} differ", rest.join(", "), last),
+        _                                       => format!("byte lane {} differs", differing.concat()),
+    };
+    format!("{} (golden {}, yours {})", list, bytes(golden), bytes(yours))
+}
+
+//ex. "lbu (funct3 100)"
+fn mem_name(funct3: u32, is_store: bool) -> String {
+    let name = match (is_store, funct3) {
+        (true, 0b000)   => "sb",
+        (true, 0b001)   => "sh",
+        (true, _)       => "sw",
+        (false, 0b000)  => "lb",
+        (false, 0b001)  => "lh",
+        (false, 0b100)  => "lbu",
+        (false, 0b101)  => "lhu",
+        (false, _)      => "lw",
+    };
+    format!("{} (funct3 {:03b})", name, funct3)
+}
+
+const fn branch_name(funct3: u32) -> &'static str {
+    match funct3 {
+        0b000 => "beq",
+        0b001 => "bne",
+        0b100 => "blt",
+        0b101 => "bge",
+        0b110 => "bltu",
+        _     => "bgeu",
+    }
+}
+
+fn imm_hint(word: u32, golden: u32, yours: u32) -> Option<String> {
+    let format = Format::of(word)?;
+
+    if yours == format.imm(word, false) {
+        return Some(format!("yours is missing sign extension (bit {} of the {} imm should be copied into every bit above it)", format.width() - 1, format));
+    }
+
+    if matches!(format, Format::B | Format::J) && (yours == ((golden as i32) >> 1) as u32) {
+        return Some(format!("yours looks shifted right by one; bit 0 of a {} imm is always 0 and isn't stored in the instruction, so the bits start at bit 1", format));
+    }
+
+    if (format == Format::U) && (yours == (word >> 12)) {
+        return Some(String::from("a U-type imm is the upper 20 bits of the value, but yours isn't shifted left by 12"));
+    }
+
+    let is_shift_imm = ((word & 0x7f) == 0b0010011) && matches!((word >> 12) & 0b111, 0b001 | 0b101);
+    if is_shift_imm && (yours == ((word >> 20) & 0x1f)) {
+        return Some(String::from("yours is just the shamt, but the imm for a shift is still all of bits 31:20 like any other I-type instruction"));
+    }
+
+    Format::ALL.iter().filter(|other| **other != format).find_map(|other| {
+        if yours == other.imm(word, true) {
+            Some(format!("your {} imm looks like it was decoded as {}", format, other))
+        } else if yours == other.imm(word, false) {
+            Some(format!("your {} imm looks like it was decoded as {} (and without sign extension)", format, other))
+        } else {
+            None
+        }
+    })
+}
+
+//Whether your value is really the bits of another field, or the right field off by one bit
+fn field_bits_hint(word: u32, field: Field, yours: u32) -> Option<String> {
+    let (_, lsb, width) = FIELD_BITS.iter().find(|(known_field, _, _)| *known_field == field)?;
+    let (lsb, width) = (*lsb, *width);
+    let bits_at = |other_lsb: u32| (word >> other_lsb) & ((1 << width) - 1);
+
+    let other_field = FIELD_BITS.iter().find(|(other_field, other_lsb, other_width)| {
+        (*other_field != field) && (*other_lsb != lsb) && (*other_width == width) && (bits_at(*other_lsb) == yours)
+    });
+    if let Some((other_field, other_lsb, _)) = other_field {
+        return Some(format!(
+            "yours is bits {}:{} of the instruction, which is where {} goes, but {} is bits {}:{}",
+            other_lsb + width - 1, other_lsb, other_field.name(), field.name(), lsb + width - 1, lsb
+        ));
+    }
+
+    let off_by_one = [lsb.checked_sub(1), (lsb + width < 32).then_some(lsb + 1)].into_iter().flatten().find(|other_lsb| bits_at(*other_lsb) == yours);
+    off_by_one.map(|other_lsb| format!(
+        "yours is bits {}:{} of the instruction, one bit off from where {} is (bits {}:{})",
+        other_lsb + width - 1, other_lsb, field.name(), lsb + width - 1, lsb
+    ))
+}
+
+//ex. "bits 31:20, 6 and 1:0 differ"
+fn describe_bits(mask: u32) -> String {
+    let mut ranges = Vec::new();
+    let mut bit = 31i32;
+    while bit >= 0 {
+        if (mask >> bit) & 1 == 0 {
+            bit -= 1;
+            continue;
+        }
+        let msb = bit;
+        while (bit >= 0) && ((mask >> bit) & 1 == 1) {
+            bit -= 1;
+        }
+        let lsb = bit + 1;
+        ranges.push(if msb == lsb { msb.to_string() } else { format!("{}:{}", msb, lsb) });
+    }
+
+    let plural = (ranges.len() > 1) || ranges.first().is_some_and(|range| range.contains(':'));
+    let list = match ranges.split_last() {
+        Some((last, rest)) if !rest.is_empty()  => format!("{} and {}", rest.join(", "), last),
+        _                                       => ranges.concat(),
+    };
+    if plural {
+        format!("bits {} differ", list)
+    } else {
+        format!("bit {} differs", list)
+    }
+}
+
+/* ------------------------------------------------------------------------------------------------
+ * Tests
+ * --------------------------------------------------------------------------------------------- */
+
+//TODO
+
+/* ------------------------------------------------------------------------------------------------
+ * Benchmarks
+ * --------------------------------------------------------------------------------------------- */
+
+//TODO
@@ -0,0 +1,281 @@
+/*
+ * File:    layout.rs
+ * Brief:   Describes the stages of the pipeline that produced a trace
+ *
+ * Copyright (C) 2024 John Jekel
+ * See the LICENSE file at the root of the project for licensing info.
+ *
+ * The traces always have [F], [D], [R], [E], [M] and [W] lines, but the pipeline behind them
+ * doesn't have to be the course's five stages. A pipeline config file says what the stages are
+ * called (in order, starting with fetch), which stage each trace tag reports on, where branches
+ * are resolved, and how many cycles late the instruction memory and register file data show up
+ * in the trace:
+ *
+ *     #A 6-stage variant with a two-cycle data memory
+ *     preset          = pd5       #Start from pd5 (the default) or pd6, then override
+ *     stages          = F, D, E, M1, M2, W
+ *     M               = M1        #Tags default to the stage with the same name (R defaults to D's stage)
+ *     branch_stage    = E         #Defaults to the stage [E] is in
+ *     imem_latency    = 0         #PD6 is 1: [F] shows the instruction word a cycle after its PC
+ *     regfile_latency = 0         #PD6 is 1: [R] shows the register data a cycle after the addresses
+ *
+ * Stages without a tag (M2 above) are still tracked, they just aren't checked. [F] must be the
+ * first stage, and the tags have to be in pipeline order.
+ *
+*/
+
+/*!
+ * Describes the stages of the pipeline that produced a trace
+*/
+
+/* ------------------------------------------------------------------------------------------------
+ * Submodules
+ * --------------------------------------------------------------------------------------------- */
+
+//TODO (includes "mod ..." and "pub mod ...")
+
+/* ------------------------------------------------------------------------------------------------
+ * Uses
+ * --------------------------------------------------------------------------------------------- */
+
+use crate::Stage;
+
+use std::path::Path;
+
+/* ------------------------------------------------------------------------------------------------
+ * Macros
+ * --------------------------------------------------------------------------------------------- */
+
+//TODO (also pub(crate) use the_macro statements here too)
+
+/* ------------------------------------------------------------------------------------------------
+ * Constants
+ * --------------------------------------------------------------------------------------------- */
+
+//TODO
+
+/* ------------------------------------------------------------------------------------------------
+ * Static Variables
+ * --------------------------------------------------------------------------------------------- */
+
+//TODO
+
+/* ------------------------------------------------------------------------------------------------
+ * Types
+ * --------------------------------------------------------------------------------------------- */
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PipelineLayout {
+    stages:                 Vec<String>,
+    tag_stages:             [usize; 6],//Indexed like Stage::ALL
+    pub branch_stage:       usize,
+    pub imem_latency:       usize,
+    pub regfile_latency:    usize,
+}
+
+/* ------------------------------------------------------------------------------------------------
+ * Associated Functions and Methods
+ * --------------------------------------------------------------------------------------------- */
+
+impl PipelineLayout {
+    //The course's PD5: five stages, everything shows up in the trace on the same cycle
+    pub fn pd5() -> PipelineLayout {
+        PipelineLayout {
+            stages:             ["F", "D", "E", "M", "W"].map(String::from).to_vec(),
+            tag_stages:         [0, 1, 1, 2, 3, 4],
+            branch_stage:       2,
+            imem_latency:       0,
+            regfile_latency:    0,
+        }
+    }
+
+    //PD6 is the same pipeline, but imemory and the register file have a cycle of latency
+    pub fn pd6() -> PipelineLayout {
+        PipelineLayout {
+            imem_latency:       1,
+            regfile_latency:    1,
+            ..PipelineLayout::pd5()
+        }
+    }
+
+    //Either "pd5", "pd6", or the path to a pipeline config file (ex. what --pipeline says)
+    pub fn from_name_or_path(name_or_path: &str) -> Result<PipelineLayout, String> {
+        match name_or_path {
+            "pd5" => Ok(PipelineLayout::pd5()),
+            "pd6" => Ok(PipelineLayout::pd6()),
+            path  => PipelineLayout::from_path(path).map_err(|e| format!("Error loading pipeline layout {}: {}", path, e)),
+        }
+    }
+
+    pub fn from_path(path: impl AsRef<Path>) -> Result<PipelineLayout, String> {
+        let contents = std::fs::read_to_string(path.as_ref()).map_err(|e| e.to_string())?;
+        PipelineLayout::parse(&contents)
+    }
+
+    pub fn parse(contents: &str) -> Result<PipelineLayout, String> {
+        let mut layout                              = PipelineLayout::pd5();
+        let mut stages: Option<Vec<String>>         = None;
+        let mut tag_names: [Option<String>; 6]      = Default::default();
+        let mut branch_stage: Option<String>        = None;
+
+        for (line_num, line) in contents.lines().enumerate() {
+            let line = line.split('#').next().unwrap().trim();
+            if line.is_empty() {
+                continue;
+            }
+
+            let Some((key, value)) = line.split_once('=') else {
+                return Err(format!("Line {}: expected `<key> = <value>`, got \"{}\"", line_num + 1, line));
+            };
+            let (key, value) = (key.trim(), value.trim());
+            let number = || value.parse::<usize>().map_err(|_| format!("Line {}: bad number \"{}\"", line_num + 1, value));
+
+            match key.to_lowercase().as_str() {
+                "preset" => {
+                    let preset = match value {
+                        "pd5" => PipelineLayout::pd5(),
+                        "pd6" => PipelineLayout::pd6(),
+                        _     => return Err(format!("Line {}: unknown preset \"{}\" (expected pd5 or pd6)", line_num + 1, value)),
+                    };
+                    layout.imem_latency     = preset.imem_latency;
+                    layout.regfile_latency  = preset.regfile_latency;
+                },
+                "stages"            => stages = Some(value.split(',').map(|stage| stage.trim().to_string()).collect()),
+                "branch_stage"      => branch_stage = Some(value.to_string()),
+                "imem_latency"      => layout.imem_latency = number()?,
+                "regfile_latency"   => layout.regfile_latency = number()?,
+                tag => {
+                    let Some(tag) = Stage::from_letter(tag) else {
+                        return Err(format!("Line {}: unknown setting \"{}\"", line_num + 1, key));
+                    };
+                    tag_names[tag.index()] = Some(value.to_string());
+                },
+            }
+        }
+
+        if let Some(stages) = stages {
+            if stages.iter().any(|stage| stage.is_empty()) {
+                return Err(String::from("Stage names can't be empty"));
+            }
+            layout.stages = stages;
+        }
+
+        let find_stage = |name: &str| layout.stages.iter().position(|stage| stage == name);
+        let mut tag_stages = [0; 6];
+        for (index, tag) in Stage::ALL.into_iter().enumerate() {
+            tag_stages[index] = match tag_names[index].as_deref() {
+                Some(name)                  => find_stage(name).ok_or(format!("{} is in stage \"{}\", which isn't in the list of stages", tag.tag(), name))?,
+                None if tag == Stage::R     => tag_stages[Stage::D.index()],
+                None                        => {
+                    let name = &tag.tag()[1..2];
+                    find_stage(name).ok_or(format!("No stage named \"{}\", so you need to say which stage {} is in", name, tag.tag()))?
+                },
+            };
+        }
+        let branch_stage = match branch_stage.as_deref() {
+            Some(name)  => find_stage(name).ok_or(format!("Branch stage \"{}\" isn't in the list of stages", name))?,
+            None        => tag_stages[Stage::E.index()],
+        };
+
+        layout.tag_stages   = tag_stages;
+        layout.branch_stage = branch_stage;
+
+        layout.validate()?;
+        Ok(layout)
+    }
+
+    fn validate(&self) -> Result<(), String> {
+        if self.stage_of(Stage::F) != 0 {
+            return Err(String::from("[F] has to be in the first stage"));
+        }
+        if self.tag_stages.windows(2).any(|pair| pair[0] > pair[1]) {
+            return Err(String::from("The tags have to be in pipeline order ([F], [D], [R], [E], [M], [W])"));
+        }
+        if self.stage_of(Stage::D) == self.stage_of(Stage::E) {
+            return Err(String::from("[D] and [E] can't be in the same stage"));
+        }
+        if self.branch_stage < self.stage_of(Stage::D) {
+            return Err(String::from("Branches can't be resolved before decode"));
+        }
+        Ok(())
+    }
+
+    pub fn depth(&self) -> usize {
+        self.stages.len()
+    }
+
+    pub fn stage_name(&self, stage: usize) -> &str {
+        &self.stages[stage]
+    }
+
+    pub const fn stage_of(&self, tag: Stage) -> usize {
+        self.tag_stages[tag.index()]
+    }
+
+    pub fn tags_in(&self, stage: usize) -> impl Iterator<Item = Stage> + '_ {
+        Stage::ALL.into_iter().filter(move |tag| self.stage_of(*tag) == stage)
+    }
+
+    //How many cycles past the current one the checkers need to see
+    pub fn lookahead(&self) -> usize {
+        self.imem_latency.max(self.regfile_latency)
+    }
+
+    //ex. "[D]/[R]", or "(M2)" for a stage with no tags
+    pub fn label(&self, stage: usize) -> String {
+        let tags: Vec<&str> = self.tags_in(stage).map(|tag| tag.tag()).collect();
+        if tags.is_empty() {
+            format!("({})", self.stages[stage])
+        } else {
+            tags.join("/")
+        }
+    }
+}
+
+/* ------------------------------------------------------------------------------------------------
+ * Traits And Default Implementations
+ * --------------------------------------------------------------------------------------------- */
+
+//TODO
+
+/* ------------------------------------------------------------------------------------------------
+ * Trait Implementations
+ * --------------------------------------------------------------------------------------------- */
+
+impl Default for PipelineLayout {
+    fn default() -> Self {
+        PipelineLayout::pd5()
+    }
+}
+
+//ex. "F, D, E, M, W (branches resolve in E, imemory latency 1, register file latency 1)", for reports
+impl std::fmt::Display for PipelineLayout {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} (branches resolve in {}, imemory latency {}, register file latency {})",
+            self.stages.join(", "),
+            self.stages[self.branch_stage],
+            self.imem_latency,
+            self.regfile_latency
+        )
+    }
+}
+
+/* ------------------------------------------------------------------------------------------------
+ * Functions
+ * --------------------------------------------------------------------------------------------- */
+
+//TODO
+
+/* ------------------------------------------------------------------------------------------------
+ * Tests
+ * --------------------------------------------------------------------------------------------- */
+
+//TODO
+
+/* ------------------------------------------------------------------------------------------------
+ * Benchmarks
+ * --------------------------------------------------------------------------------------------- */
+
+//TODO
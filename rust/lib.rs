@@ -17,7 +17,26 @@
  * Submodules
  * --------------------------------------------------------------------------------------------- */
 
-//TODO (includes "mod ..." and "pub mod ...")
+pub mod capture;
+pub mod cli;
+pub mod compare;
+pub mod csr;
+pub mod cycle;
+pub mod elf;
+pub mod hints;
+pub mod layout;
+pub mod lint;
+pub mod lockstep;
+pub mod logic;
+pub mod machine;
+pub mod muldiv;
+pub mod predictor;
+pub mod reference;
+pub mod rules;
+pub mod selfcheck;
+pub mod symbols;
+pub mod tracker;
+pub mod xfile;
 
 /* ------------------------------------------------------------------------------------------------
  * Uses
@@ -25,6 +44,8 @@
 
 use riscv_tools::*;
 
+use logic::Logic;
+
 use std::path::Path;
 use std::fs::*;
 use std::io::{BufReader, BufRead, Lines};
@@ -33,13 +54,34 @@ use std::io::{BufReader, BufRead, Lines};
  * Macros
  * --------------------------------------------------------------------------------------------- */
 
-//TODO (also pub(crate) use the_macro statements here too)
+//Compares one field of a golden and test line, reporting X/Z in your trace as its own class of error.
+//Unknown bits in the golden trace can't be compared against, so they're skipped.
+#[macro_export]
+macro_rules! check_field {
+    ($print_error:ident, $golden:expr, $yours:expr, $message:expr) => {
+        if $golden.is_known() {
+            if !$yours.is_known() {
+                $print_error($crate::ErrorClass::UnknownValue, &format!("{} (yours is {})", $message, $yours));
+            } else if $golden != $yours {
+                $print_error($crate::ErrorClass::Mismatch, $message);
+            }
+        }
+    };
+}
 
 /* ------------------------------------------------------------------------------------------------
  * Constants
  * --------------------------------------------------------------------------------------------- */
 
-//TODO
+//Header lines look like `#! benchmark: rv32ui-p-add`; any other line starting with `#` is a comment
+const HEADER_PREFIX:    &str = "#!";
+const COMMENT_PREFIX:   &str = "#";
+
+//The header key the checkers use to make sure both traces came from the same program
+pub const BENCHMARK_HEADER: &str = "benchmark";
+
+//The header key saying what the numbers before each line's tag are, ex. `#! stamp: cycle time`
+pub const STAMP_HEADER: &str = "stamp";
 
 /* ------------------------------------------------------------------------------------------------
  * Static Variables
@@ -51,127 +93,314 @@ use std::io::{BufReader, BufRead, Lines};
  * Types
  * --------------------------------------------------------------------------------------------- */
 
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Stage {
+    F,
+    D,
+    R,
+    E,
+    M,
+    W,
+}
+
 #[derive(Copy, Clone, Debug)]
 pub enum ParsedLine {
     F{
-        pc:     u32,
-        instr:  u32,
+        pc:     Logic<u32>,
+        instr:  Logic<u32>,
     },
     D{
-        pc:     u32,
-        opcode: u8,
-        rd:     u8,
-        rs1:    u8,
-        rs2:    u8,
-        funct3: u8,
-        funct7: u8,
-        imm:    u32,
-        shamt:  u8,
+        pc:     Logic<u32>,
+        opcode: Logic<u8>,
+        rd:     Logic<u8>,
+        rs1:    Logic<u8>,
+        rs2:    Logic<u8>,
+        funct3: Logic<u8>,
+        funct7: Logic<u8>,
+        imm:    Logic<u32>,
+        shamt:  Logic<u8>,
     },
     R{
-        addr_rs1:   u8,
-        addr_rs2:   u8,
-        data_rs1:   u32,
-        data_rs2:   u32,
+        addr_rs1:   Logic<u8>,
+        addr_rs2:   Logic<u8>,
+        data_rs1:   Logic<u32>,
+        data_rs2:   Logic<u32>,
     },
     E{
-        pc:             u32,
-        alu_result:     u32,
-        branch_taken:   bool,
+        pc:             Logic<u32>,
+        alu_result:     Logic<u32>,
+        branch_taken:   Logic<bool>,
     },
     M{
-        pc:             u32,
-        addr:           u32,
-        read_not_write: bool,
-        access_size:    u8,
-        memory_wdata:   u32,
+        pc:             Logic<u32>,
+        addr:           Logic<u32>,
+        read_not_write: Logic<bool>,
+        access_size:    Logic<u8>,
+        memory_wdata:   Logic<u32>,
     },
     W{
-        pc:         u32,
-        we:         bool,
-        addr_rd:    u8,
-        data_rd:    u32,
+        pc:         Logic<u32>,
+        we:         Logic<bool>,
+        addr_rd:    Logic<u8>,
+        data_rd:    Logic<u32>,
     },
 }
 
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum ErrorClass {
+    Mismatch,
+    UnknownValue,
+    Structure,//A line is missing or repeated in your trace
+    Capture,//Lines lost or repeated on the way from the board, not a problem with your CPU
+    Reference,//The golden trace disagrees with the decoder or pipeline model, not a problem with your CPU
+}
+
+//The cycle counter and/or simulation time printed before a line's tag, if any
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct Stamp {
+    pub cycle:  Option<u64>,
+    pub time:   Option<u64>,
+}
+
 pub struct ParsedLineIterator {
     buffered_lines: Lines<BufReader<File>>,
+    path:           String,//For errors
+    line_num:       usize,//Of the last line read, starting at 1
+    headers:        Vec<(String, String)>,
+    first_line:     Option<(Stamp, Result<ParsedLine, String>)>,//Read early so the headers at the top of the file are available right away
 }
 
-/* ------------------------------------------------------------------------------------------------
- * Associated Functions and Methods
- * --------------------------------------------------------------------------------------------- */
-
-impl ParsedLineIterator {
-    pub fn from_path(path: impl AsRef<Path>) -> std::io::Result<ParsedLineIterator> {
-        Ok(ParsedLineIterator {
-            buffered_lines: BufReader::new(File::open(path)?).lines(),
-        })
-    }
+enum RawLine<'a> {
+    Ignored,
+    Header(String, String),
+    Trace(&'a str),
 }
 
 /* ------------------------------------------------------------------------------------------------
- * Traits And Default Implementations
+ * Associated Functions and Methods
  * --------------------------------------------------------------------------------------------- */
 
-//TODO
+impl Stage {
+    //Every tag in the trace, in pipeline order
+    pub const ALL: [Stage; 6] = [Stage::F, Stage::D, Stage::R, Stage::E, Stage::M, Stage::W];
 
-/* ------------------------------------------------------------------------------------------------
- * Trait Implementations
- * --------------------------------------------------------------------------------------------- */
+    //Where the tag is in Stage::ALL, for tables indexed by tag
+    pub const fn index(&self) -> usize {
+        match self {
+            Stage::F => 0,
+            Stage::D => 1,
+            Stage::R => 2,
+            Stage::E => 3,
+            Stage::M => 4,
+            Stage::W => 5,
+        }
+    }
 
-impl Iterator for ParsedLineIterator {
-    type Item = ParsedLine;
+    pub const fn tag(&self) -> &'static str {
+        match self {
+            Stage::F => "[F]",
+            Stage::D => "[D]",
+            Stage::R => "[R]",
+            Stage::E => "[E]",
+            Stage::M => "[M]",
+            Stage::W => "[W]",
+        }
+    }
 
-    fn next(&mut self) -> Option<Self::Item> {
-        Some((&*self.buffered_lines.next()?.ok()?).into())
+    pub fn from_letter(s: &str) -> Option<Stage> {
+        match s {
+            "F" | "f" | "[F]" => Some(Stage::F),
+            "D" | "d" | "[D]" => Some(Stage::D),
+            "R" | "r" | "[R]" => Some(Stage::R),
+            "E" | "e" | "[E]" => Some(Stage::E),
+            "M" | "m" | "[M]" => Some(Stage::M),
+            "W" | "w" | "[W]" => Some(Stage::W),
+            _                 => None,
+        }
     }
 }
 
-impl From<&str> for ParsedLine {
-    fn from(s: &str) -> Self {
+impl ParsedLine {
+    pub const fn stage(&self) -> Stage {
+        match self {
+            ParsedLine::F{..} => Stage::F,
+            ParsedLine::D{..} => Stage::D,
+            ParsedLine::R{..} => Stage::R,
+            ParsedLine::E{..} => Stage::E,
+            ParsedLine::M{..} => Stage::M,
+            ParsedLine::W{..} => Stage::W,
+        }
+    }
+
+    pub fn parse(s: &str) -> Result<ParsedLine, String> {
         let mut tokens = s.split_whitespace();
-        match tokens.next().unwrap() {
+        let tag = tokens.next().unwrap_or_default();
+        Ok(match tag {
             "[F]" => ParsedLine::F {
-                pc:     u32::from_str_radix(tokens.next().unwrap(), 16).unwrap(),
-                instr:  u32::from_str_radix(tokens.next().unwrap(), 16).unwrap(),
+                pc:     next_field(&mut tokens, s)?,
+                instr:  next_field(&mut tokens, s)?,
             },
             "[D]" => ParsedLine::D {
-                pc:     u32::from_str_radix(tokens.next().unwrap(), 16).unwrap(),
-                opcode: u8::from_str_radix (tokens.next().unwrap(), 16).unwrap(),
-                rd:     u8::from_str_radix (tokens.next().unwrap(), 16).unwrap(),
-                rs1:    u8::from_str_radix (tokens.next().unwrap(), 16).unwrap(),
-                rs2:    u8::from_str_radix (tokens.next().unwrap(), 16).unwrap(),
-                funct3: u8::from_str_radix (tokens.next().unwrap(), 16).unwrap(),
-                funct7: u8::from_str_radix (tokens.next().unwrap(), 16).unwrap(),
-                imm:    u32::from_str_radix(tokens.next().unwrap(), 16).unwrap(),
-                shamt:  u8::from_str_radix (tokens.next().unwrap(), 16).unwrap(),
+                pc:     next_field(&mut tokens, s)?,
+                opcode: next_field(&mut tokens, s)?,
+                rd:     next_field(&mut tokens, s)?,
+                rs1:    next_field(&mut tokens, s)?,
+                rs2:    next_field(&mut tokens, s)?,
+                funct3: next_field(&mut tokens, s)?,
+                funct7: next_field(&mut tokens, s)?,
+                imm:    next_field(&mut tokens, s)?,
+                shamt:  next_field(&mut tokens, s)?,
             },
             "[R]" => ParsedLine::R {
-                addr_rs1:   u8::from_str_radix (tokens.next().unwrap(), 16).unwrap(),
-                addr_rs2:   u8::from_str_radix (tokens.next().unwrap(), 16).unwrap(),
-                data_rs1:   u32::from_str_radix(tokens.next().unwrap(), 16).unwrap(),
-                data_rs2:   u32::from_str_radix(tokens.next().unwrap(), 16).unwrap(),
+                addr_rs1:   next_field(&mut tokens, s)?,
+                addr_rs2:   next_field(&mut tokens, s)?,
+                data_rs1:   next_field(&mut tokens, s)?,
+                data_rs2:   next_field(&mut tokens, s)?,
             },
             "[E]" => ParsedLine::E {
-                pc:             u32::from_str_radix(tokens.next().unwrap(), 16).unwrap(),
-                alu_result:     u32::from_str_radix(tokens.next().unwrap(), 16).unwrap(),
-                branch_taken:   tokens.next().unwrap() == "1",
+                pc:             next_field(&mut tokens, s)?,
+                alu_result:     next_field(&mut tokens, s)?,
+                branch_taken:   next_field(&mut tokens, s)?,
             },
             "[M]" => ParsedLine::M {
-                pc:             u32::from_str_radix(tokens.next().unwrap(), 16).unwrap(),
-                addr:           u32::from_str_radix(tokens.next().unwrap(), 16).unwrap(),
-                read_not_write: tokens.next().unwrap() == "1",
-                access_size:    u8::from_str_radix (tokens.next().unwrap(), 16).unwrap(),
-                memory_wdata:   u32::from_str_radix(tokens.next().unwrap(), 16).unwrap(),
+                pc:             next_field(&mut tokens, s)?,
+                addr:           next_field(&mut tokens, s)?,
+                read_not_write: next_field(&mut tokens, s)?,
+                access_size:    next_field(&mut tokens, s)?,
+                memory_wdata:   next_field(&mut tokens, s)?,
             },
             "[W]" => ParsedLine::W {
-                pc:         u32::from_str_radix(tokens.next().unwrap(), 16).unwrap(),
-                we:         tokens.next().unwrap() == "1",
-                addr_rd:    u8::from_str_radix (tokens.next().unwrap(), 16).unwrap(),
-                data_rd:    u32::from_str_radix(tokens.next().unwrap(), 16).unwrap(),
+                pc:         next_field(&mut tokens, s)?,
+                we:         next_field(&mut tokens, s)?,
+                addr_rd:    next_field(&mut tokens, s)?,
+                data_rd:    next_field(&mut tokens, s)?,
             },
-            _ => panic!("Bad syntax on line: {}", s),
+            _ => return Err(format!("bad syntax in \"{}\"", s)),
+        })
+    }
+}
+
+impl ParsedLineIterator {
+    pub fn from_path(path: impl AsRef<Path>) -> Result<ParsedLineIterator, String> {
+        let path = path.as_ref().display().to_string();
+        let file = File::open(&path).map_err(|e| format!("Error loading trace at path {}: {}", path, e))?;
+        let mut iterator = ParsedLineIterator {
+            buffered_lines: BufReader::new(file).lines(),
+            path,
+            line_num:       0,
+            headers:        Vec::new(),
+            first_line:     None,
+        };
+        iterator.first_line = iterator.next_trace_line();
+        Ok(iterator)
+    }
+
+    //Keys are case-insensitive (ex. `MEM_DEPTH` and `mem_depth` are the same), and later headers win
+    pub fn header(&self, key: &str) -> Option<&str> {
+        let key = key.to_lowercase();
+        self.headers.iter().rev().find(|(header_key, _)| *header_key == key).map(|(_, value)| value.as_str())
+    }
+
+    pub fn headers(&self) -> &[(String, String)] {
+        &self.headers
+    }
+
+    //Each line with the stamp (if any) in front of it, or an Err saying where a line that can't be parsed is.
+    //Never panics, since a bad trace shouldn't take the checker (or a simulation using the lockstep library)
+    //down with it.
+    pub fn try_stamped(mut self) -> impl Iterator<Item = Result<(Stamp, ParsedLine), String>> {
+        std::iter::from_fn(move || self.first_line.take().or_else(|| self.next_trace_line())).map(|(stamp, line)| line.map(|line| (stamp, line)))
+    }
+
+    //All of try_stamped(), stopping at the first bad line
+    pub fn read_stamped(self) -> Result<Vec<(Stamp, ParsedLine)>, String> {
+        self.try_stamped().collect()
+    }
+
+    fn next_trace_line(&mut self) -> Option<(Stamp, Result<ParsedLine, String>)> {
+        loop {
+            let line = match self.buffered_lines.next()? {
+                Ok(line)    => line,
+                Err(e)      => return Some((Stamp::default(), Err(format!("Error reading {}: {}", self.path, e)))),
+            };
+            self.line_num += 1;
+
+            match RawLine::from(line.as_str()) {
+                RawLine::Ignored                => continue,
+                RawLine::Header(key, value)     => self.headers.push((key, value)),
+                RawLine::Trace(trace_line)      => {
+                    //Anything before the tag is the stamp
+                    let (leading, trace_line) = trace_line.split_at(trace_line.find('[').unwrap_or(0));
+                    let parsed_line = ParsedLine::parse(trace_line).map_err(|e| format!("Line {} of {}: {}", self.line_num, self.path, e));
+                    return Some((Stamp::parse(leading, self.header(STAMP_HEADER)), parsed_line));
+                },
+            }
+        }
+    }
+}
+
+impl Stamp {
+    //`columns` is the stamp header, if there is one. Without it, a lone number is the time (what %t
+    //prints) and two are the cycle then the time. Numbers can have a leading @, a trailing : or , and a
+    //time unit (ex. `@1234ns:`); anything else before the tag is ignored.
+    pub fn parse(leading: &str, columns: Option<&str>) -> Stamp {
+        let values: Vec<u64> = leading.split_whitespace().filter_map(parse_stamp_field).collect();
+        let columns: Vec<String> = match columns {
+            Some(columns)               => columns.split([' ', ',']).filter(|column| !column.is_empty()).map(str::to_lowercase).collect(),
+            None if values.len() >= 2   => vec![String::from("cycle"), String::from("time")],
+            None                        => vec![String::from("time")],
+        };
+
+        let mut stamp = Stamp::default();
+        for (column, value) in columns.iter().zip(values) {
+            match column.as_str() {
+                "cycle" => stamp.cycle = Some(value),
+                "time"  => stamp.time = Some(value),
+                _       => {},//Some other counter we don't care about
+            }
+        }
+        stamp
+    }
+
+    pub const fn is_empty(&self) -> bool {
+        self.cycle.is_none() && self.time.is_none()
+    }
+}
+
+impl ErrorClass {
+    //Whether it's a problem with your CPU, and so counts towards the errors a checker exits with
+    pub const fn counts_as_error(self) -> bool {
+        !matches!(self, ErrorClass::Capture | ErrorClass::Reference)
+    }
+}
+
+/* ------------------------------------------------------------------------------------------------
+ * Traits And Default Implementations
+ * --------------------------------------------------------------------------------------------- */
+
+//TODO
+
+/* ------------------------------------------------------------------------------------------------
+ * Trait Implementations
+ * --------------------------------------------------------------------------------------------- */
+
+impl<'a> From<&'a str> for RawLine<'a> {
+    fn from(line: &'a str) -> Self {
+        let line = line.trim();
+
+        if let Some(header) = line.strip_prefix(HEADER_PREFIX) {
+            let Some((key, value)) = header.split_once([':', '=']) else {
+                return RawLine::Ignored;//Not actually key/value, so just treat it as a comment
+            };
+            return RawLine::Header(key.trim().to_lowercase(), value.trim().to_string());
+        }
+
+        //Also allow comments at the end of a trace line
+        let line = line.split_once(COMMENT_PREFIX).map_or(line, |(before_comment, _)| before_comment).trim_end();
+        if line.is_empty() {
+            RawLine::Ignored
+        } else {
+            RawLine::Trace(line)
         }
     }
 }
@@ -182,9 +411,33 @@ impl std::fmt::Display for ParsedLine {
             ParsedLine::F{pc, instr} => write!(f, "[F] {:08x} {:08x}", pc, instr),
             ParsedLine::D{pc, opcode, rd, rs1, rs2, funct3, funct7, imm, shamt} => write!(f, "[D] {:08x} {:02x} {:02x} {:02x} {:02x} {:01x} {:02x} {:08x} {:02x}", pc, opcode, rd, rs1, rs2, funct3, funct7, imm, shamt),
             ParsedLine::R{addr_rs1, addr_rs2, data_rs1, data_rs2} => write!(f, "[R] {:02x} {:02x} {:08x} {:08x}", addr_rs1, addr_rs2, data_rs1, data_rs2),
-            ParsedLine::E{pc, alu_result, branch_taken} => write!(f, "[E] {:08x} {:08x} {}", pc, alu_result, if *branch_taken {1} else {0}),
-            ParsedLine::M{pc, addr, read_not_write, access_size, memory_wdata} => write!(f, "[M] {:08x} {:08x} {} {:01x} {:08x}", pc, addr, if *read_not_write {1} else {0}, access_size, memory_wdata),
-            ParsedLine::W{pc, we, addr_rd, data_rd} => write!(f, "[W] {:08x} {} {:02x} {:08x}", pc, if *we {1} else {0}, addr_rd, data_rd),
+            ParsedLine::E{pc, alu_result, branch_taken} => write!(f, "[E] {:08x} {:08x} {}", pc, alu_result, branch_taken),
+            ParsedLine::M{pc, addr, read_not_write, access_size, memory_wdata} => write!(f, "[M] {:08x} {:08x} {} {:01x} {:08x}", pc, addr, read_not_write, access_size, memory_wdata),
+            ParsedLine::W{pc, we, addr_rd, data_rd} => write!(f, "[W] {:08x} {} {:02x} {:08x}", pc, we, addr_rd, data_rd),
+        }
+    }
+}
+
+//ex. "cycle 12 (time 1234)", for reports
+impl std::fmt::Display for Stamp {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match (self.cycle, self.time) {
+            (Some(cycle), Some(time))   => write!(f, "cycle {} (time {})", cycle, time),
+            (Some(cycle), None)         => write!(f, "cycle {}", cycle),
+            (None, Some(time))          => write!(f, "time {}", time),
+            (None, None)                => write!(f, "an unknown time"),
+        }
+    }
+}
+
+impl std::fmt::Display for ErrorClass {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ErrorClass::Mismatch        => write!(f, "mismatch"),
+            ErrorClass::UnknownValue    => write!(f, "your value is X/Z"),
+            ErrorClass::Structure       => write!(f, "trace structure"),
+            ErrorClass::Capture         => write!(f, "capture problem"),
+            ErrorClass::Reference       => write!(f, "reference inconsistency"),
         }
     }
 }
@@ -193,7 +446,63 @@ impl std::fmt::Display for ParsedLine {
  * Functions
  * --------------------------------------------------------------------------------------------- */
 
+fn next_field<T: logic::LogicBits>(tokens: &mut std::str::SplitWhitespace, line: &str) -> Result<Logic<T>, String> {
+    let token = tokens.next().ok_or_else(|| format!("too few fields in \"{}\"", line))?;
+    Logic::from_hex(token).ok_or_else(|| format!("bad value \"{}\" in \"{}\"", token, line))
+}
+
+fn parse_stamp_field(token: &str) -> Option<u64> {
+    let token = token.trim_start_matches('@').trim_end_matches([':', ',']);
+    token.trim_end_matches(|c: char| c.is_ascii_alphabetic()).parse().ok()
+}
+
+//Err if the traces are for different benchmarks, otherwise warnings about any other headers that differ.
+//Traces without headers (ex. older ones) can't be checked, so they're allowed.
+pub fn check_headers(golden: &ParsedLineIterator, test: &ParsedLineIterator) -> Result<Vec<String>, String> {
+    if let (Some(golden_benchmark), Some(test_benchmark)) = (golden.header(BENCHMARK_HEADER), test.header(BENCHMARK_HEADER)) {
+        if golden_benchmark != test_benchmark {
+            return Err(format!(
+                "The golden trace is for benchmark \"{}\" but yours is for \"{}\"!",
+                golden_benchmark, test_benchmark
+            ));
+        }
+    }
+
+    let mut warnings = Vec::new();
+    for (key, golden_value) in golden.headers() {
+        if key == BENCHMARK_HEADER || golden.header(key) != Some(golden_value.as_str()) {
+            continue;//Already checked, or a later header with the same key overrides this one
+        }
+
+        if let Some(test_value) = test.header(key) {
+            if test_value != golden_value {
+                warnings.push(format!("Header \"{}\" is \"{}\" in the golden trace but \"{}\" in yours", key, golden_value, test_value));
+            }
+        }
+    }
+    Ok(warnings)
+}
+
+//check_headers() for the checkers: prints the benchmark and any warnings, and Err if the traces can't be compared
+pub fn verify_same_benchmark(golden: &ParsedLineIterator, test: &ParsedLineIterator) -> Result<(), String> {
+    let warnings = check_headers(golden, test)?;
+    if let Some(benchmark) = golden.header(BENCHMARK_HEADER) {
+        println!("Benchmark: \x1b[1;36m{}\x1b[0m", benchmark);
+    }
+    for warning in warnings {
+        println!("\x1b[1;33mWarning: {}\x1b[0m", warning);
+    }
+    Ok(())
+}
+
 pub fn disassemble(instr: &Instruction) -> String {
+    if let Some(disassembly) = muldiv::MulDivOp::disassemble(instr.assume_uncompressed()) {
+        return disassembly;//riscv_tools doesn't know about RV32M
+    }
+    if let Some(disassembly) = csr::disassemble(instr.assume_uncompressed()) {
+        return disassembly;//Nor Zicsr or mret
+    }
+
     let mut buffer = Vec::new();
     instr.disassemble(&mut buffer).unwrap();
     String::from_utf8(buffer).unwrap().trim().to_string()
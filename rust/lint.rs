@@ -0,0 +1,169 @@
+/*
+ * File:    lint.rs
+ * Brief:   Optional warnings about don't-care values that look uninitialised
+ *
+ * Copyright (C) 2024 John Jekel
+ * See the LICENSE file at the root of the project for licensing info.
+ *
+ * The checkers deliberately ignore don't-care fields, which is what you want for grading
+ * but can hide X-propagation bugs that only bite you later (ex. on the board). This looks
+ * at the don't-care fields of YOUR trace and warns about values that smell like garbage.
+ * Warnings are never counted as errors.
+ *
+*/
+
+/*!
+ * Optional warnings about don't-care values that look uninitialised
+*/
+
+/* ------------------------------------------------------------------------------------------------
+ * Submodules
+ * --------------------------------------------------------------------------------------------- */
+
+//TODO (includes "mod ..." and "pub mod ...")
+
+/* ------------------------------------------------------------------------------------------------
+ * Uses
+ * --------------------------------------------------------------------------------------------- */
+
+use crate::ParsedLine;
+use crate::rules::{Field, RuleTable};
+
+use riscv_tools::*;
+
+/* ------------------------------------------------------------------------------------------------
+ * Macros
+ * --------------------------------------------------------------------------------------------- */
+
+//TODO (also pub(crate) use the_macro statements here too)
+
+/* ------------------------------------------------------------------------------------------------
+ * Constants
+ * --------------------------------------------------------------------------------------------- */
+
+//Common "this memory/register was never written" fill patterns from testbenches and simulators
+const POISON_VALUES: [u32; 6] = [
+    0xdeadbeef,
+    0xdeaddead,
+    0xbaadf00d,
+    0xcafebabe,
+    0xfeedface,
+    0xa5a5a5a5,
+];
+
+/* ------------------------------------------------------------------------------------------------
+ * Static Variables
+ * --------------------------------------------------------------------------------------------- */
+
+//TODO
+
+/* ------------------------------------------------------------------------------------------------
+ * Types
+ * --------------------------------------------------------------------------------------------- */
+
+#[derive(Default)]
+pub struct Linter {
+    warning_count: u32,
+}
+
+/* ------------------------------------------------------------------------------------------------
+ * Associated Functions and Methods
+ * --------------------------------------------------------------------------------------------- */
+
+impl Linter {
+    pub fn new() -> Linter {
+        Linter::default()
+    }
+
+    //`line` is from your trace, `previous_line` is the same stage's line from your trace last cycle,
+    //and `instr` is what the golden trace says that stage is processing (None for a bubble, in which
+    //case everything is garbage anyways so we don't bother). `stalled` is whether the golden pipeline
+    //held this stage this cycle.
+    pub fn lint_line(
+        &mut self,
+        line:           &ParsedLine,
+        previous_line:  Option<&ParsedLine>,
+        instr:          Option<&Instruction>,
+        rules:          &RuleTable,
+        stalled:        bool
+    ) -> Vec<String> {
+        let mut warnings = Vec::new();
+
+        let Some(instr) = instr else {
+            return warnings;
+        };
+
+        for field in Field::ALL.into_iter().filter(|field| field.stage() == line.stage()) {
+            if rules.must_match(instr, field) {
+                continue;//Already checked for real
+            }
+
+            let Some(value) = field.value_in(line) else {
+                continue;
+            };
+
+            if !value.is_known() {
+                warnings.push(format!(
+                    "{} {} is {:08x}, which is X/Z (don't care for `{}`)",
+                    field.stage().tag(), field.name(), value, crate::disassemble(instr)
+                ));
+                continue;
+            }
+
+            if POISON_VALUES.contains(&value.value()) {
+                warnings.push(format!(
+                    "{} {} is {:08x}, which looks like an uninitialised value (don't care for `{}`)",
+                    field.stage().tag(), field.name(), value, crate::disassemble(instr)
+                ));
+            }
+
+            if stalled {
+                if let Some(previous_value) = previous_line.and_then(|previous_line| field.value_in(previous_line)) {
+                    if previous_value != value {
+                        warnings.push(format!(
+                            "{} {} changed from {:08x} to {:08x} while the stage was stalled (don't care for `{}`)",
+                            field.stage().tag(), field.name(), previous_value, value, crate::disassemble(instr)
+                        ));
+                    }
+                }
+            }
+        }
+
+        self.warning_count += warnings.len() as u32;
+        warnings
+    }
+
+    pub fn warning_count(&self) -> u32 {
+        self.warning_count
+    }
+}
+
+/* ------------------------------------------------------------------------------------------------
+ * Traits And Default Implementations
+ * --------------------------------------------------------------------------------------------- */
+
+//TODO
+
+/* ------------------------------------------------------------------------------------------------
+ * Trait Implementations
+ * --------------------------------------------------------------------------------------------- */
+
+//TODO
+
+/* ------------------------------------------------------------------------------------------------
+ * Functions
+ * --------------------------------------------------------------------------------------------- */
+
+//TODO
+
+/* ------------------------------------------------------------------------------------------------
+ * Tests
+ * --------------------------------------------------------------------------------------------- */
+
+//TODO
+
+/* ------------------------------------------------------------------------------------------------
+ * Benchmarks
+ * --------------------------------------------------------------------------------------------- */
+
+//TODO
@@ -0,0 +1,300 @@
+/*
+ * File:    lockstep.rs
+ * Brief:   Checks a simulation against a golden trace as it runs, through a C ABI (DPI-C compatible)
+ *
+ * Copyright (C) 2024 John Jekel
+ * See the LICENSE file at the root of the project for licensing info.
+ *
+ * Rather than simulating the whole program, dumping a trace and then running pd6simdiff on it, a
+ * testbench can link against this library (it's built as a cdylib too) and push each cycle's stage
+ * values in as it goes. Once a cycle is over the testbench asks for a verdict, which comes from the
+ * same comparison pd6simdiff does (see compare.rs) and so prints the same report, and it can stop the
+ * simulation at the first cycle with errors instead of wading through thousands of lines of trace.
+ *
+ * From SystemVerilog, the functions are imported with `import "DPI-C"` (c/pd_lockstep.sv has the
+ * declarations), with a chandle for the Lockstep and `int unsigned` for every value; from C, include
+ * c/pd_lockstep.h. Everything is 2-state, so there's no way to push an X or Z.
+ *
+ * If the pipeline layout needs to look ahead (ex. imemory latency), the verdict for a cycle only comes
+ * once that many more cycles have been pushed, so it's for a cycle a little while ago; the report says
+ * which one.
+ *
+*/
+
+/*!
+ * Checks a simulation against a golden trace as it runs, through a C ABI (DPI-C compatible)
+*/
+
+/* ------------------------------------------------------------------------------------------------
+ * Submodules
+ * --------------------------------------------------------------------------------------------- */
+
+//TODO (includes "mod ..." and "pub mod ...")
+
+/* ------------------------------------------------------------------------------------------------
+ * Uses
+ * --------------------------------------------------------------------------------------------- */
+
+use crate::{ParsedLineIterator, ParsedLine, Stamp, BENCHMARK_HEADER};
+use crate::cli::CommandLine;
+use crate::compare::{CompareOptions, SimComparer};
+use crate::cycle::{Cycle, CycleBuilder, CycleIterator};
+use crate::logic::Logic;
+
+use std::ffi::{c_char, c_int, CStr};
+
+/* ------------------------------------------------------------------------------------------------
+ * Macros
+ * --------------------------------------------------------------------------------------------- */
+
+//TODO (also pub(crate) use the_macro statements here too)
+
+/* ------------------------------------------------------------------------------------------------
+ * Constants
+ * --------------------------------------------------------------------------------------------- */
+
+//Returned by pd_lockstep_cycle() once there's nothing more to check (the test is over or the golden
+//trace ran out), so the testbench can finish
+pub const LOCKSTEP_DONE: c_int = -1;
+
+//Returned by pd_lockstep_cycle() if it was given a null handle (ex. pd_lockstep_open() failed)
+pub const LOCKSTEP_NO_HANDLE: c_int = -2;
+
+//The same options pd6simdiff takes, minus the ones about trace files
+const VALUE_OPTIONS:    [&str; 7] = ["--rules", "--elf", "--objdump", "--machine", "--end-of-test", "--predictor", "--pipeline"];
+const FLAG_OPTIONS:     [&str; 1] = ["--lint"];
+
+/* ------------------------------------------------------------------------------------------------
+ * Static Variables
+ * --------------------------------------------------------------------------------------------- */
+
+//TODO
+
+/* ------------------------------------------------------------------------------------------------
+ * Types
+ * --------------------------------------------------------------------------------------------- */
+
+pub struct Lockstep {
+    comparer:       SimComparer,
+    golden_cycles:  Vec<Cycle>,
+    test_cycles:    Vec<Cycle>,//Pushed but not checked yet (more than one if the layout looks ahead)
+    current:        CycleBuilder,//The cycle the testbench is pushing lines for right now
+    line_num:       usize,//Lines pushed so far, so reports can say "lines 7 thru 12" like for a trace file
+    checked:        usize,//Cycles checked so far
+    done:           bool,
+}
+
+/* ------------------------------------------------------------------------------------------------
+ * Associated Functions and Methods
+ * --------------------------------------------------------------------------------------------- */
+
+impl Lockstep {
+    //options are the same as pd6simdiff's, ex. "--pipeline pd6 --elf path/to/benchmark.elf"
+    pub fn open(golden_path: &str, options: &str) -> Result<Lockstep, String> {
+        println!("Lockstep checking against golden trace \x1b[1;33m{}\x1b[0m", golden_path);
+
+        let command_line = CommandLine::parse(options.split_whitespace().map(String::from), &VALUE_OPTIONS, &FLAG_OPTIONS)?;
+        if let Some(positional) = command_line.positional().first() {
+            return Err(format!("Unexpected argument {} (the golden trace is passed separately)", positional));
+        }
+        let options = CompareOptions::from_command_line(&command_line, "pd6")?;
+
+        let golden_trace = ParsedLineIterator::from_path(golden_path)?;
+        if let Some(benchmark) = golden_trace.header(BENCHMARK_HEADER) {
+            println!("Benchmark: \x1b[1;36m{}\x1b[0m", benchmark);
+        }
+
+        //A bad line has to come back as an error; a panic can't unwind out through the C ABI, so it would
+        //abort the whole simulation
+        let golden_lines = golden_trace.read_stamped()?;
+
+        Ok(Lockstep {
+            comparer:       SimComparer::new("pd_lockstep_open", options),
+            golden_cycles:  CycleIterator::new(golden_lines.into_iter()).collect(),
+            test_cycles:    Vec::new(),
+            current:        CycleBuilder::new(1, Stamp::default()),
+            line_num:       0,
+            checked:        0,
+            done:           false,
+        })
+    }
+
+    //A line of the cycle being simulated; lines can come in any order
+    pub fn push(&mut self, line: ParsedLine) {
+        self.current.push(line);
+        self.line_num += 1;
+    }
+
+    //Ends the cycle being simulated, returning the number of errors on the cycle that was checked (if any
+    //were, with lookahead it can take a few cycles), or LOCKSTEP_DONE once there's nothing more to check
+    pub fn end_cycle(&mut self) -> c_int {
+        if self.done {
+            return LOCKSTEP_DONE;
+        }
+
+        let next = CycleBuilder::new(self.line_num + 1, Stamp::default());
+        self.test_cycles.push(std::mem::replace(&mut self.current, next).build(None));
+
+        let window_size = self.comparer.window_size();
+        if self.test_cycles.len() < window_size {
+            return 0;
+        }
+        let Some(golden_window) = self.golden_cycles.get(self.checked..(self.checked + window_size)) else {
+            self.done = true;//Out of golden trace
+            return LOCKSTEP_DONE;
+        };
+
+        let verdict = self.comparer.check_cycle(golden_window, &self.test_cycles[..window_size]);
+        self.test_cycles.remove(0);
+        self.checked += 1;
+        self.done = verdict.end_of_test;
+
+        match verdict.error_count {
+            0 if self.done  => LOCKSTEP_DONE,
+            error_count     => error_count as c_int,
+        }
+    }
+
+    //Prints the summary, returning the total number of errors
+    pub fn finish(self) -> u32 {
+        let error_count = self.comparer.finish();
+        if error_count > 0 {
+            println!("\x1b[1;31mFound {} error(s)!\x1b[0m", error_count);
+        } else {
+            println!("\x1b[1;32mNo errors found in the {} cycle(s) checked!\x1b[0m", self.checked);
+        }
+        error_count
+    }
+}
+
+/* ------------------------------------------------------------------------------------------------
+ * Traits And Default Implementations
+ * --------------------------------------------------------------------------------------------- */
+
+//TODO
+
+/* ------------------------------------------------------------------------------------------------
+ * Trait Implementations
+ * --------------------------------------------------------------------------------------------- */
+
+//TODO
+
+/* ------------------------------------------------------------------------------------------------
+ * Functions
+ * --------------------------------------------------------------------------------------------- */
+
+/// Loads the golden trace and returns a handle for the other functions, or null if something was wrong
+/// (what is printed). `options` are the same as pd6simdiff's, ex. "--pipeline pd6", and can be null.
+///
+/// # Safety
+///
+/// `golden_path` must be a NUL-terminated string, and so must `options` unless it's null.
+#[no_mangle]
+pub unsafe extern "C" fn pd_lockstep_open(golden_path: *const c_char, options: *const c_char) -> Option<Box<Lockstep>> {
+    if golden_path.is_null() {
+        println!("\x1b[1;31mpd_lockstep_open() needs the path to a golden trace\x1b[0m");
+        return None;
+    }
+    let golden_path = CStr::from_ptr(golden_path).to_string_lossy();
+    let options     = if options.is_null() { Default::default() } else { CStr::from_ptr(options).to_string_lossy() };
+
+    match Lockstep::open(&golden_path, &options) {
+        Ok(lockstep) => Some(Box::new(lockstep)),
+        Err(e) => {
+            println!("\x1b[1;31m{}\x1b[0m", e);
+            None
+        },
+    }
+}
+
+#[no_mangle]
+pub extern "C" fn pd_lockstep_f(lockstep: Option<&mut Lockstep>, pc: u32, instr: u32) {
+    if let Some(lockstep) = lockstep {
+        lockstep.push(ParsedLine::F{pc: Logic::known(pc), instr: Logic::known(instr)});
+    }
+}
+
+#[no_mangle]
+#[allow(clippy::too_many_arguments)]//One per field, like the [D] line itself
+pub extern "C" fn pd_lockstep_d(lockstep: Option<&mut Lockstep>, pc: u32, opcode: u32, rd: u32, rs1: u32, rs2: u32, funct3: u32, funct7: u32, imm: u32, shamt: u32) {
+    if let Some(lockstep) = lockstep {
+        lockstep.push(ParsedLine::D{
+            pc:     Logic::known(pc),
+            opcode: Logic::known(opcode as u8),
+            rd:     Logic::known(rd as u8),
+            rs1:    Logic::known(rs1 as u8),
+            rs2:    Logic::known(rs2 as u8),
+            funct3: Logic::known(funct3 as u8),
+            funct7: Logic::known(funct7 as u8),
+            imm:    Logic::known(imm),
+            shamt:  Logic::known(shamt as u8),
+        });
+    }
+}
+
+#[no_mangle]
+pub extern "C" fn pd_lockstep_r(lockstep: Option<&mut Lockstep>, addr_rs1: u32, addr_rs2: u32, data_rs1: u32, data_rs2: u32) {
+    if let Some(lockstep) = lockstep {
+        lockstep.push(ParsedLine::R{
+            addr_rs1:   Logic::known(addr_rs1 as u8),
+            addr_rs2:   Logic::known(addr_rs2 as u8),
+            data_rs1:   Logic::known(data_rs1),
+            data_rs2:   Logic::known(data_rs2),
+        });
+    }
+}
+
+#[no_mangle]
+pub extern "C" fn pd_lockstep_e(lockstep: Option<&mut Lockstep>, pc: u32, alu_result: u32, branch_taken: u32) {
+    if let Some(lockstep) = lockstep {
+        lockstep.push(ParsedLine::E{pc: Logic::known(pc), alu_result: Logic::known(alu_result), branch_taken: Logic::known(branch_taken != 0)});
+    }
+}
+
+#[no_mangle]
+pub extern "C" fn pd_lockstep_m(lockstep: Option<&mut Lockstep>, pc: u32, addr: u32, read_not_write: u32, access_size: u32, memory_wdata: u32) {
+    if let Some(lockstep) = lockstep {
+        lockstep.push(ParsedLine::M{
+            pc:             Logic::known(pc),
+            addr:           Logic::known(addr),
+            read_not_write: Logic::known(read_not_write != 0),
+            access_size:    Logic::known(access_size as u8),
+            memory_wdata:   Logic::known(memory_wdata),
+        });
+    }
+}
+
+#[no_mangle]
+pub extern "C" fn pd_lockstep_w(lockstep: Option<&mut Lockstep>, pc: u32, we: u32, addr_rd: u32, data_rd: u32) {
+    if let Some(lockstep) = lockstep {
+        lockstep.push(ParsedLine::W{pc: Logic::known(pc), we: Logic::known(we != 0), addr_rd: Logic::known(addr_rd as u8), data_rd: Logic::known(data_rd)});
+    }
+}
+
+//Call once every stage's line for the cycle has been pushed; 0 means carry on, more than that is how many
+//errors were found (and reported), and LOCKSTEP_DONE means the test is over
+#[no_mangle]
+pub extern "C" fn pd_lockstep_cycle(lockstep: Option<&mut Lockstep>) -> c_int {
+    match lockstep {
+        Some(lockstep)  => lockstep.end_cycle(),
+        None            => LOCKSTEP_NO_HANDLE,
+    }
+}
+
+//Prints the summary and frees the handle, returning the total number of errors
+#[no_mangle]
+pub extern "C" fn pd_lockstep_close(lockstep: Option<Box<Lockstep>>) -> c_int {
+    lockstep.map_or(0, |lockstep| lockstep.finish() as c_int)
+}
+
+/* ------------------------------------------------------------------------------------------------
+ * Tests
+ * --------------------------------------------------------------------------------------------- */
+
+//TODO
+
+/* ------------------------------------------------------------------------------------------------
+ * Benchmarks
+ * --------------------------------------------------------------------------------------------- */
+
+//TODO
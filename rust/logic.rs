@@ -0,0 +1,337 @@
+/*
+ * File:    logic.rs
+ * Brief:   Four-state (0/1/X/Z) values as printed by Verilog's $display
+ *
+ * Copyright (C) 2024 John Jekel
+ * See the LICENSE file at the root of the project for licensing info.
+ *
+ * An undriven or uninitialised signal shows up in a trace as `x`/`z` digits (or `X`/`Z` when
+ * only some of the bits in that hex digit are unknown). Rather than falling over when we see
+ * one, every trace field is a Logic<T>: the known bits plus masks of which bits are X or Z.
+ * We can't tell which bits of an uppercase `X` digit are the unknown ones, so we
+ * conservatively treat the whole digit as unknown.
+ *
+*/
+
+/*!
+ * Four-state (0/1/X/Z) values as printed by Verilog's $display
+*/
+
+/* ------------------------------------------------------------------------------------------------
+ * Submodules
+ * --------------------------------------------------------------------------------------------- */
+
+//TODO (includes "mod ..." and "pub mod ...")
+
+/* ------------------------------------------------------------------------------------------------
+ * Uses
+ * --------------------------------------------------------------------------------------------- */
+
+use riscv_tools::*;
+
+use std::fmt::{Display, LowerHex};
+
+/* ------------------------------------------------------------------------------------------------
+ * Macros
+ * --------------------------------------------------------------------------------------------- */
+
+//TODO (also pub(crate) use the_macro statements here too)
+
+/* ------------------------------------------------------------------------------------------------
+ * Constants
+ * --------------------------------------------------------------------------------------------- */
+
+//TODO
+
+/* ------------------------------------------------------------------------------------------------
+ * Static Variables
+ * --------------------------------------------------------------------------------------------- */
+
+//TODO
+
+/* ------------------------------------------------------------------------------------------------
+ * Types
+ * --------------------------------------------------------------------------------------------- */
+
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct Logic<T: LogicBits> {
+    value:  T,//X and Z bits read as 0
+    x_mask: u32,
+    z_mask: u32,
+}
+
+/* ------------------------------------------------------------------------------------------------
+ * Associated Functions and Methods
+ * --------------------------------------------------------------------------------------------- */
+
+impl<T: LogicBits> Logic<T> {
+    pub fn known(value: T) -> Logic<T> {
+        Logic {
+            value,
+            x_mask: 0,
+            z_mask: 0,
+        }
+    }
+
+    //Accepts upper or lowercase hex digits, x/X and z/Z (and ? as a synonym for z), with optional `_` separators
+    pub fn from_hex(token: &str) -> Option<Logic<T>> {
+        let width_mask = T::width_mask();
+        let mut bits    = 0u32;
+        let mut x_mask  = 0u32;
+        let mut z_mask  = 0u32;
+
+        let digits = token.strip_prefix("0x").unwrap_or(token).chars().rev().filter(|c| *c != '_');
+        let mut digit_count = 0;
+        for (nibble_num, digit) in digits.enumerate() {
+            digit_count += 1;
+            let shift = nibble_num as u32 * 4;
+            let (nibble_bits, nibble_x, nibble_z) = match digit {
+                'x' | 'X'       => (0, 0xf, 0),
+                'z' | 'Z' | '?' => (0, 0, 0xf),
+                _               => (digit.to_digit(16)?, 0, 0),
+            };
+
+            if shift >= 32 {
+                //Only leading zeros are allowed past the end of a u32
+                if (nibble_bits != 0) || (nibble_x != 0) || (nibble_z != 0) {
+                    return None;
+                }
+                continue;
+            }
+
+            bits    |= nibble_bits << shift;
+            x_mask  |= nibble_x << shift;
+            z_mask  |= nibble_z << shift;
+        }
+
+        if digit_count == 0 {
+            return None;//Ex. "0x" or "_", which shouldn't quietly become a known 0
+        }
+
+        if (bits & !width_mask) != 0 {
+            return None;//Too big for the field
+        }
+
+        Some(Logic {
+            value:  T::from_bits(bits),
+            x_mask: x_mask & width_mask,
+            z_mask: z_mask & width_mask,
+        })
+    }
+
+    pub fn value(&self) -> T {
+        self.value
+    }
+
+    pub fn x_mask(&self) -> u32 {
+        self.x_mask
+    }
+
+    pub fn z_mask(&self) -> u32 {
+        self.z_mask
+    }
+
+    pub fn is_known(&self) -> bool {
+        (self.x_mask | self.z_mask) == 0
+    }
+
+    //For code that doesn't care about the field's original width
+    pub fn widen(&self) -> Logic<u32> {
+        Logic {
+            value:  self.value.to_bits(),
+            x_mask: self.x_mask,
+            z_mask: self.z_mask,
+        }
+    }
+
+    //Never hide set bits, even if the requested width is too small
+    fn min_digits(&self) -> usize {
+        let all_bits = self.value.to_bits() | self.x_mask | self.z_mask;
+        (32 - all_bits.leading_zeros()).div_ceil(4).max(1) as usize
+    }
+
+    fn fmt_digits(&self, f: &mut std::fmt::Formatter<'_>, num_digits: usize) -> std::fmt::Result {
+        let bits = self.value.to_bits();
+        for nibble_num in (0..num_digits).rev() {
+            if nibble_num >= 8 {
+                write!(f, "0")?;
+                continue;
+            }
+
+            let shift       = nibble_num as u32 * 4;
+            let nibble_mask = (0xf << shift) & T::width_mask();
+            let digit = if (self.x_mask & nibble_mask) == nibble_mask && nibble_mask != 0 {
+                'x'
+            } else if (self.x_mask & nibble_mask) != 0 {
+                'X'
+            } else if (self.z_mask & nibble_mask) == nibble_mask && nibble_mask != 0 {
+                'z'
+            } else if (self.z_mask & nibble_mask) != 0 {
+                'Z'
+            } else {
+                char::from_digit((bits >> shift) & 0xf, 16).unwrap()
+            };
+            write!(f, "{}", digit)?;
+        }
+        Ok(())
+    }
+}
+
+/* ------------------------------------------------------------------------------------------------
+ * Traits And Default Implementations
+ * --------------------------------------------------------------------------------------------- */
+
+pub trait LogicBits: Copy + Default + PartialEq + std::fmt::Debug {
+    const BITS: u32;
+
+    fn from_bits(bits: u32) -> Self;
+    fn to_bits(self) -> u32;
+
+    fn width_mask() -> u32 {
+        if Self::BITS >= 32 {
+            u32::MAX
+        } else {
+            (1 << Self::BITS) - 1
+        }
+    }
+}
+
+/* ------------------------------------------------------------------------------------------------
+ * Trait Implementations
+ * --------------------------------------------------------------------------------------------- */
+
+impl LogicBits for u32 {
+    const BITS: u32 = 32;
+
+    fn from_bits(bits: u32) -> Self {
+        bits
+    }
+
+    fn to_bits(self) -> u32 {
+        self
+    }
+}
+
+impl LogicBits for u8 {
+    const BITS: u32 = 8;
+
+    fn from_bits(bits: u32) -> Self {
+        bits as u8
+    }
+
+    fn to_bits(self) -> u32 {
+        self as u32
+    }
+}
+
+impl LogicBits for bool {
+    const BITS: u32 = 1;
+
+    fn from_bits(bits: u32) -> Self {
+        bits != 0
+    }
+
+    fn to_bits(self) -> u32 {
+        self as u32
+    }
+}
+
+//Only equal if every bit is known and matches
+impl<T: LogicBits> PartialEq<T> for Logic<T> {
+    fn eq(&self, other: &T) -> bool {
+        self.is_known() && (self.value == *other)
+    }
+}
+
+impl<T: LogicBits> From<T> for Logic<T> {
+    fn from(value: T) -> Self {
+        Logic::known(value)
+    }
+}
+
+//Unknown bits of an instruction word decode as 0s (the checkers report the X separately)
+impl From<Logic<u32>> for Instruction {
+    fn from(instr: Logic<u32>) -> Self {
+        instr.value().into()
+    }
+}
+
+//Natural width for the field (ex. 8 digits for a u32, 1 for a bool)
+impl<T: LogicBits> Display for Logic<T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.fmt_digits(f, T::BITS.div_ceil(4) as usize)
+    }
+}
+
+//Respects the width (ex. `{:08x}`), otherwise the natural width
+impl<T: LogicBits> LowerHex for Logic<T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let num_digits = f.width().unwrap_or(T::BITS.div_ceil(4) as usize).max(self.min_digits());
+        self.fmt_digits(f, num_digits)
+    }
+}
+
+/* ------------------------------------------------------------------------------------------------
+ * Functions
+ * --------------------------------------------------------------------------------------------- */
+
+//TODO
+
+/* ------------------------------------------------------------------------------------------------
+ * Tests
+ * --------------------------------------------------------------------------------------------- */
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn from_hex_known() {
+        assert_eq!(Logic::<u32>::from_hex("0100abCD"), Some(Logic::known(0x0100abcd)));
+        assert_eq!(Logic::<u32>::from_hex("0x0100_abcd"), Some(Logic::known(0x0100abcd)));
+        assert_eq!(Logic::<u32>::from_hex("0"), Some(Logic::known(0)));
+        assert_eq!(Logic::<u8>::from_hex("1f"), Some(Logic::known(0x1f)));
+        assert_eq!(Logic::<bool>::from_hex("1"), Some(Logic::known(true)));
+    }
+
+    #[test]
+    fn from_hex_x_and_z() {
+        let logic = Logic::<u32>::from_hex("01x0Z0?5").unwrap();
+        assert!(!logic.is_known());
+        assert_eq!(logic.value(), 0x01000005);
+        assert_eq!(logic.x_mask(), 0x00f00000);
+        assert_eq!(logic.z_mask(), 0x0000f0f0);
+
+        //Only the field's own bits can be X/Z
+        assert_eq!(Logic::<bool>::from_hex("x").unwrap().x_mask(), 0x1);
+    }
+
+    #[test]
+    fn from_hex_leading_zeros() {
+        assert_eq!(Logic::<u32>::from_hex("00000000ffffffff"), Some(Logic::known(0xffffffff)));
+        assert_eq!(Logic::<u32>::from_hex("100000000"), None);
+        assert_eq!(Logic::<u32>::from_hex("x00000000"), None);
+    }
+
+    #[test]
+    fn from_hex_too_big_for_field() {
+        assert_eq!(Logic::<u8>::from_hex("100"), None);
+        assert_eq!(Logic::<bool>::from_hex("2"), None);
+    }
+
+    #[test]
+    fn from_hex_rejects_empty_and_bad_digits() {
+        assert_eq!(Logic::<u32>::from_hex(""), None);
+        assert_eq!(Logic::<u32>::from_hex("0x"), None);
+        assert_eq!(Logic::<u32>::from_hex("_"), None);
+        assert_eq!(Logic::<u32>::from_hex("0x__"), None);
+        assert_eq!(Logic::<u32>::from_hex("12g4"), None);
+        assert_eq!(Logic::<u32>::from_hex("-1"), None);
+    }
+}
+
+/* ------------------------------------------------------------------------------------------------
+ * Benchmarks
+ * --------------------------------------------------------------------------------------------- */
+
+//TODO
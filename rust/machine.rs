@@ -0,0 +1,388 @@
+/*
+ * File:    machine.rs
+ * Brief:   Reset state and memory map of the core that produced a trace
+ *
+ * Copyright (C) 2024 John Jekel
+ * See the LICENSE file at the root of the project for licensing info.
+ *
+ * Everything used to assume memory starts at 0x01000000 with MEM_DEPTH 0x00100000, that the
+ * core resets to the start of memory with sp at the top of it, and that a program is done when
+ * the PC becomes 0 or we fetch an all-zero word. That's true for the course testbench, but not
+ * for every FPGA build, so all of it can be overridden with a machine config file like:
+ *
+ *     #Anything after a # is a comment
+ *     mem_base    = 01000000
+ *     mem_size    = 00040000      #MEM_DEPTH
+ *     reset_pc    = 01000000
+ *     sp          = 01040000      #Or x2; any register not given resets to 0 (except sp, see below)
+ *     end_of_test = pc:00000000, instr:00000000
+ *     traps       = off           #on if your core takes traps instead of treating ecall as a NOP
+ *     branch_predictor = not-taken #Or backward-taken or infer (see predictor.rs)
+ *     mtvec       = 00000000      #What mtvec resets to (only matters with traps on)
+ *
+ * Numbers are in hex (except cycle counts). If sp isn't given it resets to mem_base + mem_size,
+ * like the testbench.
+ *
+ * end_of_test is a comma-separated list of conditions, any of which ends the test:
+ *
+ *     pc:<addr>        The golden trace fetches from addr (the course default is pc:00000000)
+ *     symbol:<name>    The golden trace fetches from that symbol (needs --elf or --objdump)
+ *     instr:<word>     The golden trace fetches that instruction word (the default is instr:00000000)
+ *     ecall, ebreak    An ecall/ebreak retires (ie. reaches [W]); that cycle is still checked
+ *     tohost:<addr>    A store to addr (ex. riscv-tests' tohost) reaches [M]; that cycle is still checked
+ *     cycles:<n>       After n cycles (in decimal)
+ *     eof              Only the end of the trace (which always ends the test anyways)
+ *
+ * `irvedecoder` understands the same file format (minus end_of_test, traps, mtvec and branch_predictor, which it ignores).
+ *
+*/
+
+/*!
+ * Reset state and memory map of the core that produced a trace
+*/
+
+/* ------------------------------------------------------------------------------------------------
+ * Submodules
+ * --------------------------------------------------------------------------------------------- */
+
+//TODO (includes "mod ..." and "pub mod ...")
+
+/* ------------------------------------------------------------------------------------------------
+ * Uses
+ * --------------------------------------------------------------------------------------------- */
+
+use crate::predictor::PredictorMode;
+use crate::symbols::SymbolTable;
+
+use std::path::Path;
+
+/* ------------------------------------------------------------------------------------------------
+ * Macros
+ * --------------------------------------------------------------------------------------------- */
+
+//TODO (also pub(crate) use the_macro statements here too)
+
+/* ------------------------------------------------------------------------------------------------
+ * Constants
+ * --------------------------------------------------------------------------------------------- */
+
+pub const DEFAULT_MEM_BASE: u32 = 0x01000000;
+pub const DEFAULT_MEM_SIZE: u32 = 0x00100000;//`MEM_DEPTH in the testbench
+
+const ECALL_WORD:   u32 = 0x00000073;
+const EBREAK_WORD:  u32 = 0x00100073;
+
+const ABI_REGISTER_NAMES: [&str; 32] = [
+    "zero", "ra", "sp", "gp", "tp", "t0", "t1", "t2",
+    "s0", "s1", "a0", "a1", "a2", "a3", "a4", "a5",
+    "a6", "a7", "s2", "s3", "s4", "s5", "s6", "s7",
+    "s8", "s9", "s10", "s11", "t3", "t4", "t5", "t6",
+];
+
+/* ------------------------------------------------------------------------------------------------
+ * Static Variables
+ * --------------------------------------------------------------------------------------------- */
+
+//TODO
+
+/* ------------------------------------------------------------------------------------------------
+ * Types
+ * --------------------------------------------------------------------------------------------- */
+
+#[derive(Debug, Clone)]
+pub struct MachineConfig {
+    pub reset_pc:       u32,
+    pub mem_base:       u32,
+    pub mem_size:       u32,
+    pub init_regs:      [u32; 32],
+    pub end_of_test:    Vec<EndCondition>,//The test is over as soon as any of these happen
+    pub traps:          bool,//Whether ecall, illegal instructions, etc. trap to mtvec (see csr.rs)
+    pub mtvec:          u32,
+    pub predictor:      PredictorMode,
+}
+
+//The first three are checked against the golden trace's fetch stage before a cycle is compared,
+//and the rest after a cycle is compared
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum EndCondition {
+    Pc(u32),
+    Symbol{name: String, addr: Option<u32>},//addr is filled in by resolve_symbols()
+    InstrWord(u32),
+    Ecall,
+    Ebreak,
+    ToHost(u32),
+    Cycles(usize),
+    Eof,
+}
+
+/* ------------------------------------------------------------------------------------------------
+ * Associated Functions and Methods
+ * --------------------------------------------------------------------------------------------- */
+
+impl MachineConfig {
+    pub fn from_path(path: impl AsRef<Path>) -> Result<MachineConfig, String> {
+        let contents = std::fs::read_to_string(path.as_ref()).map_err(|e| e.to_string())?;
+        MachineConfig::parse(&contents)
+    }
+
+    //What --machine, --end-of-test and --predictor ask for, with any symbol:<name> end conditions looked up.
+    //The options override what the file says.
+    pub fn from_options(path: Option<&str>, end_of_test: Option<&str>, predictor: Option<&str>, symbols: &SymbolTable) -> Result<MachineConfig, String> {
+        let mut machine = match path {
+            Some(path) => {
+                let machine = MachineConfig::from_path(path).map_err(|e| format!("Error loading machine config at path {}: {}", path, e))?;
+                println!("Using machine config from \x1b[1;36m{}\x1b[0m", path);
+                machine
+            },
+            None => MachineConfig::default(),
+        };
+
+        if let Some(end_of_test) = end_of_test {
+            machine.end_of_test = EndCondition::parse_list(end_of_test).map_err(|e| format!("Bad --end-of-test: {}", e))?;
+        }
+
+        if let Some(predictor) = predictor {
+            machine.predictor = PredictorMode::from_name(predictor).ok_or(format!("Bad --predictor \"{}\": expected not-taken, backward-taken or infer", predictor))?;
+        }
+
+        machine.resolve_symbols(symbols)?;
+        Ok(machine)
+    }
+
+    //Anything not in the file keeps its default
+    pub fn parse(contents: &str) -> Result<MachineConfig, String> {
+        let mut config  = MachineConfig::default();
+        let mut sp      = None;
+
+        for (line_num, line) in contents.lines().enumerate() {
+            let line = line.split('#').next().unwrap().trim();
+            if line.is_empty() {
+                continue;
+            }
+
+            let Some((key, value)) = line.split_once('=') else {
+                return Err(format!("Line {}: expected `<key> = <value>`, got \"{}\"", line_num + 1, line));
+            };
+            let (key, value) = (key.trim().to_lowercase(), value.trim());
+            let hex = || parse_hex(value).ok_or(format!("Line {}: bad hex number \"{}\"", line_num + 1, value));
+
+            //irvedecoder/main.cpp's load_machine_config() reads the same files, so keep its list of keys in sync
+            match key.as_str() {
+                "reset_pc"                  => config.reset_pc = hex()?,
+                "mem_base"                  => config.mem_base = hex()?,
+                "mem_size" | "mem_depth"    => config.mem_size = hex()?,
+                "traps"                     => {
+                    config.traps = parse_bool(value).ok_or(format!("Line {}: expected on or off, got \"{}\"", line_num + 1, value))?;
+                },
+                "mtvec"                     => config.mtvec = hex()?,
+                "branch_predictor"          => {
+                    config.predictor = PredictorMode::from_name(value).ok_or(format!("Line {}: unknown branch predictor \"{}\"", line_num + 1, value))?;
+                },
+                "end_of_test"               => {
+                    config.end_of_test = EndCondition::parse_list(value).map_err(|e| format!("Line {}: {}", line_num + 1, e))?;
+                },
+                register => {
+                    let Some(register) = register_index(register) else {
+                        return Err(format!("Line {}: unknown setting \"{}\"", line_num + 1, key));
+                    };
+                    if register == 0 {
+                        return Err(format!("Line {}: x0 is always 0", line_num + 1));
+                    }
+                    if register == 2 {
+                        sp = Some(hex()?);
+                    } else {
+                        config.init_regs[register] = hex()?;
+                    }
+                },
+            }
+        }
+
+        config.init_regs[2] = sp.unwrap_or(config.mem_base.wrapping_add(config.mem_size));
+        Ok(config)
+    }
+
+    pub fn mem_contains(&self, addr: u32) -> bool {
+        addr.wrapping_sub(self.mem_base) < self.mem_size
+    }
+
+    //Looks up the addresses of any symbol:<name> end conditions
+    pub fn resolve_symbols(&mut self, symbols: &SymbolTable) -> Result<(), String> {
+        for condition in self.end_of_test.iter_mut() {
+            if let EndCondition::Symbol{name, addr} = condition {
+                *addr = Some(symbols.addr_of(name).ok_or(format!("No symbol named \"{}\" for the end-of-test condition (did you pass --elf or --objdump?)", name))?);
+            }
+        }
+        Ok(())
+    }
+
+    //Which end condition (if any) the golden trace's fetch stage just hit, in which case this cycle
+    //shouldn't be compared. `fetch_instr` is None if the instruction word isn't known (yet), in which
+    //case only the PC is checked.
+    pub fn end_before_cycle(&self, fetch_pc: u32, fetch_instr: Option<u32>) -> Option<&EndCondition> {
+        self.end_of_test.iter().find(|condition| match condition {
+            EndCondition::Pc(pc)                => fetch_pc == *pc,
+            EndCondition::Symbol{addr, ..}      => *addr == Some(fetch_pc),
+            EndCondition::InstrWord(word)       => fetch_instr == Some(*word),
+            _                                   => false,
+        })
+    }
+
+    //Which end condition (if any) was hit by the cycle that was just compared. `cycle` is one-indexed,
+    //`retired_instr` is the word of the golden instruction in [W] (if any), and `store_addr` is the
+    //address of the golden store in [M] (if any).
+    pub fn end_after_cycle(&self, cycle: usize, retired_instr: Option<u32>, store_addr: Option<u32>) -> Option<&EndCondition> {
+        self.end_of_test.iter().find(|condition| match condition {
+            EndCondition::Ecall             => retired_instr == Some(ECALL_WORD),
+            EndCondition::Ebreak            => retired_instr == Some(EBREAK_WORD),
+            EndCondition::ToHost(tohost)    => store_addr == Some(*tohost),
+            EndCondition::Cycles(cycles)    => cycle >= *cycles,
+            _                               => false,
+        })
+    }
+
+    //ex. "pc:00000000, instr:00000000", for recording in reports
+    pub fn end_of_test_policy(&self) -> String {
+        self.end_of_test.iter().map(|condition| condition.to_string()).collect::<Vec<_>>().join(", ")
+    }
+}
+
+impl EndCondition {
+    //ex. "pc:00000000, ecall, cycles:5000"
+    pub fn parse_list(s: &str) -> Result<Vec<EndCondition>, String> {
+        s.split(',').map(|condition| {
+            EndCondition::from_str(condition.trim()).ok_or(format!("unknown end-of-test condition \"{}\"", condition.trim()))
+        }).collect()
+    }
+
+    fn from_str(s: &str) -> Option<EndCondition> {
+        let (kind, argument) = s.split_once(':').map_or((s, None), |(kind, argument)| (kind, Some(argument.trim())));
+        match (kind.trim(), argument) {
+            ("pc",      Some(argument)) => Some(EndCondition::Pc(parse_hex(argument)?)),
+            ("symbol",  Some(argument)) => Some(EndCondition::Symbol{name: argument.to_string(), addr: None}),
+            ("instr",   Some(argument)) => Some(EndCondition::InstrWord(parse_hex(argument)?)),
+            ("ecall",   None)           => Some(EndCondition::Ecall),
+            ("ebreak",  None)           => Some(EndCondition::Ebreak),
+            ("tohost",  Some(argument)) => Some(EndCondition::ToHost(parse_hex(argument)?)),
+            ("cycles",  Some(argument)) => Some(EndCondition::Cycles(argument.parse().ok()?)),
+            ("eof",     None)           => Some(EndCondition::Eof),
+            _                           => None,
+        }
+    }
+
+    //ex. "PC in golden trace became 00000000", for when this condition ends the test
+    pub fn reason(&self) -> String {
+        match self {
+            EndCondition::Pc(pc)                => format!("PC in golden trace became {:08x}", pc),
+            EndCondition::Symbol{name, addr}    => format!("PC in golden trace reached `{}` ({:08x})", name, addr.unwrap_or_default()),
+            EndCondition::InstrWord(0)          => String::from("Encountered illegal instruction in golden trace"),
+            EndCondition::InstrWord(word)       => format!("Fetched instruction word {:08x} in golden trace", word),
+            EndCondition::Ecall                 => String::from("An ecall retired in golden trace"),
+            EndCondition::Ebreak                => String::from("An ebreak retired in golden trace"),
+            EndCondition::ToHost(tohost)        => format!("Golden trace stored to tohost ({:08x})", tohost),
+            EndCondition::Cycles(cycles)        => format!("Compared {} cycle(s)", cycles),
+            EndCondition::Eof                   => String::from("Reached the end of the trace"),
+        }
+    }
+
+    //Why this condition is (usually) a sensible way to detect the end of the course's benchmarks
+    pub fn explanation(&self) -> Option<&'static str> {
+        match self {
+            EndCondition::Pc(0) => Some(concat!(
+                "(This is expected for simple-programs golden traces, since if you\n",
+                "look at their assembly, when they return from main, since `ra` is initialized\n",
+                "to 0 by our hardware, but never by their code, the PC naturally becomes 0.\n",
+                "Technically a bug in their test programs, but it's a nice end-of-code flag for us!)"
+            )),
+            EndCondition::InstrWord(0) => Some(concat!(
+                "(This is expected for individual-instruction golden traces, since we simply\n",
+                "implement ecall as a NOP, and since these traces end in an ecall, we thus run\n",
+                "into the data afterwards in memory, interpreting it as an instruction)"
+            )),
+            _ => None,
+        }
+    }
+}
+
+/* ------------------------------------------------------------------------------------------------
+ * Traits And Default Implementations
+ * --------------------------------------------------------------------------------------------- */
+
+//TODO
+
+/* ------------------------------------------------------------------------------------------------
+ * Trait Implementations
+ * --------------------------------------------------------------------------------------------- */
+
+//The course testbench
+impl Default for MachineConfig {
+    fn default() -> Self {
+        let mut init_regs = [0; 32];
+        init_regs[2] = DEFAULT_MEM_BASE + DEFAULT_MEM_SIZE;
+
+        Self {
+            reset_pc:       DEFAULT_MEM_BASE,
+            mem_base:       DEFAULT_MEM_BASE,
+            mem_size:       DEFAULT_MEM_SIZE,
+            init_regs,
+            end_of_test:    vec![EndCondition::Pc(0), EndCondition::InstrWord(0)],
+            traps:          false,
+            mtvec:          0,
+            predictor:      PredictorMode::NotTaken,
+        }
+    }
+}
+
+//The same syntax as in the machine config file
+impl std::fmt::Display for EndCondition {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            EndCondition::Pc(pc)            => write!(f, "pc:{:08x}", pc),
+            EndCondition::Symbol{name, ..}  => write!(f, "symbol:{}", name),
+            EndCondition::InstrWord(word)   => write!(f, "instr:{:08x}", word),
+            EndCondition::Ecall             => write!(f, "ecall"),
+            EndCondition::Ebreak            => write!(f, "ebreak"),
+            EndCondition::ToHost(tohost)    => write!(f, "tohost:{:08x}", tohost),
+            EndCondition::Cycles(cycles)    => write!(f, "cycles:{}", cycles),
+            EndCondition::Eof               => write!(f, "eof"),
+        }
+    }
+}
+
+/* ------------------------------------------------------------------------------------------------
+ * Functions
+ * --------------------------------------------------------------------------------------------- */
+
+fn parse_hex(s: &str) -> Option<u32> {
+    u32::from_str_radix(s.strip_prefix("0x").unwrap_or(s).replace('_', "").as_str(), 16).ok()
+}
+
+fn parse_bool(s: &str) -> Option<bool> {
+    match s.to_lowercase().as_str() {
+        "on" | "true" | "yes" | "1"     => Some(true),
+        "off" | "false" | "no" | "0"    => Some(false),
+        _                               => None,
+    }
+}
+
+//Accepts x0-x31 and ABI names (plus fp for s0)
+fn register_index(name: &str) -> Option<usize> {
+    if let Some(number) = name.strip_prefix('x') {
+        return number.parse().ok().filter(|index| *index < 32);
+    }
+    if name == "fp" {
+        return Some(8);
+    }
+    ABI_REGISTER_NAMES.iter().position(|abi_name| *abi_name == name)
+}
+
+/* ------------------------------------------------------------------------------------------------
+ * Tests
+ * --------------------------------------------------------------------------------------------- */
+
+//TODO
+
+/* ------------------------------------------------------------------------------------------------
+ * Benchmarks
+ * --------------------------------------------------------------------------------------------- */
+
+//TODO
@@ -0,0 +1,182 @@
+/*
+ * File:    muldiv.rs
+ * Brief:   RV32M (multiply/divide) decoding, disassembly and semantics
+ *
+ * Copyright (C) 2024 John Jekel
+ * See the LICENSE file at the root of the project for licensing info.
+ *
+ * riscv_tools only knows the base ISA, so the M extension is handled here instead. The
+ * instructions are all R-type with opcode OP and funct7 0000001, and funct3 selecting which one.
+ *
+ * The checkers expect a multi-cycle multiplier/divider to hold the instruction in [E] (ie. the
+ * golden [E] line keeps the same PC) until it's done, with bubbles going into [M] meanwhile.
+ *
+*/
+
+/*!
+ * RV32M (multiply/divide) decoding, disassembly and semantics
+*/
+
+/* ------------------------------------------------------------------------------------------------
+ * Submodules
+ * --------------------------------------------------------------------------------------------- */
+
+//TODO (includes "mod ..." and "pub mod ...")
+
+/* ------------------------------------------------------------------------------------------------
+ * Uses
+ * --------------------------------------------------------------------------------------------- */
+
+use riscv_tools::*;
+
+/* ------------------------------------------------------------------------------------------------
+ * Macros
+ * --------------------------------------------------------------------------------------------- */
+
+//TODO (also pub(crate) use the_macro statements here too)
+
+/* ------------------------------------------------------------------------------------------------
+ * Constants
+ * --------------------------------------------------------------------------------------------- */
+
+const OPCODE_OP:        u32 = 0b0110011;
+const FUNCT7_MULDIV:    u32 = 0b0000001;
+
+/* ------------------------------------------------------------------------------------------------
+ * Static Variables
+ * --------------------------------------------------------------------------------------------- */
+
+//TODO
+
+/* ------------------------------------------------------------------------------------------------
+ * Types
+ * --------------------------------------------------------------------------------------------- */
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum MulDivOp {
+    Mul,
+    Mulh,
+    Mulhsu,
+    Mulhu,
+    Div,
+    Divu,
+    Rem,
+    Remu,
+}
+
+/* ------------------------------------------------------------------------------------------------
+ * Associated Functions and Methods
+ * --------------------------------------------------------------------------------------------- */
+
+impl MulDivOp {
+    pub fn decode(word: u32) -> Option<MulDivOp> {
+        if ((word & 0x7f) != OPCODE_OP) || ((word >> 25) != FUNCT7_MULDIV) {
+            return None;
+        }
+
+        Some(match (word >> 12) & 0b111 {
+            0b000 => MulDivOp::Mul,
+            0b001 => MulDivOp::Mulh,
+            0b010 => MulDivOp::Mulhsu,
+            0b011 => MulDivOp::Mulhu,
+            0b100 => MulDivOp::Div,
+            0b101 => MulDivOp::Divu,
+            0b110 => MulDivOp::Rem,
+            _     => MulDivOp::Remu,
+        })
+    }
+
+    pub fn of(instr: &Instruction) -> Option<MulDivOp> {
+        MulDivOp::decode(instr.assume_uncompressed())
+    }
+
+    pub const fn name(&self) -> &'static str {
+        match self {
+            MulDivOp::Mul       => "mul",
+            MulDivOp::Mulh      => "mulh",
+            MulDivOp::Mulhsu    => "mulhsu",
+            MulDivOp::Mulhu     => "mulhu",
+            MulDivOp::Div       => "div",
+            MulDivOp::Divu      => "divu",
+            MulDivOp::Rem       => "rem",
+            MulDivOp::Remu      => "remu",
+        }
+    }
+
+    //ex. "mul x10, x11, x12"
+    pub fn disassemble(word: u32) -> Option<String> {
+        let op  = MulDivOp::decode(word)?;
+        let rd  = (word >> 7) & 0x1f;
+        let rs1 = (word >> 15) & 0x1f;
+        let rs2 = (word >> 20) & 0x1f;
+        Some(format!("{} x{}, x{}, x{}", op.name(), rd, rs1, rs2))
+    }
+
+    //What the instruction should write to rd, including the spec's answers for the cases that
+    //would trap on other architectures (RISC-V never traps on these)
+    pub fn evaluate(&self, rs1: u32, rs2: u32) -> u32 {
+        let (signed_rs1, signed_rs2) = (rs1 as i32, rs2 as i32);
+        match self {
+            MulDivOp::Mul       => rs1.wrapping_mul(rs2),
+            MulDivOp::Mulh      => ((signed_rs1 as i64 * signed_rs2 as i64) >> 32) as u32,
+            MulDivOp::Mulhsu    => ((signed_rs1 as i64 * rs2 as i64) >> 32) as u32,
+            MulDivOp::Mulhu     => ((rs1 as u64 * rs2 as u64) >> 32) as u32,
+            MulDivOp::Div       => if rs2 == 0 { u32::MAX } else { signed_rs1.wrapping_div(signed_rs2) as u32 },
+            MulDivOp::Divu      => rs1.checked_div(rs2).unwrap_or(u32::MAX),
+            MulDivOp::Rem       => if rs2 == 0 { rs1 } else { signed_rs1.wrapping_rem(signed_rs2) as u32 },
+            MulDivOp::Remu      => rs1.checked_rem(rs2).unwrap_or(rs1),
+        }
+    }
+
+    //Why the result might be surprising, if it's one of the special cases
+    pub fn special_case(&self, rs1: u32, rs2: u32) -> Option<&'static str> {
+        let overflow = (rs1 == 0x80000000) && (rs2 == u32::MAX);
+        match self {
+            MulDivOp::Div | MulDivOp::Divu if rs2 == 0  => Some("dividing by zero gives all ones"),
+            MulDivOp::Rem | MulDivOp::Remu if rs2 == 0  => Some("the remainder of dividing by zero is the dividend"),
+            MulDivOp::Div if overflow                   => Some("-2^31 / -1 overflows and gives -2^31"),
+            MulDivOp::Rem if overflow                   => Some("the remainder of -2^31 / -1 is 0"),
+            _                                           => None,
+        }
+    }
+
+    //ex. "div(0000002a, 00000000) = ffffffff since dividing by zero gives all ones"
+    pub fn explain(&self, rs1: u32, rs2: u32) -> String {
+        let mut explanation = format!("{}({:08x}, {:08x}) = {:08x}", self.name(), rs1, rs2, self.evaluate(rs1, rs2));
+        if let Some(special_case) = self.special_case(rs1, rs2) {
+            explanation.push_str(" since ");
+            explanation.push_str(special_case);
+        }
+        explanation
+    }
+}
+
+/* ------------------------------------------------------------------------------------------------
+ * Traits And Default Implementations
+ * --------------------------------------------------------------------------------------------- */
+
+//TODO
+
+/* ------------------------------------------------------------------------------------------------
+ * Trait Implementations
+ * --------------------------------------------------------------------------------------------- */
+
+//TODO
+
+/* ------------------------------------------------------------------------------------------------
+ * Functions
+ * --------------------------------------------------------------------------------------------- */
+
+//TODO
+
+/* ------------------------------------------------------------------------------------------------
+ * Tests
+ * --------------------------------------------------------------------------------------------- */
+
+//TODO
+
+/* ------------------------------------------------------------------------------------------------
+ * Benchmarks
+ * --------------------------------------------------------------------------------------------- */
+
+//TODO
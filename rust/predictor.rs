@@ -0,0 +1,225 @@
+/*
+ * File:    predictor.rs
+ * Brief:   Branch predictor models, for working out when the pipeline gets flushed
+ *
+ * Copyright (C) 2024 John Jekel
+ * See the LICENSE file at the root of the project for licensing info.
+ *
+ * The checkers track which instruction is in which stage, so they need to know when fetch and
+ * decode get flushed. Branches and jumps resolve in [E] unless the pipeline layout says otherwise;
+ * what differs between cores is what was fetched behind them in the meantime:
+ *
+ *     not-taken        The course default: always fetch pc + 4, so every taken branch and every
+ *                      jump is a mispredict
+ *     backward-taken   Static backwards-taken/forwards-not-taken, predicted in [F] (ex. by
+ *                      predecoding the fetched word), with jal always predicted and jalr never
+ *     infer            Whatever the golden trace fetched behind the branch was the prediction,
+ *                      so it was a mispredict only if that's not where the branch actually went.
+ *                      Works for BTBs and anything else that redirects fetch in [F].
+ *
+ * Either way, a mispredict flushes everything before the branch resolution stage the cycle after
+ * the branch is in it (for the course's pipelines, that's [F] and [D] after [E]).
+ *
+*/
+
+/*!
+ * Branch predictor models, for working out when the pipeline gets flushed
+*/
+
+/* ------------------------------------------------------------------------------------------------
+ * Submodules
+ * --------------------------------------------------------------------------------------------- */
+
+//TODO (includes "mod ..." and "pub mod ...")
+
+/* ------------------------------------------------------------------------------------------------
+ * Uses
+ * --------------------------------------------------------------------------------------------- */
+
+use crate::rules::InstrClass;
+
+use riscv_tools::*;
+
+/* ------------------------------------------------------------------------------------------------
+ * Macros
+ * --------------------------------------------------------------------------------------------- */
+
+//TODO (also pub(crate) use the_macro statements here too)
+
+/* ------------------------------------------------------------------------------------------------
+ * Constants
+ * --------------------------------------------------------------------------------------------- */
+
+//TODO
+
+/* ------------------------------------------------------------------------------------------------
+ * Static Variables
+ * --------------------------------------------------------------------------------------------- */
+
+//TODO
+
+/* ------------------------------------------------------------------------------------------------
+ * Types
+ * --------------------------------------------------------------------------------------------- */
+
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum PredictorMode {
+    #[default]
+    NotTaken,
+    BackwardTaken,
+    Infer,
+}
+
+//How the golden trace's branches and jumps went
+#[derive(Copy, Clone, Debug, Default)]
+pub struct BranchStats {
+    pub resolved:       usize,
+    pub mispredicted:   usize,
+}
+
+//A branch or jump in [E], and what the golden trace says about it
+pub struct ResolvedBranch<'a> {
+    pub instr:          &'a Instruction,
+    pub pc:             u32,
+    pub taken:          bool,//From the golden [E] line (ignored for jumps, which are always taken)
+    pub rs1:            Option<u32>,//What the golden [R] stage read, for jalr
+    pub alu_result:     Option<u32>,//Fallback for the jalr target if we don't know rs1
+    pub fetched_next:   Option<u32>,//PC of the instruction right behind it in the pipeline, if any
+}
+
+/* ------------------------------------------------------------------------------------------------
+ * Associated Functions and Methods
+ * --------------------------------------------------------------------------------------------- */
+
+impl PredictorMode {
+    pub fn from_name(name: &str) -> Option<PredictorMode> {
+        match name.trim() {
+            "not-taken"         => Some(PredictorMode::NotTaken),
+            "backward-taken"    => Some(PredictorMode::BackwardTaken),
+            "infer"             => Some(PredictorMode::Infer),
+            _                   => None,
+        }
+    }
+
+    pub const fn name(&self) -> &'static str {
+        match self {
+            PredictorMode::NotTaken         => "not-taken",
+            PredictorMode::BackwardTaken    => "backward-taken",
+            PredictorMode::Infer            => "infer",
+        }
+    }
+
+    //None if the instruction isn't a branch or jump, otherwise whether it was mispredicted
+    //(meaning [F] and [D] should be flushed next cycle)
+    pub fn mispredicted(&self, branch: &ResolvedBranch) -> Option<bool> {
+        let class = InstrClass::of(branch.instr);
+        let taken = match class {
+            InstrClass::Branch                      => branch.taken,
+            InstrClass::Jal | InstrClass::Jalr      => true,
+            _                                       => return None,
+        };
+
+        Some(match self {
+            PredictorMode::NotTaken         => taken,
+            PredictorMode::BackwardTaken    => match class {
+                InstrClass::Branch  => taken != branch.instr.get_imm().is_some_and(|imm| imm < 0),
+                InstrClass::Jal     => false,
+                _                   => true,
+            },
+            PredictorMode::Infer            => {
+                //If nothing is behind it (ex. a stall), there's nothing to flush
+                branch.fetched_next.is_some() && (branch.fetched_next != branch.actual_next_pc())
+            },
+        })
+    }
+}
+
+impl BranchStats {
+    pub fn record(&mut self, mispredicted: bool) {
+        self.resolved += 1;
+        if mispredicted {
+            self.mispredicted += 1;
+        }
+    }
+}
+
+impl ResolvedBranch<'_> {
+    //Where the branch or jump actually went, if we can work it out
+    fn actual_next_pc(&self) -> Option<u32> {
+        let imm = self.instr.get_imm().map(|imm| imm as u32);
+        match InstrClass::of(self.instr) {
+            InstrClass::Branch if !self.taken   => Some(self.pc.wrapping_add(4)),
+            InstrClass::Branch | InstrClass::Jal => imm.map(|imm| self.pc.wrapping_add(imm)),
+            InstrClass::Jalr                    => {
+                let target = match (self.rs1, imm) {
+                    (Some(rs1), Some(imm))  => Some(rs1.wrapping_add(imm)),
+                    _                       => self.alu_result,
+                };
+                target.map(|target| target & !1)
+            },
+            _ => None,
+        }
+    }
+}
+
+/* ------------------------------------------------------------------------------------------------
+ * Traits And Default Implementations
+ * --------------------------------------------------------------------------------------------- */
+
+//TODO
+
+/* ------------------------------------------------------------------------------------------------
+ * Trait Implementations
+ * --------------------------------------------------------------------------------------------- */
+
+impl std::fmt::Display for PredictorMode {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+//ex. "3 of 120 branch(es)/jump(s) mispredicted (2.5%)"
+impl std::fmt::Display for BranchStats {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} of {} branch(es)/jump(s) mispredicted", self.mispredicted, self.resolved)?;
+        if self.resolved > 0 {
+            write!(f, " ({:.1}%)", (self.mispredicted as f64) * 100.0 / (self.resolved as f64))?;
+        }
+        Ok(())
+    }
+}
+
+/* ------------------------------------------------------------------------------------------------
+ * Functions
+ * --------------------------------------------------------------------------------------------- */
+
+//Whether a branch or jump goes anywhere other than pc + 4 given what it read from the register file,
+//for pipelines that resolve branches before [E] (and so before the golden trace says). None if it
+//isn't a branch or jump.
+pub fn taken(word: u32, rs1: u32, rs2: u32) -> Option<bool> {
+    match word & 0x7f {
+        0b1101111 | 0b1100111   => Some(true),//jal, jalr
+        0b1100011               => match (word >> 12) & 0b111 {
+            0b000 => Some(rs1 == rs2),
+            0b001 => Some(rs1 != rs2),
+            0b100 => Some((rs1 as i32) < (rs2 as i32)),
+            0b101 => Some((rs1 as i32) >= (rs2 as i32)),
+            0b110 => Some(rs1 < rs2),
+            0b111 => Some(rs1 >= rs2),
+            _     => None,
+        },
+        _ => None,
+    }
+}
+
+/* ------------------------------------------------------------------------------------------------
+ * Tests
+ * --------------------------------------------------------------------------------------------- */
+
+//TODO
+
+/* ------------------------------------------------------------------------------------------------
+ * Benchmarks
+ * --------------------------------------------------------------------------------------------- */
+
+//TODO
@@ -0,0 +1,181 @@
+/*
+ * File:    reference.rs
+ * Brief:   Keeps track of where the golden trace disagrees with the decoder or the pipeline model
+ *
+ * Copyright (C) 2024 John Jekel
+ * See the LICENSE file at the root of the project for licensing info.
+ *
+ * The checkers lean on riscv_tools' decoder to know which fields an instruction has, and on the
+ * pipeline tracker to know which instruction each golden line is about. If the golden trace disagrees
+ * with either of them, one of the two is wrong, but it's nothing to do with your trace. Rather than
+ * giving up on the whole comparison, it's reported as a reference inconsistency and we carry on.
+ *
+ * For decode fields we can usually tell which side is wrong: the field's bits are right there in the
+ * instruction word, so if the golden trace agrees with them it's the decoder that's off (a bug in the
+ * checker), and otherwise it's the golden trace. A PC that doesn't line up with the tracker points at
+ * the golden trace, or at --pipeline/--predictor not matching the CPU that made it.
+ *
+*/
+
+/*!
+ * Keeps track of where the golden trace disagrees with the decoder or the pipeline model
+*/
+
+/* ------------------------------------------------------------------------------------------------
+ * Submodules
+ * --------------------------------------------------------------------------------------------- */
+
+//TODO (includes "mod ..." and "pub mod ...")
+
+/* ------------------------------------------------------------------------------------------------
+ * Uses
+ * --------------------------------------------------------------------------------------------- */
+
+use crate::Stage;
+use crate::hints;
+use crate::logic::Logic;
+use crate::rules::Field;
+
+use riscv_tools::*;
+
+/* ------------------------------------------------------------------------------------------------
+ * Macros
+ * --------------------------------------------------------------------------------------------- */
+
+//TODO (also pub(crate) use the_macro statements here too)
+
+/* ------------------------------------------------------------------------------------------------
+ * Constants
+ * --------------------------------------------------------------------------------------------- */
+
+//TODO
+
+/* ------------------------------------------------------------------------------------------------
+ * Static Variables
+ * --------------------------------------------------------------------------------------------- */
+
+//TODO
+
+/* ------------------------------------------------------------------------------------------------
+ * Types
+ * --------------------------------------------------------------------------------------------- */
+
+//Which side of a reference inconsistency is probably wrong
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Suspect {
+    GoldenTrace,
+    Decoder,
+}
+
+#[derive(Default)]
+pub struct ReferenceLog {
+    golden_trace_count: u32,
+    decoder_count:      u32,
+}
+
+/* ------------------------------------------------------------------------------------------------
+ * Associated Functions and Methods
+ * --------------------------------------------------------------------------------------------- */
+
+impl Suspect {
+    const fn reason(&self) -> &'static str {
+        match self {
+            Suspect::GoldenTrace    => "the instruction word's bits don't back the golden trace up either, so it's the suspect",
+            Suspect::Decoder        => "the golden trace agrees with the instruction word's bits, so the decoder is the suspect (a bug in the checker)",
+        }
+    }
+}
+
+impl ReferenceLog {
+    pub fn new() -> ReferenceLog {
+        ReferenceLog::default()
+    }
+
+    //A golden field vs. what the decoder got for it from the instruction word (ex. from get_rd()). Returns
+    //what to report if they differ; X/Z in the golden trace can't be compared against, so it's skipped
+    pub fn check_decode(&mut self, instr: &Instruction, field: Field, golden: Logic<u32>, decoded: u32) -> Option<String> {
+        if !golden.is_known() || (golden == decoded) {
+            return None;
+        }
+
+        //[R] and [W] register numbers come from the same bits as their [D] fields
+        let word = instr.assume_uncompressed();
+        let bits_field = match field {
+            Field::RAddrRs1 => Field::DRs1,
+            Field::RAddrRs2 => Field::DRs2,
+            Field::WAddrRd  => Field::DRd,
+            _               => field,
+        };
+        let suspect = if hints::decoded_field(word, bits_field) == Some(golden.value()) { Suspect::Decoder } else { Suspect::GoldenTrace };
+        self.record(suspect);
+
+        Some(format!(
+            "The golden trace's {} {} is {:x}, but the decoder says `{}` ({:08x}) has {:x}; {}",
+            field.stage().tag(), field.name(), golden, crate::disassemble(instr), word, decoded, suspect.reason()
+        ))
+    }
+
+    //A golden line's PC vs. where the tracker has that stage's instruction, going by the golden [F] lines
+    pub fn check_pc(&mut self, tag: Stage, golden: Logic<u32>, tracked: u32) -> Option<String> {
+        if !golden.is_known() || (golden == tracked) {
+            return None;
+        }
+        self.record(Suspect::GoldenTrace);
+
+        Some(format!(
+            "The golden trace's {} PC is {:08x}, but going by its [F] lines the instruction there should be @PC {:08x}; is it the right trace for this benchmark, and do --pipeline and --predictor match the CPU it came from?",
+            tag.tag(), golden, tracked
+        ))
+    }
+
+    pub fn record(&mut self, suspect: Suspect) {
+        match suspect {
+            Suspect::GoldenTrace    => self.golden_trace_count += 1,
+            Suspect::Decoder        => self.decoder_count += 1,
+        }
+    }
+
+    pub fn count(&self) -> u32 {
+        self.golden_trace_count + self.decoder_count
+    }
+
+    //Which side looks wrong overall, if there were any inconsistencies
+    pub fn summary(&self) -> Option<String> {
+        match (self.golden_trace_count, self.decoder_count) {
+            (0, 0)              => None,
+            (_, 0)              => Some(String::from("the golden trace is the suspect for all of them, so check it's the right one for this benchmark and that the options match the CPU that made it")),
+            (0, _)              => Some(String::from("the decoder is the suspect for all of them, since the golden trace matches the instruction words; this is a bug in the checker, so please report it")),
+            (golden, decoder)   => Some(format!("the golden trace is the suspect for {} and the decoder for {}", golden, decoder)),
+        }
+    }
+}
+
+/* ------------------------------------------------------------------------------------------------
+ * Traits And Default Implementations
+ * --------------------------------------------------------------------------------------------- */
+
+//TODO
+
+/* ------------------------------------------------------------------------------------------------
+ * Trait Implementations
+ * --------------------------------------------------------------------------------------------- */
+
+//TODO
+
+/* ------------------------------------------------------------------------------------------------
+ * Functions
+ * --------------------------------------------------------------------------------------------- */
+
+//TODO
+
+/* ------------------------------------------------------------------------------------------------
+ * Tests
+ * --------------------------------------------------------------------------------------------- */
+
+//TODO
+
+/* ------------------------------------------------------------------------------------------------
+ * Benchmarks
+ * --------------------------------------------------------------------------------------------- */
+
+//TODO
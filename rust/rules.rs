@@ -0,0 +1,541 @@
+/*
+ * File:    rules.rs
+ * Brief:   Declarative table of which trace fields matter for which instructions
+ *
+ * Copyright (C) 2024 John Jekel
+ * See the LICENSE file at the root of the project for licensing info.
+ *
+ * Rather than hard-coding "skip the ALU result for system instructions" and friends in each
+ * checker, the checkers ask a RuleTable whether a given field must match for the instruction
+ * currently in that stage.
+ *
+ * The built-in table (DEFAULT_RULES below) is written in the same format as rule files, so
+ * a rule file is just a list of overrides applied on top of it. Each non-comment line is:
+ *
+ *     <classes>   <stages>   <fields>   <rule>
+ *
+ * where classes, stages and fields may be comma-separated lists or `*`, and rule is one of
+ * `must`, `dont-care` or `if-has-<rd|rs1|rs2|funct3|funct7|imm|shamt>` (the latter meaning
+ * "must match if the decoder says the instruction actually has that operand"). Later lines
+ * win over earlier ones. `#` starts a comment.
+ *
+*/
+
+/*!
+ * Declarative table of which trace fields matter for which instructions
+*/
+
+/* ------------------------------------------------------------------------------------------------
+ * Submodules
+ * --------------------------------------------------------------------------------------------- */
+
+//TODO (includes "mod ..." and "pub mod ...")
+
+/* ------------------------------------------------------------------------------------------------
+ * Uses
+ * --------------------------------------------------------------------------------------------- */
+
+use crate::{ParsedLine, Stage};
+use crate::csr::CsrInstr;
+use crate::logic::Logic;
+use crate::muldiv::MulDivOp;
+
+use riscv_tools::*;
+
+use std::path::Path;
+
+/* ------------------------------------------------------------------------------------------------
+ * Macros
+ * --------------------------------------------------------------------------------------------- */
+
+//TODO (also pub(crate) use the_macro statements here too)
+
+/* ------------------------------------------------------------------------------------------------
+ * Constants
+ * --------------------------------------------------------------------------------------------- */
+
+//Mirrors what the checkers hard-coded before the rule table existed
+pub const DEFAULT_RULES: &str = "
+#classes        stages  fields                          rule
+*               F       pc,instr                        must
+
+*               D       pc,opcode                       must
+fence           D       opcode                          dont-care
+*               D       rd                              if-has-rd
+*               D       rs1                             if-has-rs1
+*               D       rs2                             if-has-rs2
+*               D       funct3                          if-has-funct3
+*               D       funct7                          if-has-funct7
+*               D       imm                             if-has-imm
+*               D       shamt                           if-has-shamt
+
+*               R       addr_rs1,data_rs1               if-has-rs1
+*               R       addr_rs2,data_rs2               if-has-rs2
+
+*               E       pc,alu_result                   must
+fence,system    E       alu_result                      dont-care
+csr             E       alu_result                      dont-care
+*               E       branch_taken                    dont-care
+branch          E       branch_taken                    must
+
+*               M       pc,read_not_write               must
+*               M       addr,access_size,memory_wdata   dont-care
+load,store      M       addr,access_size                must
+store           M       memory_wdata                    must
+
+*               W       pc,we                           must
+*               W       addr_rd,data_rd                 if-has-rd
+fence           W       we,addr_rd,data_rd              dont-care
+";
+
+/* ------------------------------------------------------------------------------------------------
+ * Static Variables
+ * --------------------------------------------------------------------------------------------- */
+
+//TODO
+
+/* ------------------------------------------------------------------------------------------------
+ * Types
+ * --------------------------------------------------------------------------------------------- */
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum InstrClass {
+    Lui,
+    Auipc,
+    Jal,
+    Jalr,
+    Branch,
+    Load,
+    Store,
+    OpImm,
+    Op,
+    MulDiv,
+    Fence,
+    Csr,
+    System,
+    Unknown,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Field {
+    FPc,
+    FInstr,
+    DPc,
+    DOpcode,
+    DRd,
+    DRs1,
+    DRs2,
+    DFunct3,
+    DFunct7,
+    DImm,
+    DShamt,
+    RAddrRs1,
+    RAddrRs2,
+    RDataRs1,
+    RDataRs2,
+    EPc,
+    EAluResult,
+    EBranchTaken,
+    MPc,
+    MAddr,
+    MReadNotWrite,
+    MAccessSize,
+    MMemoryWdata,
+    WPc,
+    WWe,
+    WAddrRd,
+    WDataRd,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Operand {
+    Rd,
+    Rs1,
+    Rs2,
+    Funct3,
+    Funct7,
+    Imm,
+    Shamt,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Rule {
+    MustMatch,
+    DontCare,
+    IfHas(Operand),
+}
+
+#[derive(Clone, Debug)]
+pub struct RuleTable {
+    table: [[Rule; Field::ALL.len()]; InstrClass::ALL.len()],
+}
+
+/* ------------------------------------------------------------------------------------------------
+ * Associated Functions and Methods
+ * --------------------------------------------------------------------------------------------- */
+
+impl InstrClass {
+    pub const ALL: [InstrClass; 14] = [
+        InstrClass::Lui,
+        InstrClass::Auipc,
+        InstrClass::Jal,
+        InstrClass::Jalr,
+        InstrClass::Branch,
+        InstrClass::Load,
+        InstrClass::Store,
+        InstrClass::OpImm,
+        InstrClass::Op,
+        InstrClass::MulDiv,
+        InstrClass::Fence,
+        InstrClass::Csr,
+        InstrClass::System,
+        InstrClass::Unknown,
+    ];
+
+    pub fn of(instr: &Instruction) -> InstrClass {
+        if MulDivOp::of(instr).is_some() {
+            return InstrClass::MulDiv;
+        }
+        if CsrInstr::of(instr).is_some() {
+            return InstrClass::Csr;
+        }
+
+        match instr.assume_uncompressed() & 0x7f {
+            0b0110111 => InstrClass::Lui,
+            0b0010111 => InstrClass::Auipc,
+            0b1101111 => InstrClass::Jal,
+            0b1100111 => InstrClass::Jalr,
+            0b1100011 => InstrClass::Branch,
+            0b0000011 => InstrClass::Load,
+            0b0100011 => InstrClass::Store,
+            0b0010011 => InstrClass::OpImm,
+            0b0110011 => InstrClass::Op,
+            0b0001111 => InstrClass::Fence,
+            0b1110011 => InstrClass::System,
+            _         => InstrClass::Unknown,
+        }
+    }
+
+    pub const fn name(&self) -> &'static str {
+        match self {
+            InstrClass::Lui     => "lui",
+            InstrClass::Auipc   => "auipc",
+            InstrClass::Jal     => "jal",
+            InstrClass::Jalr    => "jalr",
+            InstrClass::Branch  => "branch",
+            InstrClass::Load    => "load",
+            InstrClass::Store   => "store",
+            InstrClass::OpImm   => "op-imm",
+            InstrClass::Op      => "op",
+            InstrClass::MulDiv  => "muldiv",
+            InstrClass::Fence   => "fence",
+            InstrClass::Csr     => "csr",
+            InstrClass::System  => "system",
+            InstrClass::Unknown => "unknown",
+        }
+    }
+
+    fn from_name(name: &str) -> Option<InstrClass> {
+        InstrClass::ALL.into_iter().find(|class| class.name() == name)
+    }
+
+    const fn index(&self) -> usize {
+        *self as usize
+    }
+}
+
+impl Field {
+    pub const ALL: [Field; 27] = [
+        Field::FPc,
+        Field::FInstr,
+        Field::DPc,
+        Field::DOpcode,
+        Field::DRd,
+        Field::DRs1,
+        Field::DRs2,
+        Field::DFunct3,
+        Field::DFunct7,
+        Field::DImm,
+        Field::DShamt,
+        Field::RAddrRs1,
+        Field::RAddrRs2,
+        Field::RDataRs1,
+        Field::RDataRs2,
+        Field::EPc,
+        Field::EAluResult,
+        Field::EBranchTaken,
+        Field::MPc,
+        Field::MAddr,
+        Field::MReadNotWrite,
+        Field::MAccessSize,
+        Field::MMemoryWdata,
+        Field::WPc,
+        Field::WWe,
+        Field::WAddrRd,
+        Field::WDataRd,
+    ];
+
+    pub const fn stage(&self) -> Stage {
+        match self {
+            Field::FPc | Field::FInstr => Stage::F,
+            Field::DPc | Field::DOpcode | Field::DRd | Field::DRs1 | Field::DRs2 | Field::DFunct3 | Field::DFunct7 | Field::DImm | Field::DShamt => Stage::D,
+            Field::RAddrRs1 | Field::RAddrRs2 | Field::RDataRs1 | Field::RDataRs2 => Stage::R,
+            Field::EPc | Field::EAluResult | Field::EBranchTaken => Stage::E,
+            Field::MPc | Field::MAddr | Field::MReadNotWrite | Field::MAccessSize | Field::MMemoryWdata => Stage::M,
+            Field::WPc | Field::WWe | Field::WAddrRd | Field::WDataRd => Stage::W,
+        }
+    }
+
+    //Same names as the members of ParsedLine
+    pub const fn name(&self) -> &'static str {
+        match self {
+            Field::FPc | Field::DPc | Field::EPc | Field::MPc | Field::WPc => "pc",
+            Field::FInstr           => "instr",
+            Field::DOpcode          => "opcode",
+            Field::DRd              => "rd",
+            Field::DRs1             => "rs1",
+            Field::DRs2             => "rs2",
+            Field::DFunct3          => "funct3",
+            Field::DFunct7          => "funct7",
+            Field::DImm             => "imm",
+            Field::DShamt           => "shamt",
+            Field::RAddrRs1         => "addr_rs1",
+            Field::RAddrRs2         => "addr_rs2",
+            Field::RDataRs1         => "data_rs1",
+            Field::RDataRs2         => "data_rs2",
+            Field::EAluResult       => "alu_result",
+            Field::EBranchTaken     => "branch_taken",
+            Field::MAddr            => "addr",
+            Field::MReadNotWrite    => "read_not_write",
+            Field::MAccessSize      => "access_size",
+            Field::MMemoryWdata     => "memory_wdata",
+            Field::WWe              => "we",
+            Field::WAddrRd          => "addr_rd",
+            Field::WDataRd          => "data_rd",
+        }
+    }
+
+    //None if the line is for a different stage
+    pub fn value_in(&self, line: &ParsedLine) -> Option<Logic<u32>> {
+        match (self, *line) {
+            (Field::FPc,            ParsedLine::F{pc, ..})              => Some(pc.widen()),
+            (Field::FInstr,         ParsedLine::F{instr, ..})           => Some(instr.widen()),
+            (Field::DPc,            ParsedLine::D{pc, ..})              => Some(pc.widen()),
+            (Field::DOpcode,        ParsedLine::D{opcode, ..})          => Some(opcode.widen()),
+            (Field::DRd,            ParsedLine::D{rd, ..})              => Some(rd.widen()),
+            (Field::DRs1,           ParsedLine::D{rs1, ..})             => Some(rs1.widen()),
+            (Field::DRs2,           ParsedLine::D{rs2, ..})             => Some(rs2.widen()),
+            (Field::DFunct3,        ParsedLine::D{funct3, ..})          => Some(funct3.widen()),
+            (Field::DFunct7,        ParsedLine::D{funct7, ..})          => Some(funct7.widen()),
+            (Field::DImm,           ParsedLine::D{imm, ..})             => Some(imm.widen()),
+            (Field::DShamt,         ParsedLine::D{shamt, ..})           => Some(shamt.widen()),
+            (Field::RAddrRs1,       ParsedLine::R{addr_rs1, ..})        => Some(addr_rs1.widen()),
+            (Field::RAddrRs2,       ParsedLine::R{addr_rs2, ..})        => Some(addr_rs2.widen()),
+            (Field::RDataRs1,       ParsedLine::R{data_rs1, ..})        => Some(data_rs1.widen()),
+            (Field::RDataRs2,       ParsedLine::R{data_rs2, ..})        => Some(data_rs2.widen()),
+            (Field::EPc,            ParsedLine::E{pc, ..})              => Some(pc.widen()),
+            (Field::EAluResult,     ParsedLine::E{alu_result, ..})      => Some(alu_result.widen()),
+            (Field::EBranchTaken,   ParsedLine::E{branch_taken, ..})    => Some(branch_taken.widen()),
+            (Field::MPc,            ParsedLine::M{pc, ..})              => Some(pc.widen()),
+            (Field::MAddr,          ParsedLine::M{addr, ..})            => Some(addr.widen()),
+            (Field::MReadNotWrite,  ParsedLine::M{read_not_write, ..})  => Some(read_not_write.widen()),
+            (Field::MAccessSize,    ParsedLine::M{access_size, ..})     => Some(access_size.widen()),
+            (Field::MMemoryWdata,   ParsedLine::M{memory_wdata, ..})    => Some(memory_wdata.widen()),
+            (Field::WPc,            ParsedLine::W{pc, ..})              => Some(pc.widen()),
+            (Field::WWe,            ParsedLine::W{we, ..})              => Some(we.widen()),
+            (Field::WAddrRd,        ParsedLine::W{addr_rd, ..})         => Some(addr_rd.widen()),
+            (Field::WDataRd,        ParsedLine::W{data_rd, ..})         => Some(data_rd.widen()),
+            _                                                           => None,
+        }
+    }
+
+    fn from_names(stage: Stage, name: &str) -> Option<Field> {
+        Field::ALL.into_iter().find(|field| (field.stage() == stage) && (field.name() == name))
+    }
+
+    const fn index(&self) -> usize {
+        *self as usize
+    }
+}
+
+impl Operand {
+    fn from_name(name: &str) -> Option<Operand> {
+        match name {
+            "rd"        => Some(Operand::Rd),
+            "rs1"       => Some(Operand::Rs1),
+            "rs2"       => Some(Operand::Rs2),
+            "funct3"    => Some(Operand::Funct3),
+            "funct7"    => Some(Operand::Funct7),
+            "imm"       => Some(Operand::Imm),
+            "shamt"     => Some(Operand::Shamt),
+            _           => None,
+        }
+    }
+
+    pub fn is_present_in(&self, instr: &Instruction) -> bool {
+        if MulDivOp::of(instr).is_some() {
+            //Plain R-type, but riscv_tools doesn't know about these
+            return matches!(self, Operand::Rd | Operand::Rs1 | Operand::Rs2 | Operand::Funct3 | Operand::Funct7);
+        }
+        if CsrInstr::of(instr).is_some() {
+            //rs1 holds the immediate for csrr*i, but either way decode should pass it along
+            return matches!(self, Operand::Rd | Operand::Rs1 | Operand::Funct3);
+        }
+
+        match self {
+            Operand::Rd     => instr.get_rd().is_some(),
+            Operand::Rs1    => instr.get_rs1().is_some(),
+            Operand::Rs2    => instr.get_rs2().is_some(),
+            Operand::Funct3 => instr.get_funct3().is_some(),
+            Operand::Funct7 => instr.get_funct7().is_some(),
+            Operand::Imm    => instr.get_imm().is_some(),
+            Operand::Shamt  => instr.get_shamt().is_some(),
+        }
+    }
+}
+
+impl Rule {
+    fn from_name(name: &str) -> Option<Rule> {
+        match name {
+            "must"      => Some(Rule::MustMatch),
+            "dont-care" => Some(Rule::DontCare),
+            _           => Operand::from_name(name.strip_prefix("if-has-")?).map(Rule::IfHas),
+        }
+    }
+}
+
+impl RuleTable {
+    pub fn builtin() -> RuleTable {
+        let mut rule_table = RuleTable {
+            table: [[Rule::MustMatch; Field::ALL.len()]; InstrClass::ALL.len()],
+        };
+        rule_table.apply(DEFAULT_RULES).expect("Built-in rule table is malformed");
+        rule_table
+    }
+
+    //Loads the built-in table, then applies the overrides in the file on top of it
+    pub fn from_path(path: impl AsRef<Path>) -> Result<RuleTable, String> {
+        let contents = std::fs::read_to_string(path.as_ref()).map_err(|e| e.to_string())?;
+        let mut rule_table = RuleTable::builtin();
+        rule_table.apply(&contents)?;
+        Ok(rule_table)
+    }
+
+    //What --rules asks for: the overrides in the file if given one, otherwise just the built-in table
+    pub fn from_option(path: Option<&str>) -> Result<RuleTable, String> {
+        let Some(path) = path else {
+            return Ok(RuleTable::builtin());
+        };
+
+        let rules = RuleTable::from_path(path).map_err(|e| format!("Error loading rules at path {}: {}", path, e))?;
+        println!("Using don't-care rule overrides from \x1b[1;36m{}\x1b[0m", path);
+        Ok(rules)
+    }
+
+    pub fn apply(&mut self, rules: &str) -> Result<(), String> {
+        for (line_num, line) in rules.lines().enumerate() {
+            let line = line.split('#').next().unwrap().trim();
+            if line.is_empty() {
+                continue;
+            }
+
+            let tokens: Vec<&str> = line.split_whitespace().collect();
+            let [classes, stages, fields, rule] = tokens[..] else {
+                return Err(format!("Line {}: expected `<classes> <stages> <fields> <rule>`, got \"{}\"", line_num + 1, line));
+            };
+
+            let rule = Rule::from_name(rule).ok_or(format!("Line {}: unknown rule \"{}\"", line_num + 1, rule))?;
+
+            let classes: Vec<InstrClass> = if classes == "*" {
+                InstrClass::ALL.to_vec()
+            } else {
+                classes.split(',').map(|name| {
+                    InstrClass::from_name(name).ok_or(format!("Line {}: unknown instruction class \"{}\"", line_num + 1, name))
+                }).collect::<Result<_, _>>()?
+            };
+
+            let stages: Vec<Stage> = if stages == "*" {
+                vec![Stage::F, Stage::D, Stage::R, Stage::E, Stage::M, Stage::W]
+            } else {
+                stages.split(',').map(|name| {
+                    Stage::from_letter(name).ok_or(format!("Line {}: unknown stage \"{}\"", line_num + 1, name))
+                }).collect::<Result<_, _>>()?
+            };
+
+            let mut matched_fields = Vec::new();
+            for stage in stages.iter() {
+                if fields == "*" {
+                    matched_fields.extend(Field::ALL.into_iter().filter(|field| field.stage() == *stage));
+                } else {
+                    for name in fields.split(',') {
+                        //With several stages listed, a field only has to exist in one of them
+                        if let Some(field) = Field::from_names(*stage, name) {
+                            matched_fields.push(field);
+                        } else if stages.len() == 1 {
+                            return Err(format!("Line {}: stage {} has no field \"{}\"", line_num + 1, stage.tag(), name));
+                        }
+                    }
+                }
+            }
+
+            if matched_fields.is_empty() {
+                return Err(format!("Line {}: no fields matched \"{}\"", line_num + 1, fields));
+            }
+
+            for class in classes.iter() {
+                for field in matched_fields.iter() {
+                    self.table[class.index()][field.index()] = rule;
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    pub fn rule(&self, class: InstrClass, field: Field) -> Rule {
+        self.table[class.index()][field.index()]
+    }
+
+    //Should the given field be compared between the golden trace and ours for this instruction?
+    pub fn must_match(&self, instr: &Instruction, field: Field) -> bool {
+        match self.rule(InstrClass::of(instr), field) {
+            Rule::MustMatch         => true,
+            Rule::DontCare          => false,
+            Rule::IfHas(operand)    => operand.is_present_in(instr),
+        }
+    }
+}
+
+/* ------------------------------------------------------------------------------------------------
+ * Traits And Default Implementations
+ * --------------------------------------------------------------------------------------------- */
+
+//TODO
+
+/* ------------------------------------------------------------------------------------------------
+ * Trait Implementations
+ * --------------------------------------------------------------------------------------------- */
+
+impl Default for RuleTable {
+    fn default() -> Self {
+        RuleTable::builtin()
+    }
+}
+
+/* ------------------------------------------------------------------------------------------------
+ * Functions
+ * --------------------------------------------------------------------------------------------- */
+
+//TODO
+
+/* ------------------------------------------------------------------------------------------------
+ * Tests
+ * --------------------------------------------------------------------------------------------- */
+
+//TODO
+
+/* ------------------------------------------------------------------------------------------------
+ * Benchmarks
+ * --------------------------------------------------------------------------------------------- */
+
+//TODO
@@ -0,0 +1,404 @@
+/*
+ * File:    selfcheck.rs
+ * Brief:   Checks your trace against itself when there's no golden trace
+ *
+ * Copyright (C) 2024 John Jekel
+ * See the LICENSE file at the root of the project for licensing info.
+ *
+ * There's only a golden trace for the benchmarks we were given, but a lot of bugs can be caught
+ * without one since the trace says what your CPU did at every step. The pipeline tracker follows
+ * your trace as if it were the golden one (so it works out stalls and flushes from your lines), and
+ * then each instruction is checked against what your own trace says about it:
+ *
+ * - The [D] fields should be what the instruction word from your [F] line decodes to
+ * - The [E] ALU result should be what the instruction does to your [R] operands, and a branch
+ *   should be taken exactly when its comparison of them is true
+ * - The [W] line should carry the result of the same instruction: your [E] ALU result, pc + 4 for
+ *   jumps, the RV32M result, the old CSR value, or for loads the bytes your earlier stores left there
+ *   (otherwise we can at least check the sign/zero extension)
+ * - Instructions should reach [W] in program order, so each one should be where the one before it
+ *   went, which catches branches and jumps that went to the wrong place without being flushed
+ *
+ * Fields the rules say are don't cares are skipped, as they would be against a golden trace.
+ *
+ * The same checks work on a golden trace too (validate-golden), to catch one that doesn't make sense
+ * before you spend time chasing "mismatches" against it.
+ *
+ * self_check() drives a SelfChecker over a whole trace (yours, or the golden one for validate-golden)
+ * and prints the report, for pd5diff and pd6diff.
+ *
+*/
+
+/*!
+ * Checks your trace against itself when there's no golden trace
+*/
+
+/* ------------------------------------------------------------------------------------------------
+ * Submodules
+ * --------------------------------------------------------------------------------------------- */
+
+//TODO (includes "mod ..." and "pub mod ...")
+
+/* ------------------------------------------------------------------------------------------------
+ * Uses
+ * --------------------------------------------------------------------------------------------- */
+
+use crate::{ErrorClass, ParsedLine, Stage};
+use crate::compare::{CompareOptions, CycleReport, ReportLines, ReportTotals, Whose};
+use crate::csr::TrapCause;
+use crate::cycle::Cycle;
+use crate::hints;
+use crate::logic::Logic;
+use crate::muldiv::MulDivOp;
+use crate::predictor;
+use crate::rules::{Field, InstrClass, RuleTable};
+use crate::tracker::{PipelineTracker, StageState};
+
+use riscv_tools::*;
+
+use std::collections::HashMap;
+
+/* ------------------------------------------------------------------------------------------------
+ * Macros
+ * --------------------------------------------------------------------------------------------- */
+
+//TODO (also pub(crate) use the_macro statements here too)
+
+/* ------------------------------------------------------------------------------------------------
+ * Constants
+ * --------------------------------------------------------------------------------------------- */
+
+//The [D] fields that come straight from the instruction word
+const DECODE_FIELDS: [Field; 8] = [
+    Field::DOpcode,
+    Field::DRd,
+    Field::DRs1,
+    Field::DRs2,
+    Field::DFunct3,
+    Field::DFunct7,
+    Field::DImm,
+    Field::DShamt,
+];
+
+/* ------------------------------------------------------------------------------------------------
+ * Static Variables
+ * --------------------------------------------------------------------------------------------- */
+
+//TODO
+
+/* ------------------------------------------------------------------------------------------------
+ * Types
+ * --------------------------------------------------------------------------------------------- */
+
+pub struct SelfChecker<'a> {
+    rules:          &'a RuleTable,
+    whose:          &'static str,//Whose trace this is, for messages (ex. "your")
+    memory:         HashMap<u32, u8>,//Bytes written by the stores in the trace so far, going by their operands
+    last_retired:   Option<Retired>,
+}
+
+//The last instruction to reach [W], and where the one after it should be
+struct Retired {
+    pc:             u32,
+    disassembly:    String,
+    next_pc:        Option<u32>,//None if we can't tell (ex. it trapped)
+}
+
+/* ------------------------------------------------------------------------------------------------
+ * Associated Functions and Methods
+ * --------------------------------------------------------------------------------------------- */
+
+impl<'a> SelfChecker<'a> {
+    //`whose` is whose trace is being checked against itself
+    pub fn new(rules: &'a RuleTable, whose: Whose) -> SelfChecker<'a> {
+        SelfChecker {
+            rules,
+            whose:          match whose {
+                Whose::Golden   => "the golden trace's",
+                Whose::Yours    => "your",
+            },
+            memory:         HashMap::new(),
+            last_retired:   None,
+        }
+    }
+
+    //`cycle` is a cycle of the trace being checked, and `tracker` has already been updated with it (and
+    //is following that trace)
+    pub fn check_cycle(&mut self, cycle: &Cycle, tracker: &PipelineTracker) -> Vec<(ErrorClass, String)> {
+        let mut errors = Vec::new();
+        self.check_decode(cycle, tracker.stage_for(Stage::D), &mut errors);
+        self.check_execute(cycle, tracker.stage_for(Stage::E), &mut errors);
+        self.check_writeback(cycle, tracker.stage_for(Stage::W), &mut errors);
+        errors
+    }
+
+    fn check_decode(&self, cycle: &Cycle, d: &StageState, errors: &mut Vec<(ErrorClass, String)>) {
+        let (Ok(instr), Some(t_dline)) = (d.instr.as_ref(), cycle.line(Stage::D)) else {
+            return;
+        };
+        let word = instr.assume_uncompressed();
+
+        if self.rules.must_match(instr, Field::DPc) {
+            check_pc(errors, self.whose, Stage::D, d.pc, &t_dline);
+        }
+
+        for field in DECODE_FIELDS.into_iter().filter(|field| self.rules.must_match(instr, *field)) {
+            let (Some(expected), Some(actual)) = (hints::decoded_field(word, field), field.value_in(&t_dline)) else {
+                continue;
+            };
+            let hint = hints::decode_hint(word, field, Logic::known(expected), actual);
+            check_value(errors, expected, actual, format!("[D] {} should be {:x} going by the instruction word {:08x}{}", field.name(), expected, word, hint));
+        }
+    }
+
+    fn check_execute(&self, cycle: &Cycle, e: &StageState, errors: &mut Vec<(ErrorClass, String)>) {
+        let (Ok(instr), Some(t_eline @ ParsedLine::E{alu_result: t_alu_result, branch_taken: t_branch_taken, ..})) = (e.instr.as_ref(), cycle.line(Stage::E)) else {
+            return;
+        };
+        if MulDivOp::of(instr).is_some() {
+            return;//Takes a few cycles, so its result is checked once it gets to [W]
+        }
+        let word = instr.assume_uncompressed();
+
+        if self.rules.must_match(instr, Field::EPc) {
+            check_pc(errors, self.whose, Stage::E, e.pc, &t_eline);
+        }
+
+        let Some((rs1, rs2)) = e.operands else {
+            return;//The [R] line for it was missing or X/Z
+        };
+
+        if self.rules.must_match(instr, Field::EAluResult) {
+            if let Some((expected, how)) = hints::expected_alu_result(word, e.pc, (rs1, rs2)) {
+                let hint = hints::alu_hint(word, e.pc, e.operands, Logic::known(expected), t_alu_result);
+                check_value(errors, expected, t_alu_result, format!("[E] ALU result should be {:08x} ({}) going by {} [R] operands{}", expected, how, self.whose, hint));
+            }
+        }
+
+        if (InstrClass::of(instr) == InstrClass::Branch) && self.rules.must_match(instr, Field::EBranchTaken) {
+            if let Some(expected) = predictor::taken(word, rs1, rs2) {
+                let hint = hints::branch_hint(word, e.pc, e.operands, e.regfile_operands, Logic::known(expected), t_branch_taken, None);
+                check_value(errors, expected as u32, t_branch_taken.widen(), format!("[E] Branch taken should be {} going by {} [R] operands{}", expected as u8, self.whose, hint));
+            }
+        }
+    }
+
+    fn check_writeback(&mut self, cycle: &Cycle, w: &StageState, errors: &mut Vec<(ErrorClass, String)>) {
+        let Ok(instr) = w.instr.as_ref() else {
+            return;
+        };
+        let word = instr.assume_uncompressed();
+
+        //Every instruction that isn't flushed gets here in program order
+        if let Some(last_retired) = self.last_retired.take() {
+            if last_retired.next_pc.is_some_and(|next_pc| next_pc != w.pc) {
+                errors.push((ErrorClass::Mismatch, format!(
+                    "The instruction @PC {:08x} made it to [W] next, but `{}` @PC {:08x} before it should have gone to {:08x}",
+                    w.pc, last_retired.disassembly, last_retired.pc, last_retired.next_pc.unwrap()
+                )));
+            }
+        }
+        self.last_retired = Some(Retired {
+            pc:             w.pc,
+            disassembly:    crate::disassemble(instr),
+            next_pc:        next_pc(instr, w),
+        });
+
+        if let Some(t_wline @ ParsedLine::W{addr_rd: t_addr_rd, data_rd: t_data_rd, ..}) = cycle.line(Stage::W) {
+            if self.rules.must_match(instr, Field::WPc) {
+                check_pc(errors, self.whose, Stage::W, w.pc, &t_wline);
+            }
+
+            if self.rules.must_match(instr, Field::WAddrRd) {
+                if let Some(rd) = hints::decoded_field(word, Field::DRd) {
+                    check_value(errors, rd, t_addr_rd.widen(), format!("[W] RD address should be {:02x} going by the instruction word {:08x}", rd, word));
+                }
+            }
+
+            if self.rules.must_match(instr, Field::WDataRd) {
+                if let Some((expected, how)) = self.expected_rd(instr, w) {
+                    check_value(errors, expected, t_data_rd, format!("[W] RD data should be {:08x} ({})", expected, how));
+                } else if let Some(problem) = extension_problem(word, t_data_rd) {
+                    errors.push((ErrorClass::Mismatch, format!("[W] RD data {:08x} can't be what `{}` loaded: {}", t_data_rd, crate::disassemble(instr), problem)));
+                }
+            }
+        }
+
+        //Only once the load (if it is one) has been checked, in case it's a store to the same place
+        self.store(word, w.operands);
+    }
+
+    //What the instruction in [W] should write to rd, and where that comes from
+    fn expected_rd(&self, instr: &Instruction, w: &StageState) -> Option<(u32, String)> {
+        let word = instr.assume_uncompressed();
+        match InstrClass::of(instr) {
+            InstrClass::Jal | InstrClass::Jalr => Some((w.pc.wrapping_add(4), String::from("pc + 4"))),
+            InstrClass::Lui | InstrClass::Auipc | InstrClass::OpImm | InstrClass::Op => {
+                w.alu_result().map(|alu_result| (alu_result, format!("{} [E] ALU result for it", self.whose)))
+            },
+            InstrClass::MulDiv => {
+                let (op, (rs1, rs2)) = (MulDivOp::of(instr)?, w.operands?);
+                Some((op.evaluate(rs1, rs2), op.explain(rs1, rs2)))
+            },
+            InstrClass::Csr => w.csr_read.map(|(csr, old_value)| (old_value, format!("the old value of {}", csr.name()))),
+            InstrClass::Load => {
+                let addr = load_store_addr(word, w.operands)?;
+                let funct3 = (word >> 12) & 0b111;
+                let loaded = (0..(hints::access_width(funct3) / 8)).rev().try_fold(0, |loaded, offset| {
+                    self.memory.get(&addr.wrapping_add(offset)).map(|byte| (loaded << 8) | (*byte as u32))
+                })?;
+                Some((hints::load_result(funct3, loaded), format!("what {} earlier stores left at {:08x}", self.whose, addr)))
+            },
+            _ => None,
+        }
+    }
+
+    fn store(&mut self, word: u32, operands: Option<(u32, u32)>) {
+        if (word & 0x7f) != 0b0100011 {
+            return;
+        }
+        let (Some(addr), Some((_, rs2))) = (load_store_addr(word, operands), operands) else {
+            return;
+        };
+        let funct3 = (word >> 12) & 0b111;
+        for offset in 0..(hints::access_width(funct3) / 8) {
+            self.memory.insert(addr.wrapping_add(offset), (rs2 >> (offset * 8)) as u8);
+        }
+    }
+}
+
+/* ------------------------------------------------------------------------------------------------
+ * Traits And Default Implementations
+ * --------------------------------------------------------------------------------------------- */
+
+//TODO
+
+/* ------------------------------------------------------------------------------------------------
+ * Trait Implementations
+ * --------------------------------------------------------------------------------------------- */
+
+//TODO
+
+/* ------------------------------------------------------------------------------------------------
+ * Functions
+ * --------------------------------------------------------------------------------------------- */
+
+//Checks a trace against itself, returning the number of errors (or problems, for the golden trace)
+pub fn self_check(cycles: &[Cycle], whose: Whose, options: &CompareOptions) -> u32 {
+    let CompareOptions {rules, symbols, machine, layout, ..} = options;
+    let mut totals  = ReportTotals::default();
+    let mut tracker = PipelineTracker::new(layout, machine);//Following the trace being checked this time
+    let mut checker = SelfChecker::new(rules, whose);
+
+    for (cycle_num, window) in cycles.windows(layout.lookahead() + 1).enumerate() {
+        let cycle_num   = cycle_num + 1;//Since enumerate() is zero-indexed
+        let cycle       = &window[0];
+
+        let update = tracker.update(window);
+        if let Some(end_condition) = &update.end_of_test {
+            totals.end_of_test(end_condition);
+            break;
+        }
+
+        let lines = match whose {
+            Whose::Golden   => ReportLines::Golden(cycle),
+            Whose::Yours    => ReportLines::Yours(cycle),
+        };
+        let mut report = CycleReport::new(cycle_num, lines, &tracker, layout, symbols, &mut totals);
+
+        for problem in &cycle.problems {
+            report.print(ErrorClass::Structure, &problem.to_string());
+        }
+
+        //Only a golden trace has to have every line the tracker needs, and go where traps say it should
+        if whose == Whose::Golden {
+            if update.weird {
+                report.print(ErrorClass::Structure, "The cycle is missing an [F], [D] or [E] line the pipeline tracker needs to follow it");
+            }
+            if let (Some(redirect), Some(ParsedLine::F{pc: g_pc, ..})) = (update.fetch_redirect.as_ref(), cycle.line(Stage::F)) {
+                if redirect.target.is_some_and(|target| g_pc != target) {
+                    report.print(ErrorClass::Mismatch, &format!("Expected {}, but it fetched {:08x} instead; is `traps = on` right for this trace?", redirect, g_pc));
+                }
+            }
+        }
+
+        for (class, message) in checker.check_cycle(cycle, &tracker) {
+            report.print(class, &message);
+        }
+
+        if totals.end_after_cycle(cycle_num, cycle, &tracker, machine) {
+            break;
+        }
+    }
+
+    totals.summarize(&tracker, machine, whose, None, None)
+}
+
+//Like check_field!, but against what the rest of the trace says the value should be
+fn check_value(errors: &mut Vec<(ErrorClass, String)>, expected: u32, actual: Logic<u32>, message: String) {
+    if !actual.is_known() {
+        errors.push((ErrorClass::UnknownValue, format!("{} (it's {})", message, actual)));
+    } else if actual != expected {
+        errors.push((ErrorClass::Mismatch, message));
+    }
+}
+
+//Whether a line's PC is the instruction the tracker has in that stage (going by the trace's [F] lines)
+fn check_pc(errors: &mut Vec<(ErrorClass, String)>, whose: &str, tag: Stage, pc: u32, line: &ParsedLine) {
+    let t_pc = match *line {
+        ParsedLine::D{pc, ..} | ParsedLine::E{pc, ..} | ParsedLine::M{pc, ..} | ParsedLine::W{pc, ..} => pc,
+        _ => return,
+    };
+    check_value(errors, pc, t_pc, format!("{} PC should be {:08x}, the instruction {} [F] lines put there", tag.tag(), pc, whose));
+}
+
+//rs1 + imm, for a load or store with known operands
+fn load_store_addr(word: u32, operands: Option<(u32, u32)>) -> Option<u32> {
+    let (rs1, _) = operands?;
+    hints::decoded_field(word, Field::DImm).map(|imm| rs1.wrapping_add(imm))
+}
+
+//Where the instruction after this one should be, given what it read in the [R] stage
+fn next_pc(instr: &Instruction, w: &StageState) -> Option<u32> {
+    let word    = instr.assume_uncompressed();
+    let imm     = hints::decoded_field(word, Field::DImm);
+    let taken   = w.operands.and_then(|(rs1, rs2)| predictor::taken(word, rs1, rs2));
+    if TrapCause::of(word, w.alu_result(), taken.unwrap_or(false)).is_some() {
+        return None;//Off to the trap handler (or it would be if traps are on)
+    }
+
+    match InstrClass::of(instr) {
+        InstrClass::System  => None,//mret
+        InstrClass::Branch  => if taken? { imm.map(|imm| w.pc.wrapping_add(imm)) } else { Some(w.pc.wrapping_add(4)) },
+        InstrClass::Jal     => imm.map(|imm| w.pc.wrapping_add(imm)),
+        InstrClass::Jalr    => Some(w.operands?.0.wrapping_add(imm?) & !1),
+        _                   => Some(w.pc.wrapping_add(4)),
+    }
+}
+
+//For a load we don't know the bytes for, whether rd at least has the right extension
+fn extension_problem(word: u32, data_rd: Logic<u32>) -> Option<&'static str> {
+    if ((word & 0x7f) != 0b0000011) || !data_rd.is_known() {
+        return None;
+    }
+    let funct3 = (word >> 12) & 0b111;
+    if hints::load_result(funct3, data_rd.value()) == data_rd.value() {
+        None
+    } else if (funct3 & 0b100) != 0 {
+        Some("the upper bits should be zero since it's an unsigned load")
+    } else {
+        Some("the upper bits should all be copies of the loaded value's sign bit")
+    }
+}
+
+/* ------------------------------------------------------------------------------------------------
+ * Tests
+ * --------------------------------------------------------------------------------------------- */
+
+//TODO
+
+/* ------------------------------------------------------------------------------------------------
+ * Benchmarks
+ * --------------------------------------------------------------------------------------------- */
+
+//TODO
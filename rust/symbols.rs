@@ -0,0 +1,285 @@
+/*
+ * File:    symbols.rs
+ * Brief:   Maps PCs back to function names and source lines for nicer reports
+ *
+ * Copyright (C) 2024 John Jekel
+ * See the LICENSE file at the root of the project for licensing info.
+ *
+ * "instruction @PC 010000bc" isn't very helpful when the benchmark was compiled from C.
+ * Given the benchmark's ELF file and/or an objdump listing of it, this turns a PC into
+ * something like "in `bubble_sort`+0x1c (bubble_sort.c:42)".
+ *
+ * Function names can come from either the ELF symbol table or the objdump listing. Source
+ * lines only come from the listing, and only if it was made with line numbers
+ * (ex. `riscv32-unknown-elf-objdump -dl benchmark.elf > benchmark.dump`).
+ *
+*/
+
+/*!
+ * Maps PCs back to function names and source lines for nicer reports
+*/
+
+/* ------------------------------------------------------------------------------------------------
+ * Submodules
+ * --------------------------------------------------------------------------------------------- */
+
+//TODO (includes "mod ..." and "pub mod ...")
+
+/* ------------------------------------------------------------------------------------------------
+ * Uses
+ * --------------------------------------------------------------------------------------------- */
+
+use crate::elf::{Elf, SymbolKind};
+
+use std::collections::HashMap;
+use std::path::Path;
+
+/* ------------------------------------------------------------------------------------------------
+ * Macros
+ * --------------------------------------------------------------------------------------------- */
+
+//TODO (also pub(crate) use the_macro statements here too)
+
+/* ------------------------------------------------------------------------------------------------
+ * Constants
+ * --------------------------------------------------------------------------------------------- */
+
+//TODO
+
+/* ------------------------------------------------------------------------------------------------
+ * Static Variables
+ * --------------------------------------------------------------------------------------------- */
+
+//TODO
+
+/* ------------------------------------------------------------------------------------------------
+ * Types
+ * --------------------------------------------------------------------------------------------- */
+
+//Empty by default, in which case annotate() adds nothing to reports
+#[derive(Debug, Default)]
+pub struct SymbolTable {
+    functions:  Vec<Function>,//Sorted by address
+    lines:      HashMap<u32, SourceLine>,
+}
+
+#[derive(Debug)]
+struct Function {
+    name:   String,
+    addr:   u32,
+    size:   u32,//0 means "until the next function"
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SourceLine {
+    pub file: String,
+    pub line: u32,
+}
+
+/* ------------------------------------------------------------------------------------------------
+ * Associated Functions and Methods
+ * --------------------------------------------------------------------------------------------- */
+
+impl SymbolTable {
+    pub fn new() -> SymbolTable {
+        SymbolTable::default()
+    }
+
+    //What --elf and --objdump ask for (either, both or neither)
+    pub fn load(elf_path: Option<&str>, objdump_path: Option<&str>) -> Result<SymbolTable, String> {
+        let mut symbols = SymbolTable::new();
+
+        if let Some(elf_path) = elf_path {
+            symbols.load_elf(elf_path).map_err(|e| format!("Error loading ELF file at path {}: {}", elf_path, e))?;
+            println!("Using symbols from \x1b[1;36m{}\x1b[0m", elf_path);
+        }
+
+        if let Some(objdump_path) = objdump_path {
+            symbols.load_objdump(objdump_path).map_err(|e| format!("Error loading objdump listing at path {}: {}", objdump_path, e))?;
+            println!("Using symbols and source lines from \x1b[1;36m{}\x1b[0m", objdump_path);
+        }
+
+        Ok(symbols)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.functions.is_empty() && self.lines.is_empty()
+    }
+
+    pub fn load_elf(&mut self, path: impl AsRef<Path>) -> Result<(), String> {
+        self.add_elf(&Elf::from_path(path)?);
+        Ok(())
+    }
+
+    pub fn add_elf(&mut self, elf: &Elf) {
+        for symbol in elf.symbols().iter().filter(|symbol| symbol.kind != SymbolKind::Object) {
+            //Prefer real function symbols over labels at the same address
+            if let Some(existing) = self.functions.iter_mut().find(|function| function.addr == symbol.addr) {
+                if symbol.kind == SymbolKind::Function {
+                    existing.name = symbol.name.clone();
+                    existing.size = symbol.size;
+                }
+                continue;
+            }
+
+            self.functions.push(Function {
+                name: symbol.name.clone(),
+                addr: symbol.addr,
+                size: symbol.size,
+            });
+        }
+        self.functions.sort_by_key(|function| function.addr);
+    }
+
+    pub fn load_objdump(&mut self, path: impl AsRef<Path>) -> Result<(), String> {
+        let listing = std::fs::read_to_string(path.as_ref()).map_err(|e| e.to_string())?;
+        self.add_objdump(&listing);
+        Ok(())
+    }
+
+    //Understands `objdump -d` output, plus the `file:line` lines `objdump -dl` adds.
+    //Lines we don't recognize (section headers, `-S` source interleaving, etc.) are skipped.
+    pub fn add_objdump(&mut self, listing: &str) {
+        let mut current_function: Option<Function> = None;
+        let mut current_line: Option<SourceLine> = None;
+
+        for line in listing.lines() {
+            //ex. "010000bc <bubble_sort>:"
+            if let Some((addr, name)) = parse_function_header(line) {
+                if let Some(function) = current_function.take() {
+                    self.add_objdump_function(function);
+                }
+                current_function = Some(Function {
+                    name: name.to_string(),
+                    addr,
+                    size: 0,
+                });
+                current_line = None;
+                continue;
+            }
+
+            //ex. " 10000bc:	fe010113          	addi	sp,sp,-32"
+            if let Some(addr) = parse_instruction_addr(line) {
+                if let Some(function) = current_function.as_mut() {
+                    function.size = addr.wrapping_add(4).wrapping_sub(function.addr);
+                }
+                if let Some(source_line) = current_line.as_ref() {
+                    self.lines.insert(addr, source_line.clone());
+                }
+                continue;
+            }
+
+            //ex. "/home/jzj/rv32-benchmarks/individual-instructions/bubble_sort.c:42 (discriminator 3)"
+            if let Some(source_line) = parse_source_line(line) {
+                current_line = Some(source_line);
+            }
+        }
+
+        if let Some(function) = current_function.take() {
+            self.add_objdump_function(function);
+        }
+        self.functions.sort_by_key(|function| function.addr);
+    }
+
+    fn add_objdump_function(&mut self, function: Function) {
+        //The ELF symbol table (if we have one) knows better
+        if !self.functions.iter().any(|existing| existing.addr == function.addr) {
+            self.functions.push(function);
+        }
+    }
+
+    //Returns the function containing the PC and how far into it the PC is
+    pub fn function(&self, pc: u32) -> Option<(&str, u32)> {
+        let index = self.functions.partition_point(|function| function.addr <= pc).checked_sub(1)?;
+        let function = &self.functions[index];
+        let offset = pc - function.addr;
+        if (function.size != 0) && (offset >= function.size) {
+            return None;//Past the end of the closest function, so it's probably in the middle of nowhere
+        }
+        Some((&function.name, offset))
+    }
+
+    pub fn addr_of(&self, name: &str) -> Option<u32> {
+        self.functions.iter().find(|function| function.name == name).map(|function| function.addr)
+    }
+
+    pub fn source_line(&self, pc: u32) -> Option<&SourceLine> {
+        self.lines.get(&pc)
+    }
+
+    //ex. " in `bubble_sort`+0x1c (bubble_sort.c:42)", or an empty string if we know nothing about the PC.
+    //Has a leading space so it can be tacked onto the end of "instruction @PC 010000bc"
+    pub fn annotate(&self, pc: u32) -> String {
+        let mut annotation = String::new();
+        if let Some((name, offset)) = self.function(pc) {
+            annotation.push_str(&format!(" in `{}`+0x{:x}", name, offset));
+        }
+        if let Some(source_line) = self.source_line(pc) {
+            annotation.push_str(&format!(" ({})", source_line));
+        }
+        annotation
+    }
+}
+
+/* ------------------------------------------------------------------------------------------------
+ * Traits And Default Implementations
+ * --------------------------------------------------------------------------------------------- */
+
+//TODO
+
+/* ------------------------------------------------------------------------------------------------
+ * Trait Implementations
+ * --------------------------------------------------------------------------------------------- */
+
+//Only the file name, the full path from the machine the benchmark was compiled on is just noise
+impl std::fmt::Display for SourceLine {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let file_name = self.file.rsplit(['/', '\\']).next().unwrap_or(&self.file);
+        write!(f, "{}:{}", file_name, self.line)
+    }
+}
+
+/* ------------------------------------------------------------------------------------------------
+ * Functions
+ * --------------------------------------------------------------------------------------------- */
+
+fn parse_function_header(line: &str) -> Option<(u32, &str)> {
+    let (addr, rest) = line.trim_end().split_once(' ')?;
+    let name = rest.strip_prefix('<')?.strip_suffix(">:")?;
+    Some((u32::from_str_radix(addr, 16).ok()?, name))
+}
+
+fn parse_instruction_addr(line: &str) -> Option<u32> {
+    if !line.starts_with(' ') {
+        return None;
+    }
+    let (addr, _) = line.trim_start().split_once(":\t")?;
+    u32::from_str_radix(addr, 16).ok()
+}
+
+fn parse_source_line(line: &str) -> Option<SourceLine> {
+    let line = line.split(" (discriminator").next().unwrap().trim_end();
+    if line.starts_with(' ') || line.ends_with(':') {
+        return None;
+    }
+    let (file, line_num) = line.rsplit_once(':')?;
+    if file.is_empty() {
+        return None;
+    }
+    Some(SourceLine {
+        file: file.to_string(),
+        line: line_num.parse().ok()?,
+    })
+}
+
+/* ------------------------------------------------------------------------------------------------
+ * Tests
+ * --------------------------------------------------------------------------------------------- */
+
+//TODO
+
+/* ------------------------------------------------------------------------------------------------
+ * Benchmarks
+ * --------------------------------------------------------------------------------------------- */
+
+//TODO
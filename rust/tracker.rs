@@ -0,0 +1,459 @@
+/*
+ * File:    tracker.rs
+ * Brief:   Follows which instruction is in which stage of the golden pipeline
+ *
+ * Copyright (C) 2024 John Jekel
+ * See the LICENSE file at the root of the project for licensing info.
+ *
+ * The checkers compare your trace against the golden one line by line, but to know which fields
+ * matter (and to explain what's going on) they need to know which instruction each line is about.
+ * This works that out from the golden trace one cycle at a time, for any PipelineLayout:
+ *
+ * - Normally every stage moves along one, with a new instruction entering fetch
+ * - If decode stalls (the golden stage after it shows the same PC as decode), everything up to
+ *   decode stays put and a bubble goes into the next stage
+ * - If a multi-cycle instruction (ex. an RV32M divide) is still busy in execute (the golden [E]
+ *   PC hasn't changed), everything up to execute stays put instead
+ * - When a branch reaches the branch resolution stage and the predictor got it wrong, or an
+ *   instruction traps in execute, everything younger is flushed the next cycle
+ * - With imemory latency, the instruction word for the PC in fetch only shows up on a later [F]
+ *   line (and not until fetch stops stalling), so it isn't known right away
+ *
+ * It also keeps a copy of the register file as the golden [W] lines write it, so it can say what an
+ * instruction would have read without forwarding from the instructions still ahead of it, and the
+ * address each load/store used in memory so the [W] stage can tell which byte lanes a load read.
+ *
+*/
+
+/*!
+ * Follows which instruction is in which stage of the golden pipeline
+*/
+
+/* ------------------------------------------------------------------------------------------------
+ * Submodules
+ * --------------------------------------------------------------------------------------------- */
+
+//TODO (includes "mod ..." and "pub mod ...")
+
+/* ------------------------------------------------------------------------------------------------
+ * Uses
+ * --------------------------------------------------------------------------------------------- */
+
+use crate::{disassemble, ParsedLine, Stage};
+use crate::csr::{Csr, CsrFile, CsrInstr, Redirect};
+use crate::cycle::Cycle;
+use crate::layout::PipelineLayout;
+use crate::logic::Logic;
+use crate::machine::{EndCondition, MachineConfig};
+use crate::muldiv::MulDivOp;
+use crate::predictor::{self, BranchStats, ResolvedBranch};
+use crate::symbols::SymbolTable;
+
+use riscv_tools::*;
+
+/* ------------------------------------------------------------------------------------------------
+ * Macros
+ * --------------------------------------------------------------------------------------------- */
+
+//TODO (also pub(crate) use the_macro statements here too)
+
+/* ------------------------------------------------------------------------------------------------
+ * Constants
+ * --------------------------------------------------------------------------------------------- */
+
+//TODO
+
+/* ------------------------------------------------------------------------------------------------
+ * Static Variables
+ * --------------------------------------------------------------------------------------------- */
+
+//TODO
+
+/* ------------------------------------------------------------------------------------------------
+ * Types
+ * --------------------------------------------------------------------------------------------- */
+
+pub type MaybeInstr = Result<Instruction, InstrNotPresentReason>;
+
+#[derive(Debug)]
+pub enum InstrNotPresentReason {
+    Bubble,
+    NotFetchedYet,//Imemory latency, and fetch is stalled so the word hasn't shown up in the golden trace yet
+}
+
+pub struct StageState {
+    pub pc:                 u32,
+    pub instr:              MaybeInstr,
+    pub operands:           Option<(u32, u32)>,//What the golden [R] stage read for this instruction, if it's been through decode
+    pub regfile_operands:   (Option<u32>, Option<u32>),//What the register file itself held for rs1 and rs2 then (so without forwarding), if we know
+    pub mem_addr:           Option<u32>,//What the golden [M] line gave as the address, once this has been through memory
+    pub csr_read:           Option<(Csr, u32)>,//For Zicsr instructions, the CSR and what we think it held when this reached [E]
+    executed:               Option<Execution>,
+    resolved:               bool,//Whether the predictor has been checked for this instruction (if it's a branch or jump)
+}
+
+//What the golden [E] line said about an instruction
+#[derive(Copy, Clone, Debug)]
+struct Execution {
+    taken:      bool,
+    alu_result: Option<u32>,
+}
+
+pub struct PipelineTracker {
+    layout:             PipelineLayout,
+    machine:            MachineConfig,
+    stages:             Vec<StageState>,
+    squash_before:      Option<usize>,//Flush every stage before this one next cycle
+    pending_redirect:   Option<Redirect>,//Where a trap/mret in execute said fetch should go next
+    csrs:               CsrFile,
+    registers:          [Option<u32>; 32],//The machine's reset values, as overwritten by the golden [W] lines so far
+    branch_stats:       BranchStats,
+}
+
+//What happened on a cycle
+#[derive(Default)]
+pub struct CycleUpdate {
+    pub end_of_test:    Option<EndCondition>,//If set, the cycle shouldn't be compared
+    pub held_stage:     Option<usize>,//This stage and everything before it didn't move
+    pub fetch_redirect: Option<Redirect>,//If the new instruction in fetch is where a trap/mret went
+    pub weird:          bool,//The golden trace is missing a line we need
+}
+
+/* ------------------------------------------------------------------------------------------------
+ * Associated Functions and Methods
+ * --------------------------------------------------------------------------------------------- */
+
+impl StageState {
+    pub fn dis(&self, symbols: &SymbolTable) -> String {
+        match self.instr.as_ref() {
+            Ok(instr_ref)                               => format!("instruction @PC {:08x}{}: {:08x}: {}", self.pc, symbols.annotate(self.pc), instr_ref.assume_uncompressed(), disassemble(instr_ref)),
+            Err(InstrNotPresentReason::Bubble)          => String::from("nothing (bubble)"),
+            Err(InstrNotPresentReason::NotFetchedYet)   => format!("instruction @PC {:08x}{}: ????????: instruction word not available from golden trace due to stall next cycle", self.pc, symbols.annotate(self.pc)),
+        }
+    }
+
+    pub const fn is_bubble(&self) -> bool {
+        matches!(self.instr, Err(InstrNotPresentReason::Bubble))
+    }
+
+    //What the [E] line said the ALU produced for this instruction, once it's been through execute
+    pub fn alu_result(&self) -> Option<u32> {
+        self.executed.and_then(|execution| execution.alu_result)
+    }
+}
+
+impl CycleUpdate {
+    //Whether the given stage kept its instruction from last cycle
+    pub fn holds(&self, stage: usize) -> bool {
+        self.held_stage.is_some_and(|held_stage| stage <= held_stage)
+    }
+}
+
+impl PipelineTracker {
+    //Keeps its own copy of the layout and machine config, so it can outlive them (ex. in the lockstep library)
+    pub fn new(layout: &PipelineLayout, machine: &MachineConfig) -> PipelineTracker {
+        PipelineTracker {
+            layout:             layout.clone(),
+            machine:            machine.clone(),
+            stages:             (0..layout.depth()).map(|_| StageState::default()).collect(),
+            squash_before:      None,
+            pending_redirect:   None,
+            csrs:               CsrFile::new(machine),
+            registers:          machine.init_regs.map(Some),
+            branch_stats:       BranchStats::default(),
+        }
+    }
+
+    pub fn stages(&self) -> &[StageState] {
+        &self.stages
+    }
+
+    pub fn stage_for(&self, tag: Stage) -> &StageState {
+        &self.stages[self.layout.stage_of(tag)]
+    }
+
+    //Whether the stages before the branch resolution stage will be flushed next cycle
+    pub const fn squash_pending(&self) -> bool {
+        self.squash_before.is_some()
+    }
+
+    pub const fn branch_stats(&self) -> BranchStats {
+        self.branch_stats
+    }
+
+    //`cycles[0]` is this cycle's golden lines, and `cycles[n]` the golden lines n cycles later (there
+    //needs to be at least layout.lookahead() more)
+    pub fn update(&mut self, cycles: &[Cycle]) -> CycleUpdate {
+        let cycle = &cycles[0];
+        let mut update = CycleUpdate {
+            weird: [Stage::F, Stage::D, Stage::E].into_iter().any(|tag| cycle.line(tag).is_none()),
+            ..CycleUpdate::default()
+        };
+
+        if let Some(squash_before) = self.squash_before.take() {
+            self.stages[..squash_before].iter_mut().for_each(|stage| *stage = StageState::default());
+        }
+
+        update.held_stage = self.held_stage(cycle);
+        match (update.held_stage, cycle.line(Stage::F)) {
+            (Some(held_stage), _) => self.advance_holding(held_stage),
+            (None, Some(ParsedLine::F{pc, instr})) => {
+                let instr = if self.layout.imem_latency == 0 { Ok(instr.into()) } else { Err(InstrNotPresentReason::NotFetchedYet) };
+                self.advance(pc.value(), instr);
+            },
+            (None, _) => {},
+        }
+
+        let fetched_word = self.fill_fetched_word(cycles);
+        if update.held_stage.is_none() {
+            let fetched_word = fetched_word.filter(|word| word.is_known()).map(|word| word.value());
+            if let Some(end_condition) = self.machine.end_before_cycle(self.stages[0].pc, fetched_word) {
+                update.end_of_test = Some(end_condition.clone());
+                return update;
+            }
+            update.fetch_redirect = self.pending_redirect.take();
+        }
+
+        self.capture_operands(cycles);
+        self.write_back(cycle);
+        self.capture_mem_addr(cycle);
+
+        let execute_stage   = self.layout.stage_of(Stage::E);
+        let trap            = self.execute(cycle);
+        let squash_before   = self.resolve_branch();
+        if let Some(squash_before) = squash_before {
+            self.squash(squash_before);
+        }
+        if let Some(trap) = trap {
+            //Unless it was behind a mispredicted branch, in which case it never really happened
+            if squash_before.is_none_or(|squash_before| squash_before <= execute_stage) {
+                self.squash(execute_stage);
+                self.pending_redirect = Some(trap);
+            }
+        }
+
+        update
+    }
+
+    fn squash(&mut self, before: usize) {
+        self.squash_before = Some(self.squash_before.map_or(before, |squash_before| squash_before.max(before)));
+    }
+
+    fn advance(&mut self, f_pc: u32, f_instr: MaybeInstr) {
+        for stage in (1..self.stages.len()).rev() {
+            self.stages[stage] = std::mem::take(&mut self.stages[stage - 1]);
+        }
+        self.stages[0] = StageState {
+            pc:     f_pc,
+            instr:  f_instr,
+            ..StageState::default()
+        };
+    }
+
+    fn advance_holding(&mut self, held_stage: usize) {
+        for stage in ((held_stage + 2)..self.stages.len()).rev() {
+            self.stages[stage] = std::mem::take(&mut self.stages[stage - 1]);
+        }
+        if let Some(stage) = self.stages.get_mut(held_stage + 1) {
+            *stage = StageState::default();
+        }
+    }
+
+    //Which stage (if any) keeps its instruction this cycle, given the golden lines for it
+    fn held_stage(&self, cycle: &Cycle) -> Option<usize> {
+        //A multi-cycle instruction still busy in execute, in which case the golden [E] PC won't have changed
+        let execute_stage = self.layout.stage_of(Stage::E);
+        if let Some(ParsedLine::E{pc: g_e_pc, ..}) = cycle.line(Stage::E) {
+            let execute = &self.stages[execute_stage];
+            if execute.instr.as_ref().is_ok_and(|instr| MulDivOp::of(instr).is_some()) && (g_e_pc == execute.pc) {
+                return Some(execute_stage);
+            }
+        }
+
+        //Decode stalled, in which case the stage after it shows the same PC. Don't check if that stage
+        //is a bubble because it could be we're stalling multiple cycles. We do need to check decode
+        //though because if the PCs just happen to match but were squashed we're not actually stalling.
+        let decode_stage = self.layout.stage_of(Stage::D);
+        let after_decode_pc = self.layout.tags_in(decode_stage + 1).find_map(|tag| line_pc(cycle, tag));
+        if let (Some(g_d_pc), Some(g_after_decode_pc)) = (line_pc(cycle, Stage::D), after_decode_pc) {
+            if !self.stages[decode_stage].is_bubble() && (g_d_pc == g_after_decode_pc) {
+                return Some(decode_stage);
+            }
+        }
+
+        None
+    }
+
+    //Fills in the instruction word for fetch once it shows up in the golden trace, returning it if so
+    fn fill_fetched_word(&mut self, cycles: &[Cycle]) -> Option<Logic<u32>> {
+        let latency = self.layout.imem_latency;
+        if latency == 0 {
+            return match cycles[0].line(Stage::F) {
+                Some(ParsedLine::F{instr, ..}) if self.stages[0].instr.is_ok() => Some(instr),
+                _ => None,
+            };
+        }
+
+        if !matches!(self.stages[0].instr, Err(InstrNotPresentReason::NotFetchedYet)) {
+            return None;
+        }
+
+        //If fetch is stalled next cycle, the word we'd see isn't for this fetch (yet)
+        if cycles.get(1).is_some_and(|next_cycle| self.held_stage(next_cycle).is_some()) {
+            return None;
+        }
+
+        if let Some(ParsedLine::F{instr, ..}) = cycles.get(latency).and_then(|cycle| cycle.line(Stage::F)) {
+            self.stages[0].instr = Ok(instr.into());
+            Some(instr)
+        } else {
+            None
+        }
+    }
+
+    //Records what the golden [E] line says about the instruction that just reached execute, and models
+    //what it does to the CSRs. Returns where to redirect fetch if it traps (or is an mret).
+    fn execute(&mut self, cycle: &Cycle) -> Option<Redirect> {
+        let Some(ParsedLine::E{alu_result: g_alu_result, branch_taken: g_branch_taken, ..}) = cycle.line(Stage::E) else {
+            return None;
+        };
+
+        let stage = &mut self.stages[self.layout.stage_of(Stage::E)];
+        let Ok(instr) = stage.instr.as_ref() else {
+            return None;
+        };
+        if stage.executed.is_some() {
+            return None;//Still busy with a multi-cycle instruction
+        }
+
+        let alu_result = g_alu_result.is_known().then_some(g_alu_result.value());
+        stage.executed = Some(Execution {
+            taken: g_branch_taken.value(),
+            alu_result,
+        });
+
+        let rs1_value = stage.operands.map(|(rs1, _)| rs1);
+        stage.csr_read = CsrInstr::of(instr).and_then(|csr_instr| self.csrs.access(&csr_instr, rs1_value));
+        self.csrs.redirect(stage.pc, instr.assume_uncompressed(), alu_result, g_branch_taken.value())
+    }
+
+    //Checks the predictor for a branch or jump that just reached the branch resolution stage, returning
+    //which stages to flush if it was mispredicted
+    fn resolve_branch(&mut self) -> Option<usize> {
+        let branch_stage = self.layout.branch_stage;
+        let fetched_next = self.stages[..branch_stage].iter().rev().find(|stage| !stage.is_bubble()).map(|stage| stage.pc);
+
+        let stage = &mut self.stages[branch_stage];
+        let Ok(instr) = stage.instr.as_ref() else {
+            return None;
+        };
+        if stage.resolved {
+            return None;
+        }
+
+        //Before execute, we have to work out whether it's taken ourselves from what decode read
+        let execution = stage.executed.or_else(|| {
+            let (rs1, rs2) = stage.operands?;
+            Some(Execution {
+                taken:      predictor::taken(instr.assume_uncompressed(), rs1, rs2)?,
+                alu_result: None,
+            })
+        })?;
+
+        let branch = ResolvedBranch {
+            instr,
+            pc:             stage.pc,
+            taken:          execution.taken,
+            rs1:            stage.operands.map(|(rs1, _)| rs1),
+            alu_result:     execution.alu_result,
+            fetched_next,
+        };
+        let mispredicted = self.machine.predictor.mispredicted(&branch)?;
+
+        stage.resolved = true;
+        self.branch_stats.record(mispredicted);
+        mispredicted.then_some(branch_stage)
+    }
+
+    //Remembers what decode read, for explaining RV32M results, Zicsr writes, jalr targets and branches
+    fn capture_operands(&mut self, cycles: &[Cycle]) {
+        let r_line = cycles.get(self.layout.regfile_latency).and_then(|cycle| cycle.line(Stage::R));
+        if let Some(ParsedLine::R{addr_rs1: g_addr_rs1, addr_rs2: g_addr_rs2, data_rs1: g_data_rs1, data_rs2: g_data_rs2}) = r_line {
+            let stage = &mut self.stages[self.layout.stage_of(Stage::R)];
+            if g_data_rs1.is_known() && g_data_rs2.is_known() {
+                stage.operands = Some((g_data_rs1.value(), g_data_rs2.value()));
+            }
+
+            let registers = &self.registers;
+            let regfile_value = |addr: Logic<u8>| addr.is_known().then(|| registers[(addr.value() & 0x1f) as usize]).flatten();
+            stage.regfile_operands = (regfile_value(g_addr_rs1), regfile_value(g_addr_rs2));
+        }
+    }
+
+    //Remembers the address a load/store used, for explaining load data in [W]
+    fn capture_mem_addr(&mut self, cycle: &Cycle) {
+        if let Some(ParsedLine::M{pc: g_pc, addr: g_addr, ..}) = cycle.line(Stage::M) {
+            let stage = &mut self.stages[self.layout.stage_of(Stage::M)];
+            if g_pc.is_known() && (g_pc.value() == stage.pc) && g_addr.is_known() {
+                stage.mem_addr = Some(g_addr.value());
+            }
+        }
+    }
+
+    //Updates our copy of the register file with this cycle's golden [W] line
+    fn write_back(&mut self, cycle: &Cycle) {
+        if let Some(ParsedLine::W{we: g_we, addr_rd: g_addr_rd, data_rd: g_data_rd, ..}) = cycle.line(Stage::W) {
+            if g_we.is_known() && g_we.value() && g_addr_rd.is_known() && (g_addr_rd.value() != 0) {
+                self.registers[(g_addr_rd.value() & 0x1f) as usize] = g_data_rd.is_known().then_some(g_data_rd.value());
+            }
+        }
+    }
+}
+
+/* ------------------------------------------------------------------------------------------------
+ * Traits And Default Implementations
+ * --------------------------------------------------------------------------------------------- */
+
+//TODO
+
+/* ------------------------------------------------------------------------------------------------
+ * Trait Implementations
+ * --------------------------------------------------------------------------------------------- */
+
+impl Default for StageState {
+    fn default() -> Self {
+        Self {
+            pc:                 0,
+            instr:              Err(InstrNotPresentReason::Bubble),
+            operands:           None,
+            regfile_operands:   (None, None),
+            mem_addr:           None,
+            csr_read:           None,
+            executed:           None,
+            resolved:           false,
+        }
+    }
+}
+
+/* ------------------------------------------------------------------------------------------------
+ * Functions
+ * --------------------------------------------------------------------------------------------- */
+
+fn line_pc(cycle: &Cycle, tag: Stage) -> Option<Logic<u32>> {
+    match cycle.line(tag)? {
+        ParsedLine::F{pc, ..} | ParsedLine::D{pc, ..} | ParsedLine::E{pc, ..} | ParsedLine::M{pc, ..} | ParsedLine::W{pc, ..} => Some(pc),
+        ParsedLine::R{..} => None,
+    }
+}
+
+/* ------------------------------------------------------------------------------------------------
+ * Tests
+ * --------------------------------------------------------------------------------------------- */
+
+//TODO
+
+/* ------------------------------------------------------------------------------------------------
+ * Benchmarks
+ * --------------------------------------------------------------------------------------------- */
+
+//TODO
@@ -0,0 +1,130 @@
+/*
+ * File:    xfile.rs
+ * Brief:   Writing `.x` memory images
+ *
+ * Copyright (C) 2024 John Jekel
+ * See the LICENSE file at the root of the project for licensing info.
+ *
+ * The `.x` files in rv32-benchmarks (and that `irvedecoder` and the testbench read) are just
+ * whitespace-separated 32-bit hex words, the first of which lives at 0x01000000 and each one
+ * after that at the next word address. There's no address information in the file itself.
+ *
+*/
+
+/*!
+ * Writing `.x` memory images
+*/
+
+/* ------------------------------------------------------------------------------------------------
+ * Submodules
+ * --------------------------------------------------------------------------------------------- */
+
+//TODO (includes "mod ..." and "pub mod ...")
+
+/* ------------------------------------------------------------------------------------------------
+ * Uses
+ * --------------------------------------------------------------------------------------------- */
+
+use crate::elf::Elf;
+
+use std::io::Write;
+
+/* ------------------------------------------------------------------------------------------------
+ * Macros
+ * --------------------------------------------------------------------------------------------- */
+
+//TODO (also pub(crate) use the_macro statements here too)
+
+/* ------------------------------------------------------------------------------------------------
+ * Constants
+ * --------------------------------------------------------------------------------------------- */
+
+//TODO
+
+/* ------------------------------------------------------------------------------------------------
+ * Static Variables
+ * --------------------------------------------------------------------------------------------- */
+
+//TODO
+
+/* ------------------------------------------------------------------------------------------------
+ * Types
+ * --------------------------------------------------------------------------------------------- */
+
+//TODO includes "type"-defs, structs, enums, unions, etc
+
+/* ------------------------------------------------------------------------------------------------
+ * Associated Functions and Methods
+ * --------------------------------------------------------------------------------------------- */
+
+//TODO
+
+/* ------------------------------------------------------------------------------------------------
+ * Traits And Default Implementations
+ * --------------------------------------------------------------------------------------------- */
+
+//TODO
+
+/* ------------------------------------------------------------------------------------------------
+ * Trait Implementations
+ * --------------------------------------------------------------------------------------------- */
+
+//TODO
+
+/* ------------------------------------------------------------------------------------------------
+ * Functions
+ * --------------------------------------------------------------------------------------------- */
+
+pub fn write(words: &[u32], mut writer: impl Write) -> std::io::Result<()> {
+    for word in words {
+        writeln!(writer, "{:08x}", word)?;
+    }
+    Ok(())
+}
+
+//Flattens the ELF's loadable segments into an image starting at `base_addr`. Gaps between segments
+//are zero-filled, like `objcopy -O binary`; so is the rest of the last word if a segment ends partway
+//into one. Zero-initialized memory past the end of the file data (ex. .bss) is left out since
+//there'd be nothing to put there but zeros anyways. Every segment has to fit in the `mem_size` bytes
+//of memory, otherwise one linked somewhere else entirely (ex. a stack at 80000000) would make the
+//image gigabytes long.
+pub fn from_elf(elf: &Elf, base_addr: u32, mem_size: u32) -> Result<Vec<u32>, String> {
+    let mut bytes: Vec<u8> = Vec::new();
+
+    for segment in elf.segments().iter().filter(|segment| !segment.data.is_empty()) {
+        let offset      = segment.addr.wrapping_sub(base_addr) as u64;
+        let segment_end = offset + segment.data.len() as u64;
+        if segment_end > mem_size as u64 {
+            return Err(format!(
+                "Segment at {:08x} ({} bytes) doesn't fit in memory ({:08x} thru {:08x}); was the program linked for this core?",
+                segment.addr, segment.data.len(), base_addr, base_addr.wrapping_add(mem_size).wrapping_sub(1)
+            ));
+        }
+
+        let start   = offset as usize;
+        let end     = segment_end as usize;
+        if bytes.len() < end {
+            bytes.resize(end, 0);
+        }
+        bytes[start..end].copy_from_slice(&segment.data);
+    }
+
+    if bytes.is_empty() {
+        return Err("ELF file has nothing to load".to_string());
+    }
+
+    bytes.resize(bytes.len().next_multiple_of(4), 0);
+    Ok(bytes.chunks_exact(4).map(|word| u32::from_le_bytes(word.try_into().unwrap())).collect())
+}
+
+/* ------------------------------------------------------------------------------------------------
+ * Tests
+ * --------------------------------------------------------------------------------------------- */
+
+//TODO
+
+/* ------------------------------------------------------------------------------------------------
+ * Benchmarks
+ * --------------------------------------------------------------------------------------------- */
+
+//TODO